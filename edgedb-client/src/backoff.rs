@@ -0,0 +1,11 @@
+use std::cmp::min;
+use std::time::Duration;
+
+
+/// Exponential backoff: `initial` is doubled on each retry up to `max`
+pub(crate) fn delay(initial: Duration, max: Duration, retry: u32)
+    -> Duration
+{
+    let factor = 1u32.checked_shl(retry).unwrap_or(u32::max_value());
+    min(initial.checked_mul(factor).unwrap_or(max), max)
+}
@@ -0,0 +1,85 @@
+//! Several independent scripts sent in a single write
+//!
+//! Scripts are executed with `ExecuteScript`, the same way as
+//! `Client::execute`, so they can't have arguments and return no data.
+//!
+//! ```rust,no_run
+//! # use edgedb_client::batch::ScriptBatch;
+//! # async fn example(cli: &mut edgedb_client::Client<'_>)
+//! #     -> Result<(), edgedb_client::Error>
+//! # {
+//! let results = cli.execute_script_batch(ScriptBatch::new()
+//!     .add("INSERT User { name := 'alice' }")
+//!     .add("INSERT User { name := 'bob' }")).await?;
+//! for result in results {
+//!     println!("{:?}", result);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use bytes::Bytes;
+
+use edgedb_protocol::client_message::{ClientMessage, ExecuteScript};
+
+use crate::client::Client;
+use crate::errors::Error;
+use crate::instrument::{Operation, Kind};
+
+
+/// List of scripts to execute
+///
+/// Each script is run by the server on its own, i.e. failure of one of
+/// them doesn't prevent the next ones from running. Unless they are in
+/// a transaction block, of course.
+#[derive(Debug, Clone, Default)]
+pub struct ScriptBatch {
+    scripts: Vec<String>,
+}
+
+
+impl ScriptBatch {
+    pub fn new() -> ScriptBatch {
+        ScriptBatch {
+            scripts: Vec::new(),
+        }
+    }
+    pub fn add<S: ToString>(&mut self, script: S) -> &mut Self {
+        self.scripts.push(script.to_string());
+        self
+    }
+    pub fn len(&self) -> usize {
+        self.scripts.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.scripts.is_empty()
+    }
+}
+
+impl<'a> Client<'a> {
+    /// Executes all scripts of the batch with a single round trip
+    ///
+    /// Returns a status or an error for each script in the same order.
+    /// The outer error means that connection is broken and results are
+    /// unknown.
+    pub async fn execute_script_batch(&mut self, batch: &ScriptBatch)
+        -> Result<Vec<Result<Bytes, Error>>, Error>
+    {
+        if batch.is_empty() {
+            return Ok(Vec::new());
+        }
+        let messages = batch.scripts.iter()
+            .map(|script| ClientMessage::ExecuteScript(ExecuteScript {
+                headers: self.conn.headers.clone(),
+                script_text: script.clone(),
+            }))
+            .collect::<Vec<_>>();
+        let operation = Operation::new(Kind::Execute, &self.conn.params,
+                                       None);
+        self.send_request(operation, &messages).await?;
+        let mut results = Vec::with_capacity(batch.len());
+        for _ in 0..batch.len() {
+            results.push(self._process_script().await?);
+        }
+        Ok(results)
+    }
+}
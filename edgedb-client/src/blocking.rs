@@ -0,0 +1,241 @@
+//! Synchronous client for programs that don't use async code
+//!
+//! Each connection runs its own runtime internally, queries are the same
+//! as in the async client and return the same errors.
+//!
+//! ```rust,no_run
+//! # use edgedb_protocol::value::Value;
+//! # fn example() -> Result<(), edgedb_client::Error> {
+//! let mut params = edgedb_client::Builder::new();
+//! params.user("edgedb").password("secret");
+//! let mut conn = edgedb_client::blocking::Connection::connect(&params)?;
+//! for name in conn.query::<String, _>("SELECT User.name",
+//!                                     &Value::empty_tuple())?
+//! {
+//!     let name = name.map_err(|source| {
+//!         edgedb_client::Error::Read { source }
+//!     })?;
+//!     println!("{}", name);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures_util::stream::StreamExt;
+use snafu::ResultExt;
+
+use edgedb_protocol::codec::Codec;
+use edgedb_protocol::headers::RequestHeaders;
+use edgedb_protocol::query_args::QueryArgs;
+use edgedb_protocol::queryable::Queryable;
+use edgedb_protocol::server_message::TransactionState;
+
+use crate::builder::Builder;
+use crate::client::{self, Client};
+use crate::errors::{self, Error};
+use crate::reader::{Decode, QueryResponse, QueryableDecoder, ReadError};
+use crate::rt::{self, Runtime};
+use crate::stream::Stream;
+use crate::transaction::{Attempts, TransactionOptions};
+
+
+/// Authenticated connection with a blocking interface
+pub struct Connection {
+    runtime: Runtime,
+    conn: client::Connection,
+}
+
+/// Iterator over the rows of the query result
+///
+/// Like the `QueryResponse` it wraps, it should be read to the end before
+/// running the next query, otherwise call `Connection::wait_ready`.
+pub struct QueryIter<'a, D> {
+    runtime: &'a mut Runtime,
+    response: QueryResponse<'a, Stream, D>,
+}
+
+/// Connection running in a transaction block
+///
+/// Don't commit or roll back the transaction manually, this is done by
+/// `Connection::transaction` depending on the result of the closure.
+pub struct Transaction<'a> {
+    conn: &'a mut Connection,
+}
+
+
+impl Connection {
+    /// Connects and authenticates using the parameters
+    pub fn connect(params: &Builder) -> Result<Connection, Error> {
+        let mut runtime = Runtime::new().context(errors::Runtime)?;
+        let conn = runtime.block_on(async {
+            let mut conn = params.connect().await?;
+            conn.authenticate().await?;
+            Ok::<_, Error>(conn)
+        })?;
+        Ok(Connection { runtime, conn })
+    }
+
+    fn block_on<'a, F, T>(&'a mut self, f: impl FnOnce(Client<'a>) -> F)
+        -> T
+        where F: Future<Output=T>,
+    {
+        let Connection { runtime, conn } = self;
+        runtime.block_on(f(Client { conn }))
+    }
+
+    /// Transaction state as reported by the server, `None` when busy
+    pub fn transaction_state(&self) -> Option<TransactionState> {
+        self.conn.reader.transaction_state()
+    }
+
+    /// Sets headers sent with all subsequent requests
+    pub fn set_request_headers(&mut self, headers: &RequestHeaders) {
+        Client { conn: &mut self.conn }.set_request_headers(headers)
+    }
+
+    /// Waits until server is ready for the next command
+    pub fn wait_ready(&mut self) -> Result<(), Error> {
+        self.block_on(|mut cli| async move { cli.wait_ready().await })
+    }
+
+    /// Gracefully closes the connection
+    pub fn terminate(mut self) -> Result<(), Error> {
+        self.block_on(|cli| cli.terminate())
+    }
+
+    /// Executes one or more statements ignoring the results
+    pub fn execute<S: ToString>(&mut self, request: S)
+        -> Result<Bytes, Error>
+    {
+        let request = request.to_string();
+        self.block_on(|mut cli| async move { cli.execute(request).await })
+    }
+
+    /// Executes a single statement with arguments ignoring the result
+    pub fn execute_args<A>(&mut self, request: &str, arguments: &A)
+        -> Result<Bytes, Error>
+        where A: QueryArgs + ?Sized,
+    {
+        self.block_on(|mut cli| async move {
+            cli.execute_args(request, arguments).await
+        })
+    }
+
+    /// Runs the query and returns an iterator over rows decoded into `R`
+    pub fn query<R, A>(&mut self, request: &str, arguments: &A)
+        -> Result<QueryIter<'_, QueryableDecoder<R>>, Error>
+        where R: Queryable,
+              A: QueryArgs + ?Sized,
+    {
+        let Connection { runtime, conn } = self;
+        let response = runtime.block_on(conn.query(request, arguments))?;
+        Ok(QueryIter { runtime, response })
+    }
+
+    /// Runs the query and returns an iterator over rows serialized to JSON
+    pub fn query_json<A>(&mut self, request: &str, arguments: &A)
+        -> Result<QueryIter<'_, QueryableDecoder<String>>, Error>
+        where A: QueryArgs + ?Sized,
+    {
+        let Connection { runtime, conn } = self;
+        let response = runtime.block_on(
+            conn.query_json(request, arguments))?;
+        Ok(QueryIter { runtime, response })
+    }
+
+    /// Runs the query and returns an iterator over dynamically typed values
+    pub fn query_dynamic<A>(&mut self, request: &str, arguments: &A)
+        -> Result<QueryIter<'_, Arc<dyn Codec>>, Error>
+        where A: QueryArgs + ?Sized,
+    {
+        let Connection { runtime, conn } = self;
+        let response = runtime.block_on(
+            conn.query_dynamic(request, arguments))?;
+        Ok(QueryIter { runtime, response })
+    }
+
+    /// Runs the query that returns at most one element
+    pub fn query_single<R, A>(&mut self, request: &str, arguments: &A)
+        -> Result<Option<R>, Error>
+        where R: Queryable,
+              A: QueryArgs + ?Sized,
+    {
+        self.block_on(|mut cli| async move {
+            cli.query_single(request, arguments).await
+        })
+    }
+
+    /// Runs the query that returns exactly one element
+    pub fn query_required_single<R, A>(&mut self, request: &str,
+        arguments: &A)
+        -> Result<R, Error>
+        where R: Queryable,
+              A: QueryArgs + ?Sized,
+    {
+        self.block_on(|mut cli| async move {
+            cli.query_required_single(request, arguments).await
+        })
+    }
+
+    /// Runs `body` in a transaction block
+    ///
+    /// Works like `Client::transaction`: the transaction is committed if
+    /// `body` returns `Ok`, rolled back otherwise and run again on
+    /// serialization or deadlock errors.
+    pub fn transaction<T, F>(&mut self, options: &TransactionOptions,
+        mut body: F)
+        -> Result<T, Error>
+        where F: FnMut(&mut Transaction<'_>) -> Result<T, Error>,
+    {
+        let mut attempts = Attempts::new(options);
+        loop {
+            let start = attempts.start(self.transaction_state())?;
+            self.execute(start)?;
+            let result = match body(&mut Transaction { conn: self }) {
+                Ok(value) => self.execute("COMMIT").map(|_| value),
+                Err(e) => Err(e),
+            };
+            let err = match result {
+                Ok(value) => return Ok(value),
+                Err(e) => e,
+            };
+            let delay = attempts.failed(&err);
+            // same as in `Client::transaction`
+            if !err.is_disconnect() && self.block_on(|mut cli| async move {
+                cli.rollback().await
+            }).is_err() {
+                return Err(err);
+            }
+            match delay {
+                Some(delay) => self.runtime.block_on(rt::sleep(delay)),
+                None => return Err(err),
+            }
+            attempts.next(&self.conn.params, &err);
+        }
+    }
+}
+
+impl<D: Decode> Iterator for QueryIter<'_, D> {
+    type Item = Result<D::Output, ReadError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let QueryIter { runtime, response } = self;
+        runtime.block_on(response.next())
+    }
+}
+
+impl Deref for Transaction<'_> {
+    type Target = Connection;
+    fn deref(&self) -> &Connection {
+        self.conn
+    }
+}
+
+impl DerefMut for Transaction<'_> {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.conn
+    }
+}
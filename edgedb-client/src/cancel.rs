@@ -0,0 +1,56 @@
+//! Cancellation of the running queries
+//!
+//! ```rust,no_run
+//! # use async_std::task;
+//! # async fn example(cli: &mut edgedb_client::Client<'_>)
+//! #     -> Result<(), edgedb_client::Error>
+//! # {
+//! if let Some(handle) = cli.cancel_handle() {
+//!     task::spawn(async move {
+//!         task::sleep(std::time::Duration::from_secs(10)).await;
+//!         handle.cancel().await
+//!     });
+//! }
+//! // fails with an error if not complete in 10 seconds
+//! cli.execute("SELECT sys::_sleep(60)").await?;
+//! # Ok(())
+//! # }
+//! ```
+use bytes::BytesMut;
+use futures_util::io::AsyncWriteExt;
+use snafu::ResultExt;
+
+use edgedb_protocol::client_message::{ClientMessage, CancelRequest};
+
+use crate::builder::Builder;
+use crate::errors::{self, Error};
+
+
+/// Cancels queries running on the connection it was obtained from
+///
+/// Cancellation is done over a separate connection, so the handle can be
+/// used while the connection itself is busy waiting for the result.
+#[derive(Debug, Clone)]
+pub struct CancelHandle {
+    pub(crate) params: Builder,
+    pub(crate) key_data: [u8; 32],
+}
+
+
+impl CancelHandle {
+    /// Asks server to cancel the query that is currently running
+    ///
+    /// The query fails with an error and the connection stays usable. It's
+    /// fine to call this when no query is running, the request is ignored
+    /// by the server in this case.
+    pub async fn cancel(&self) -> Result<(), Error> {
+        let mut stream = self.params.connect_stream().await?;
+        let mut buf = BytesMut::new();
+        ClientMessage::CancelRequest(CancelRequest {
+            key_data: self.key_data,
+        }).encode(&mut buf).context(errors::Encode)?;
+        stream.write_all(&buf[..]).await.context(errors::Write)?;
+        stream.flush().await.context(errors::Write)?;
+        Ok(())
+    }
+}
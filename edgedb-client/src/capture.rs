@@ -0,0 +1,366 @@
+//! Recording protocol traffic and replaying it
+//!
+//! A capture file starts with `MAGIC`, followed by records of:
+//!
+//! * direction: `>` is a message sent by the client, `<` is received
+//! * connection number (`u32`), connections of a single `Recorder` are
+//!   numbered from one
+//! * timestamp in microseconds since the UNIX epoch (`u64`)
+//! * message frame exactly as it's sent over the wire
+//!
+//! All integers are big endian, the frame contains its own length. Enable
+//! recording with `Builder::record_traffic`, then use `read_file` to
+//! inspect the capture or `Replay` to run the client against it.
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::task::{Poll, Context};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::{Bytes, BytesMut, BufMut};
+use futures_util::io::{AsyncRead, AsyncWrite};
+
+use edgedb_protocol::client_message::ClientMessage;
+use edgedb_protocol::errors::DecodeError;
+use edgedb_protocol::server_message::ServerMessage;
+
+use crate::builder::Builder;
+use crate::client::Connection;
+
+
+/// First bytes of the capture file
+pub const MAGIC: &[u8] = b"EDGEDB-CAPTURE-1\n";
+/// Direction, connection number and timestamp
+const RECORD_HEADER: usize = 1 + 4 + 8;
+/// Message type and length
+const FRAME_HEADER: usize = 1 + 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// Single message of the capture
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub direction: Direction,
+    pub connection: u32,
+    pub timestamp: SystemTime,
+    pub frame: Bytes,
+}
+
+/// Writes traffic of all connections of a `Builder` to a capture file
+///
+/// Writing is synchronous and errors are ignored, so use this for
+/// debugging only.
+#[derive(Debug)]
+pub struct Recorder {
+    file: Mutex<fs::File>,
+    connections: AtomicU32,
+}
+
+/// Recorder bound to a single connection
+#[derive(Debug, Clone)]
+pub(crate) struct ConnectionRecorder {
+    recorder: Arc<Recorder>,
+    connection: u32,
+}
+
+/// Fake server stream that replays a capture
+///
+/// Reads return messages received from the server in the capture. Written
+/// messages are checked to be of the same types as the recorded ones
+/// (contents may differ, e.g. because of random authentication nonce).
+#[derive(Debug)]
+pub struct Replay {
+    received: VecDeque<Bytes>,
+    sent: VecDeque<u8>,
+    read_buf: Bytes,
+    write_buf: BytesMut,
+}
+
+
+impl Record {
+    /// Message type, i.e. the first byte of the frame
+    pub fn message_type(&self) -> u8 {
+        self.frame[0]
+    }
+    pub fn decode_client(&self) -> Result<ClientMessage, DecodeError> {
+        ClientMessage::decode(&self.frame)
+    }
+    pub fn decode_server(&self) -> Result<ServerMessage, DecodeError> {
+        ServerMessage::decode(&self.frame)
+    }
+    fn encode(&self, buf: &mut BytesMut) {
+        let micros = self.timestamp.duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::from_secs(0))
+            .as_micros() as u64;
+        buf.reserve(RECORD_HEADER + self.frame.len());
+        buf.put_u8(match self.direction {
+            Direction::Sent => b'>',
+            Direction::Received => b'<',
+        });
+        buf.put_u32(self.connection);
+        buf.put_u64(micros);
+        buf.extend_from_slice(&self.frame);
+    }
+}
+
+impl Recorder {
+    /// Creates (or truncates) the capture file
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Recorder> {
+        let mut file = fs::File::create(path)?;
+        file.write_all(MAGIC)?;
+        Ok(Recorder {
+            file: Mutex::new(file),
+            connections: AtomicU32::new(0),
+        })
+    }
+    fn write(&self, record: &Record) {
+        let mut buf = BytesMut::new();
+        record.encode(&mut buf);
+        let mut file = self.file.lock().expect("recorder is not poisoned");
+        // capture is best-effort, it should not break the connection
+        file.write_all(&buf).ok();
+    }
+}
+
+impl ConnectionRecorder {
+    pub(crate) fn new(recorder: &Arc<Recorder>) -> ConnectionRecorder {
+        ConnectionRecorder {
+            recorder: recorder.clone(),
+            connection: recorder.connections.fetch_add(1, Ordering::SeqCst)
+                + 1,
+        }
+    }
+    pub(crate) fn record(&self, direction: Direction, frame: &[u8]) {
+        self.recorder.write(&Record {
+            direction,
+            connection: self.connection,
+            timestamp: SystemTime::now(),
+            frame: Bytes::copy_from_slice(frame),
+        });
+    }
+}
+
+/// Parses records of the capture
+pub fn read(data: &[u8]) -> io::Result<Vec<Record>> {
+    if !data.starts_with(MAGIC) {
+        return Err(invalid_data("not a capture file"));
+    }
+    let mut data = &data[MAGIC.len()..];
+    let mut records = Vec::new();
+    while !data.is_empty() {
+        if data.len() < RECORD_HEADER + FRAME_HEADER {
+            return Err(invalid_data("truncated record"));
+        }
+        let direction = match data[0] {
+            b'>' => Direction::Sent,
+            b'<' => Direction::Received,
+            _ => return Err(invalid_data("invalid direction")),
+        };
+        let connection = u32::from_be_bytes(data[1..5].try_into().unwrap());
+        let micros = u64::from_be_bytes(data[5..13].try_into().unwrap());
+        let frame = &data[RECORD_HEADER..];
+        let len = frame_len(frame).expect("header is checked above");
+        if frame.len() < len {
+            return Err(invalid_data("truncated frame"));
+        }
+        records.push(Record {
+            direction,
+            connection,
+            timestamp: UNIX_EPOCH + Duration::from_micros(micros),
+            frame: Bytes::copy_from_slice(&frame[..len]),
+        });
+        data = &frame[len..];
+    }
+    Ok(records)
+}
+
+/// Reads and parses the capture file
+pub fn read_file(path: impl AsRef<Path>) -> io::Result<Vec<Record>> {
+    read(&fs::read(path)?)
+}
+
+/// Length of the whole frame if its header is complete
+fn frame_len(data: &[u8]) -> Option<usize> {
+    if data.len() < FRAME_HEADER {
+        return None;
+    }
+    let len = u32::from_be_bytes(data[1..5].try_into().unwrap());
+    Some(len as usize + 1)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl Replay {
+    /// Replays messages of the connection number `connection`
+    pub fn new(records: &[Record], connection: u32) -> Replay {
+        let mut received = VecDeque::new();
+        let mut sent = VecDeque::new();
+        for record in records {
+            if record.connection != connection {
+                continue;
+            }
+            match record.direction {
+                Direction::Received => {
+                    received.push_back(record.frame.clone());
+                }
+                Direction::Sent => sent.push_back(record.message_type()),
+            }
+        }
+        Replay {
+            received,
+            sent,
+            read_buf: Bytes::new(),
+            write_buf: BytesMut::new(),
+        }
+    }
+    /// Returns a connection that talks to this fake server
+    pub fn connect(self, params: &Builder) -> Connection {
+        Connection::new(self.into(), params.clone())
+    }
+    /// Number of messages the client is expected to send yet
+    pub fn pending_sent(&self) -> usize {
+        self.sent.len()
+    }
+}
+
+impl AsyncRead for Replay {
+    fn poll_read(mut self: Pin<&mut Self>, _cx: &mut Context,
+        buf: &mut [u8])
+        -> Poll<io::Result<usize>>
+    {
+        if self.read_buf.is_empty() {
+            match self.received.pop_front() {
+                Some(frame) => self.read_buf = frame,
+                // end of capture looks like the server closed connection
+                None => return Poll::Ready(Ok(0)),
+            }
+        }
+        let len = buf.len().min(self.read_buf.len());
+        buf[..len].copy_from_slice(&self.read_buf.split_to(len));
+        Poll::Ready(Ok(len))
+    }
+}
+
+impl AsyncWrite for Replay {
+    fn poll_write(mut self: Pin<&mut Self>, _cx: &mut Context, buf: &[u8])
+        -> Poll<io::Result<usize>>
+    {
+        self.write_buf.extend_from_slice(buf);
+        while let Some(len) = frame_len(&self.write_buf) {
+            if self.write_buf.len() < len {
+                break;
+            }
+            let frame = self.write_buf.split_to(len);
+            match self.sent.pop_front() {
+                Some(expected) if expected == frame[0] => {}
+                Some(expected) => {
+                    return Poll::Ready(Err(invalid_data(&format!(
+                        "client sent message {:?}, capture has {:?}",
+                        frame[0] as char, expected as char))));
+                }
+                None => {
+                    return Poll::Ready(Err(invalid_data(
+                        "client sent more messages than captured")));
+                }
+            }
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context)
+        -> Poll<io::Result<()>>
+    {
+        Poll::Ready(Ok(()))
+    }
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context)
+        -> Poll<io::Result<()>>
+    {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::sync::Arc;
+
+    use bytes::{Bytes, BytesMut};
+
+    use edgedb_protocol::server_message::{ServerMessage, Authentication};
+    use edgedb_protocol::server_message::{ReadyForCommand, TransactionState};
+    use edgedb_protocol::server_message::CommandComplete;
+
+    use crate::builder::Builder;
+    use crate::rt;
+    use super::{Recorder, ConnectionRecorder, Replay, Direction};
+    use super::read_file;
+
+    fn frame(msg: ServerMessage) -> Bytes {
+        let mut buf = BytesMut::new();
+        msg.encode(&mut buf).unwrap();
+        buf.freeze()
+    }
+
+    fn ready() -> ServerMessage {
+        ServerMessage::ReadyForCommand(ReadyForCommand {
+            headers: HashMap::new(),
+            transaction_state: TransactionState::NotInTransaction,
+        })
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let path = std::env::temp_dir()
+            .join(format!("edgedb-capture-{}.bin", std::process::id()));
+        let recorder = Arc::new(Recorder::create(&path).unwrap());
+        let conn = ConnectionRecorder::new(&recorder);
+        // client messages are only checked by type
+        conn.record(Direction::Sent, b"V\0\0\0\x04");
+        conn.record(Direction::Received,
+            &frame(ServerMessage::Authentication(Authentication::Ok)));
+        conn.record(Direction::Received, &frame(ready()));
+        conn.record(Direction::Sent, b"Q\0\0\0\x04");
+        conn.record(Direction::Received,
+            &frame(ServerMessage::CommandComplete(CommandComplete {
+                headers: HashMap::new(),
+                status_data: Bytes::from_static(b"SELECT"),
+            })));
+        conn.record(Direction::Received, &frame(ready()));
+        drop(conn);
+        drop(recorder);
+
+        let records = read_file(&path).unwrap();
+        fs::remove_file(&path).ok();
+        assert_eq!(records.len(), 6);
+        assert!(records.iter().all(|r| r.connection == 1));
+        assert_eq!(records[1].decode_server().unwrap(),
+                   ServerMessage::Authentication(Authentication::Ok));
+
+        let mut conn = Replay::new(&records, 1).connect(&Builder::new());
+        let status = rt::block_on(async {
+            let mut cli = conn.authenticate().await?;
+            cli.execute("SELECT 1").await
+        }).unwrap();
+        assert_eq!(&status[..], b"SELECT");
+
+        let mut conn = Replay::new(&records, 1).connect(&Builder::new());
+        let err = rt::block_on(async {
+            let mut cli = conn.authenticate().await?;
+            // `Prepare` instead of `ExecuteScript`
+            cli.describe_query("SELECT 1").await
+        }).unwrap_err();
+        assert!(err.to_string().contains("capture has"), "{}", err);
+    }
+}
@@ -0,0 +1,877 @@
+use std::collections::HashMap;
+use std::str;
+use std::sync::Arc;
+
+use bytes::{Bytes, BytesMut};
+use futures_util::io::AsyncWriteExt;
+use futures_util::stream::StreamExt;
+use scram::ScramClient;
+use snafu::{ResultExt, OptionExt};
+
+use edgedb_protocol::client_message::{ClientMessage, ClientHandshake};
+use edgedb_protocol::client_message::{Prepare, IoFormat, Cardinality};
+use edgedb_protocol::client_message::{DescribeStatement, DescribeAspect};
+use edgedb_protocol::client_message::{Execute, ExecuteScript};
+use edgedb_protocol::codec::Codec;
+use edgedb_protocol::error_response::error_name;
+use edgedb_protocol::headers::{Headers, RequestHeaders};
+use edgedb_protocol::query_args::QueryArgs;
+use edgedb_protocol::queryable::Queryable;
+use edgedb_protocol::server_message::{ServerMessage, Authentication};
+use edgedb_protocol::server_message::CommandDataDescription;
+use edgedb_protocol::server_message::TransactionState;
+
+use crate::builder::Builder;
+use crate::cancel::CancelHandle;
+use crate::capture::{ConnectionRecorder, Direction};
+use crate::errors::{self, Error, CLIENT_CONNECTION_ERROR};
+use crate::instrument::{Operation, Kind};
+use crate::reader::{self, Reader, ReadError, QueryableDecoder, QueryResponse};
+use crate::rt;
+use crate::statement_cache::{self as cache, StatementCache};
+use crate::stream::Stream;
+
+
+/// Established connection
+///
+/// Call `authenticate` to get a `Client` that can run queries.
+pub struct Connection {
+    stream: Stream,
+    pub(crate) reader: Reader<Stream>,
+    outbuf: BytesMut,
+    pub(crate) params: Builder,
+    server_params: HashMap<String, Bytes>,
+    statements: StatementCache,
+    key_data: Option<[u8; 32]>,
+    /// Result cardinality of the statement prepared by `Client::prepare`
+    prepared_cardinality: Cardinality,
+    /// Sent with every `Prepare`, `Execute` and `ExecuteScript`
+    pub(crate) headers: Headers,
+    authenticated: bool,
+    recorder: Option<ConnectionRecorder>,
+}
+
+/// Authenticated connection
+///
+/// Only one query can run at a time: a result of the query borrows the
+/// client until it's read to the end.
+pub struct Client<'a> {
+    pub(crate) conn: &'a mut Connection,
+}
+
+
+impl Connection {
+    pub(crate) fn new(stream: Stream, params: Builder) -> Connection {
+        let mut reader = Reader::new(stream.clone());
+        reader.set_log_handler(params.log_handler.clone());
+        let recorder = params.recorder.as_ref().map(ConnectionRecorder::new);
+        reader.set_recorder(recorder.clone());
+        Connection {
+            reader,
+            stream,
+            outbuf: BytesMut::with_capacity(8912),
+            statements: StatementCache::new(params.statement_cache_size),
+            params,
+            server_params: HashMap::new(),
+            key_data: None,
+            prepared_cardinality: Cardinality::NoResult,
+            headers: HashMap::new(),
+            authenticated: false,
+            recorder,
+        }
+    }
+
+    /// Returns true if connection is authenticated and is ready for the
+    /// next command outside of a transaction block
+    pub fn is_idle(&self) -> bool {
+        self.authenticated && self.reader.transaction_state()
+            == Some(TransactionState::NotInTransaction)
+    }
+
+    /// Returns false if the idle connection is closed by the server
+    pub(crate) fn is_alive(&mut self) -> bool {
+        self.reader.is_alive()
+    }
+
+    /// Opens a new connection with the same parameters instead of this one
+    ///
+    /// Use this when the connection is lost, see `Error::is_disconnect`.
+    /// Prepared statements, session settings and the transaction block of
+    /// the old connection are lost. Request headers are kept.
+    pub async fn reconnect(&mut self) -> Result<(), Error> {
+        let mut conn = self.params.connect().await?;
+        conn.authenticate().await?;
+        conn.headers = self.headers.clone();
+        *self = conn;
+        Ok(())
+    }
+
+    /// Reconnects making up to `Builder::reconnect_attempts` attempts
+    async fn reconnect_with_backoff(&mut self) -> Result<(), Error> {
+        let mut retry = 0;
+        let mut operation: Option<Operation> = None;
+        loop {
+            let result = match operation {
+                Some(ref mut operation) => {
+                    operation.run(self.reconnect()).await
+                }
+                None => self.reconnect().await,
+            };
+            match result {
+                Ok(()) => return Ok(()),
+                Err(ref e) if e.code() == Some(CLIENT_CONNECTION_ERROR) &&
+                    retry + 1 < self.params.reconnect_attempts
+                => {
+                    rt::sleep(self.params.get_reconnect_delay(retry)).await;
+                    retry += 1;
+                    operation = Some(Operation::retry(&self.params, retry, e));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn cancel_handle(&self) -> Option<CancelHandle> {
+        self.key_data.map(|key_data| CancelHandle {
+            params: self.params.clone(),
+            key_data,
+        })
+    }
+
+    /// Performs a handshake and authentication
+    ///
+    /// The whole process is limited by `Builder::auth_timeout`. If the
+    /// connection is already authenticated, e.g. by `reconnect`, just
+    /// returns the client.
+    pub async fn authenticate(&mut self) -> Result<Client<'_>, Error> {
+        if self.authenticated {
+            return Ok(Client { conn: self });
+        }
+        let auth_timeout = self.params.auth_timeout;
+        rt::timeout(auth_timeout, self._authenticate()).await
+            .map_err(|_| errors::Timeout {
+                operation: "authentication",
+                timeout: auth_timeout,
+            }.build())??;
+        let cancel = self.cancel_handle();
+        self.reader.set_timeout(self.params.query_timeout, cancel);
+        Ok(Client { conn: self })
+    }
+
+    async fn _authenticate(&mut self) -> Result<(), Error> {
+        let params = self.params.clone();
+        let mut cli = Client { conn: self };
+        let mut handshake_params = HashMap::new();
+        handshake_params.insert(String::from("user"), params.user.clone());
+        handshake_params.insert(String::from("database"),
+                                params.database.clone());
+
+        cli.send_message(&ClientMessage::ClientHandshake(ClientHandshake {
+            major_ver: 0,
+            minor_ver: 7,
+            params: handshake_params,
+            extensions: HashMap::new(),
+        })).await?;
+
+        let mut msg = cli.message().await?;
+        if let ServerMessage::ServerHandshake {..} = msg {
+            // TODO(tailhook) react on this somehow
+            msg = cli.message().await?;
+        }
+        match msg {
+            ServerMessage::Authentication(Authentication::Ok) => {}
+            ServerMessage::Authentication(Authentication::Sasl { methods })
+            => {
+                if methods.iter().any(|x| x == "SCRAM-SHA-256") {
+                    let password = params.get_password().await?
+                        .context(errors::PasswordRequired)?;
+                    cli.scram(&params.user, &password).await?;
+                } else {
+                    return errors::UnsupportedAuth { methods }.fail();
+                }
+            }
+            ServerMessage::ErrorResponse(error) => {
+                return errors::Server { error }.fail();
+            }
+            message => {
+                return errors::UnexpectedMessage { message }.fail();
+            }
+        }
+
+        loop {
+            match cli.message().await? {
+                ServerMessage::ReadyForCommand(..) => break,
+                ServerMessage::ServerKeyData(key) => {
+                    cli.conn.key_data = Some(key.data);
+                }
+                ServerMessage::ParameterStatus(par) => {
+                    let name = String::from_utf8_lossy(&par.name[..]);
+                    cli.conn.server_params.insert(name.into_owned(),
+                                                  par.value);
+                }
+                _ => {}
+            }
+        }
+        cli.conn.authenticated = true;
+        Ok(())
+    }
+    // Query methods returning a response are implemented on the connection,
+    // so the response can borrow the connection rather than the client,
+    // which is useful for wrappers like `blocking::Connection`.
+
+    pub(crate) async fn query<R, A>(&mut self, request: &str, arguments: &A)
+        -> Result<
+            QueryResponse<'_, Stream, QueryableDecoder<R>>,
+            Error
+        >
+        where R: Queryable,
+              A: QueryArgs + ?Sized,
+    {
+        let mut cli = Client { conn: self };
+        let stmt = cli._query(request, arguments, IoFormat::Binary,
+            Cardinality::Many).await?;
+        let desc = &stmt.output;
+        match desc.root_pos() {
+            Some(root_pos) => {
+                R::check_descriptor(&desc.as_queryable_context(), root_pos)
+                    .context(errors::ResultMismatch)?;
+                Ok(cli.conn.reader.response(QueryableDecoder::new(),
+                                            stmt.cardinality))
+            }
+            None => {
+                let completion_message = cli._process_exec().await?;
+                errors::NoResultExpected { completion_message }.fail()
+            }
+        }
+    }
+
+    pub(crate) async fn query_json<A>(&mut self, request: &str,
+        arguments: &A)
+        -> Result<
+            QueryResponse<'_, Stream, QueryableDecoder<String>>,
+            Error
+        >
+        where A: QueryArgs + ?Sized,
+    {
+        let mut cli = Client { conn: self };
+        let stmt = cli._query(request, arguments,
+            IoFormat::JsonElements, Cardinality::Many).await?;
+        let desc = &stmt.output;
+        match desc.root_pos() {
+            Some(root_pos) => {
+                String::check_descriptor(
+                    &desc.as_queryable_context(), root_pos)
+                    .context(errors::ResultMismatch)?;
+                Ok(cli.conn.reader.response(QueryableDecoder::new(),
+                                            stmt.cardinality))
+            }
+            None => {
+                let completion_message = cli._process_exec().await?;
+                errors::NoResultExpected { completion_message }.fail()
+            }
+        }
+    }
+
+    pub(crate) async fn query_dynamic<A>(&mut self, request: &str,
+        arguments: &A)
+        -> Result<
+            QueryResponse<'_, Stream, Arc<dyn Codec>>,
+            Error
+        >
+        where A: QueryArgs + ?Sized,
+    {
+        let mut cli = Client { conn: self };
+        let stmt = cli._query(request, arguments, IoFormat::Binary,
+            Cardinality::Many).await?;
+        let codec = stmt.output.build_codec().context(errors::Codec)?;
+        Ok(cli.conn.reader.response(codec, stmt.cardinality))
+    }
+}
+
+impl<'a> Client<'a> {
+    async fn scram(&mut self, user: &str, password: &str)
+        -> Result<(), Error>
+    {
+        use edgedb_protocol::client_message::SaslInitialResponse;
+        use edgedb_protocol::client_message::SaslResponse;
+
+        let auth_error = |e: scram::Error| {
+            errors::Authentication { message: e.to_string() }.build()
+        };
+        let scram = ScramClient::new(user, password, None);
+
+        let (scram, first) = scram.client_first();
+        self.send_message(&ClientMessage::AuthenticationSaslInitialResponse(
+                SaslInitialResponse {
+                method: "SCRAM-SHA-256".into(),
+                data: Bytes::copy_from_slice(first.as_bytes()),
+            })).await?;
+        let data = match self.message().await? {
+            ServerMessage::Authentication(
+                Authentication::SaslContinue { data }
+            ) => data,
+            ServerMessage::ErrorResponse(error) => {
+                return errors::Server { error }.fail();
+            }
+            message => {
+                return errors::UnexpectedMessage { message }.fail();
+            }
+        };
+        let data = str::from_utf8(&data[..]).ok()
+            .context(errors::Authentication {
+                message: "invalid utf-8 in SCRAM-SHA-256 auth",
+            })?;
+        let scram = scram.handle_server_first(data).map_err(auth_error)?;
+        let (scram, data) = scram.client_final();
+        self.send_message(&ClientMessage::AuthenticationSaslResponse(
+            SaslResponse {
+                data: Bytes::copy_from_slice(data.as_bytes()),
+            })).await?;
+        let data = match self.message().await? {
+            ServerMessage::Authentication(Authentication::SaslFinal { data })
+            => data,
+            ServerMessage::ErrorResponse(error) => {
+                return errors::Server { error }.fail();
+            }
+            message => {
+                return errors::UnexpectedMessage { message }.fail();
+            }
+        };
+        let data = str::from_utf8(&data[..]).ok()
+            .context(errors::Authentication {
+                message: "invalid utf-8 in SCRAM-SHA-256 auth",
+            })?;
+        scram.handle_server_final(data).map_err(auth_error)?;
+        loop {
+            if let ServerMessage::Authentication(Authentication::Ok)
+                = self.message().await?
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    async fn message(&mut self) -> Result<ServerMessage, Error> {
+        self.conn.reader.message().await.context(errors::Read)
+    }
+
+    async fn send_message(&mut self, msg: &ClientMessage)
+        -> Result<(), Error>
+    {
+        self.send_messages(std::slice::from_ref(msg)).await
+    }
+
+    /// Sends the request, `operation` is reported when the response is
+    /// read up to `ReadyForCommand`
+    pub(crate) async fn send_request(&mut self, mut operation: Operation,
+        msgs: &[ClientMessage])
+        -> Result<(), Error>
+    {
+        operation.run(self.send_messages(msgs)).await?;
+        self.conn.reader.start_operation(operation);
+        Ok(())
+    }
+
+    /// Sends all the messages in a single write
+    pub(crate) async fn send_messages(&mut self, msgs: &[ClientMessage])
+        -> Result<(), Error>
+    {
+        self.conn.reader.start_request(
+            msgs.iter().filter(|msg| expects_ready(msg)).count());
+        self.conn.outbuf.truncate(0);
+        for msg in msgs {
+            let start = self.conn.outbuf.len();
+            msg.encode(&mut self.conn.outbuf).context(errors::Encode)?;
+            if let Some(ref recorder) = self.conn.recorder {
+                recorder.record(Direction::Sent,
+                                &self.conn.outbuf[start..]);
+            }
+        }
+        self.conn.stream.write_all(&self.conn.outbuf[..]).await
+            .context(errors::Write)?;
+        Ok(())
+    }
+
+    /// Handle that can be used to cancel queries from another task
+    ///
+    /// Returns `None` if server hasn't sent the key data on connect.
+    pub fn cancel_handle(&self) -> Option<CancelHandle> {
+        self.conn.cancel_handle()
+    }
+
+    /// Raw value of the parameter reported by the server on connect
+    pub fn server_param(&self, name: &str) -> Option<&Bytes> {
+        self.conn.server_params.get(name)
+    }
+
+    /// State reported by the server when the last command was complete
+    ///
+    /// Returns `None` if the command is still in progress.
+    pub fn transaction_state(&self) -> Option<TransactionState> {
+        self.conn.reader.transaction_state()
+    }
+
+    /// Sets headers (implicit limit, allowed capabilities, etc.) sent with
+    /// all subsequent requests
+    ///
+    /// Statements are prepared with these headers, so changing them
+    /// clears the statement cache.
+    pub fn set_request_headers(&mut self, headers: &RequestHeaders) {
+        let headers = headers.build();
+        if headers != self.conn.headers {
+            self.conn.statements.clear();
+            self.conn.headers = headers;
+        }
+    }
+
+    /// Waits until server is ready for the next command
+    ///
+    /// Use this to skip the rest of the query response if it's not read
+    /// to the end.
+    pub async fn wait_ready(&mut self) -> Result<(), Error> {
+        self.conn.reader.wait_ready().await.context(errors::Read)
+    }
+
+    /// Gracefully closes the connection
+    pub async fn terminate(mut self) -> Result<(), Error> {
+        self.send_message(&ClientMessage::Terminate).await?;
+        match self.conn.reader.message().await {
+            Err(ReadError::Eos) => Ok(()),
+            Err(e) => Err(e).context(errors::Read),
+            Ok(message) => errors::UnexpectedMessage { message }.fail(),
+        }
+    }
+
+    /// Executes one or more statements ignoring the results
+    ///
+    /// Returns status of the last statement, e.g. `CREATE DATABASE`.
+    pub async fn execute<S>(&mut self, request: S)
+        -> Result<Bytes, Error>
+        where S: ToString,
+    {
+        let script_text = request.to_string();
+        let operation = Operation::new(Kind::Execute, &self.conn.params,
+                                       Some(&script_text));
+        self.send_request(operation, &[
+            ClientMessage::ExecuteScript(ExecuteScript {
+                headers: self.conn.headers.clone(),
+                script_text,
+            }),
+        ]).await?;
+        self._process_script().await?
+    }
+
+    /// Reads the response to a single `ExecuteScript` message
+    ///
+    /// Outer error means that the connection is broken, inner one is
+    /// returned by the server for this script only.
+    pub(crate) async fn _process_script(&mut self)
+        -> Result<Result<Bytes, Error>, Error>
+    {
+        let status = loop {
+            match self.message().await? {
+                ServerMessage::CommandComplete(c) => {
+                    self.wait_ready().await?;
+                    break c.status_data;
+                }
+                ServerMessage::ErrorResponse(error) => {
+                    self.wait_ready().await?;
+                    return Ok(errors::Server { error }.fail());
+                }
+                _ => {}
+            }
+        };
+        if cache::invalidates_cache(&status[..]) {
+            self.conn.statements.clear();
+        }
+        Ok(Ok(status))
+    }
+
+    /// Prepares the statement and returns its type descriptors
+    ///
+    /// Prepared statement can then be run by `execute_prepared`.
+    pub async fn prepare(&mut self, request: &str, io_format: IoFormat)
+        -> Result<CommandDataDescription, Error>
+    {
+        let description = self._prepare(Kind::Prepare, request, io_format,
+            Cardinality::Many, Bytes::from_static(b"")).await?;
+        self.conn.prepared_cardinality = description.result_cardinality;
+        Ok(description)
+    }
+
+    async fn _prepare(&mut self, kind: Kind, request: &str,
+        io_format: IoFormat, expected_cardinality: Cardinality,
+        statement_name: Bytes)
+        -> Result<CommandDataDescription, Error>
+    {
+        let mut operation = Operation::new(kind, &self.conn.params,
+                                           Some(request));
+        let result = operation.run(self._prepare_statement(request,
+            io_format, expected_cardinality, statement_name)).await;
+        if let Ok(ref description) = result {
+            operation.cardinality(description.result_cardinality);
+        }
+        result
+    }
+
+    async fn _prepare_statement(&mut self, request: &str,
+        io_format: IoFormat, expected_cardinality: Cardinality,
+        statement_name: Bytes)
+        -> Result<CommandDataDescription, Error>
+    {
+        // server skips the rest of the messages up to `Sync` on error, so
+        // it's safe to send the description request without waiting
+        self.send_messages(&[
+            ClientMessage::Prepare(Prepare {
+                headers: self.conn.headers.clone(),
+                io_format,
+                expected_cardinality,
+                statement_name: statement_name.clone(),
+                command_text: String::from(request),
+            }),
+            ClientMessage::DescribeStatement(DescribeStatement {
+                headers: HashMap::new(),
+                aspect: DescribeAspect::DataDescription,
+                statement_name,
+            }),
+            ClientMessage::Sync,
+        ]).await?;
+
+        let cardinality = match self.message().await? {
+            ServerMessage::PrepareComplete(complete) => complete.cardinality,
+            ServerMessage::ErrorResponse(error) => {
+                self.wait_ready().await?;
+                return errors::Server { error }.fail();
+            }
+            message => {
+                return errors::UnexpectedMessage { message }.fail();
+            }
+        };
+        let data_desc = match self.message().await? {
+            ServerMessage::CommandDataDescription(data_desc) => data_desc,
+            ServerMessage::ErrorResponse(error) => {
+                self.wait_ready().await?;
+                return errors::Server { error }.fail();
+            }
+            message => {
+                return errors::UnexpectedMessage { message }.fail();
+            }
+        };
+        self.wait_ready().await?;
+        if expected_cardinality == Cardinality::One &&
+            cardinality == Cardinality::Many
+        {
+            return errors::CardinalityMismatch {
+                message: "query may return more than one element",
+            }.fail();
+        }
+        Ok(data_desc)
+    }
+
+    /// Returns type descriptors of the query without executing it
+    pub async fn describe_query(&mut self, request: &str)
+        -> Result<CommandDataDescription, Error>
+    {
+        let description = self._prepare(Kind::Describe, request,
+            IoFormat::Binary, Cardinality::Many, Bytes::from_static(b""))
+            .await?;
+        self.conn.prepared_cardinality = description.result_cardinality;
+        Ok(description)
+    }
+
+    /// Executes statement prepared by `prepare` with encoded arguments
+    pub async fn execute_prepared<D>(&mut self, arguments: Bytes, decoder: D)
+        -> Result<QueryResponse<'_, Stream, D>, Error>
+        where D: reader::Decode,
+    {
+        let cardinality = self.conn.prepared_cardinality;
+        self._execute(None, cardinality, Bytes::from_static(b""), arguments)
+            .await?;
+        Ok(self.conn.reader.response(decoder, cardinality))
+    }
+
+    async fn _execute(&mut self, request: Option<&str>,
+        cardinality: Cardinality, statement_name: Bytes, arguments: Bytes)
+        -> Result<(), Error>
+    {
+        let operation = Operation::new(Kind::Execute, &self.conn.params,
+                                       request);
+        operation.cardinality(cardinality);
+        self.send_request(operation, &[
+            ClientMessage::Execute(Execute {
+                headers: self.conn.headers.clone(),
+                statement_name,
+                arguments,
+            }),
+            ClientMessage::Sync,
+        ]).await
+    }
+
+    /// Executes the statement, preparing it first unless it's cached
+    ///
+    /// Read-only query is retried on a new connection if the connection is
+    /// lost before the response is received (see
+    /// `Builder::retry_read_only`).
+    async fn _query<A>(&mut self, request: &str, arguments: &A,
+        io_format: IoFormat, cardinality: Cardinality)
+        -> Result<Arc<cache::Statement>, Error>
+        where A: QueryArgs + ?Sized,
+    {
+        let retry = self.conn.params.retry_read_only &&
+            self.transaction_state() ==
+                Some(TransactionState::NotInTransaction) &&
+            is_read_only(request);
+        let result = self._query_once(request, arguments,
+                                      io_format, cardinality).await;
+        match result {
+            Err(ref e) if retry && e.is_disconnect() => {
+                let mut operation = Operation::retry(&self.conn.params, 1, e);
+                operation.run(async {
+                    self.conn.reconnect_with_backoff().await?;
+                    self._query_once(request, arguments,
+                                     io_format, cardinality).await
+                }).await
+            }
+            result => result,
+        }
+    }
+
+    async fn _query_once<A>(&mut self, request: &str, arguments: &A,
+        io_format: IoFormat, cardinality: Cardinality)
+        -> Result<Arc<cache::Statement>, Error>
+        where A: QueryArgs + ?Sized,
+    {
+        let key = cache::Key {
+            query: request.into(),
+            io_format,
+            cardinality,
+        };
+        if let Some(stmt) = self.conn.statements.get(&key) {
+            let arguments = encode_arguments(&stmt, arguments)?;
+            self._execute(Some(request), stmt.cardinality,
+                          stmt.name.clone(), arguments).await?;
+            match self.message().await? {
+                ServerMessage::ErrorResponse(ref error)
+                if cache::is_stale(error) => {
+                    self.wait_ready().await?;
+                    self.conn.statements.remove(&key);
+                }
+                message => {
+                    self.conn.reader.push_back(message);
+                    return Ok(stmt);
+                }
+            }
+        }
+        let name = if self.conn.statements.is_enabled() {
+            self.conn.statements.next_name()
+        } else {
+            Bytes::from_static(b"")
+        };
+        let data_description = self._prepare(Kind::Prepare, request,
+            io_format, cardinality, name.clone()).await?;
+        let stmt = Arc::new(cache::Statement::new(name, &data_description)?);
+        let arguments = encode_arguments(&stmt, arguments)?;
+        self._execute(Some(request), stmt.cardinality,
+                      stmt.name.clone(), arguments).await?;
+        self.conn.statements.insert(key, stmt.clone());
+        // wait for the response so that lost connection is detected here
+        let message = self.message().await?;
+        self.conn.reader.push_back(message);
+        Ok(stmt)
+    }
+
+    /// Runs the query and returns a stream of rows decoded into `R`
+    ///
+    /// Returns `Error::NoResultExpected` if the statement returns no data.
+    pub async fn query<R, A>(&mut self, request: &str, arguments: &A)
+        -> Result<
+            QueryResponse<'_, Stream, QueryableDecoder<R>>,
+            Error
+        >
+        where R: Queryable,
+              A: QueryArgs + ?Sized,
+    {
+        self.conn.query(request, arguments).await
+    }
+
+    /// Runs the query and returns a stream of rows serialized to JSON
+    pub async fn query_json<A>(&mut self, request: &str, arguments: &A)
+        -> Result<
+            QueryResponse<'_, Stream, QueryableDecoder<String>>,
+            Error
+        >
+        where A: QueryArgs + ?Sized,
+    {
+        self.conn.query_json(request, arguments).await
+    }
+
+    /// Runs the query and returns a stream of dynamically typed values
+    pub async fn query_dynamic<A>(&mut self, request: &str, arguments: &A)
+        -> Result<
+            QueryResponse<'_, Stream, Arc<dyn Codec>>,
+            Error
+        >
+        where A: QueryArgs + ?Sized,
+    {
+        self.conn.query_dynamic(request, arguments).await
+    }
+
+    async fn _query_single<R, A>(&mut self, request: &str, arguments: &A)
+        -> Result<Option<R>, Error>
+        where R: Queryable,
+              A: QueryArgs + ?Sized,
+    {
+        let stmt = self._query(request, arguments, IoFormat::Binary,
+            Cardinality::One).await?;
+        let desc = &stmt.output;
+        let root_pos = match desc.root_pos() {
+            Some(root_pos) => root_pos,
+            None => {
+                let completion_message = self._process_exec().await?;
+                return errors::NoResultExpected { completion_message }.fail();
+            }
+        };
+        R::check_descriptor(&desc.as_queryable_context(), root_pos)
+            .context(errors::ResultMismatch)?;
+        let mut response = self.conn.reader.response(
+            QueryableDecoder::<R>::new(), stmt.cardinality);
+        let result = match response.next().await.transpose() {
+            Ok(None) => return Ok(None),
+            Ok(Some(value)) => match response.next().await.transpose() {
+                Ok(None) => return Ok(Some(value)),
+                Ok(Some(_)) => errors::CardinalityMismatch {
+                    message: "more than one element returned",
+                }.fail(),
+                Err(e) => Err(e).context(errors::Read),
+            },
+            Err(e) => Err(e).context(errors::Read),
+        };
+        self.wait_ready().await?;
+        result
+    }
+
+    /// Runs the query that returns at most one element
+    ///
+    /// Returns `Error::CardinalityMismatch` if the query may return more
+    /// than one element.
+    pub async fn query_single<R, A>(&mut self, request: &str, arguments: &A)
+        -> Result<Option<R>, Error>
+        where R: Queryable,
+              A: QueryArgs + ?Sized,
+    {
+        self._query_single(request, arguments).await
+            .map_err(cardinality_error)
+    }
+
+    /// Runs the query that returns exactly one element
+    ///
+    /// Returns `Error::NoData` if the query returns nothing.
+    pub async fn query_required_single<R, A>(&mut self, request: &str,
+        arguments: &A)
+        -> Result<R, Error>
+        where R: Queryable,
+              A: QueryArgs + ?Sized,
+    {
+        self._query_single(request, arguments).await
+            .map_err(cardinality_error)?
+            .context(errors::NoData)
+    }
+
+    async fn _process_exec(&mut self) -> Result<Bytes, Error> {
+        let status = loop {
+            match self.message().await? {
+                ServerMessage::CommandComplete(c) => {
+                    self.wait_ready().await?;
+                    break c.status_data;
+                }
+                ServerMessage::ErrorResponse(error) => {
+                    self.wait_ready().await?;
+                    return errors::Server { error }.fail();
+                }
+                _ => {}
+            }
+        };
+        if cache::invalidates_cache(&status[..]) {
+            self.conn.statements.clear();
+        }
+        Ok(status)
+    }
+
+    /// Executes a single statement with arguments ignoring the result
+    pub async fn execute_args<A>(&mut self, request: &str, arguments: &A)
+        -> Result<Bytes, Error>
+        where A: QueryArgs + ?Sized,
+    {
+        self._query(request, arguments, IoFormat::Binary,
+            Cardinality::Many).await?;
+        self._process_exec().await
+    }
+}
+
+/// Returns true if the query is certainly not modifying anything
+///
+/// This is conservative: any query containing data modification keywords,
+/// even in string literals, is considered modifying.
+fn is_read_only(query: &str) -> bool {
+    const MODIFYING: &[&str] = &[
+        "INSERT", "UPDATE", "DELETE", "CONFIGURE", "SET", "RESET",
+    ];
+    let mut words = query
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|word| !word.is_empty());
+    match words.next() {
+        Some(word) if word.eq_ignore_ascii_case("SELECT") => {}
+        _ => return false,
+    }
+    !words.any(|word| {
+        MODIFYING.iter().any(|kw| word.eq_ignore_ascii_case(kw))
+    })
+}
+
+/// Returns true if server answers the message with `ReadyForCommand`
+fn expects_ready(msg: &ClientMessage) -> bool {
+    match msg {
+        ClientMessage::ClientHandshake(..) => true,
+        ClientMessage::ExecuteScript(..) => true,
+        ClientMessage::Sync => true,
+        _ => false,
+    }
+}
+
+/// Converts server error on result cardinality into a distinct error
+fn cardinality_error(e: Error) -> Error {
+    match e.server_error() {
+        Some(err) if error_name(err.code) == "ResultCardinalityMismatchError"
+        => {
+            errors::CardinalityMismatch { message: err.message.clone() }
+            .build()
+        }
+        _ => e,
+    }
+}
+
+fn encode_arguments<A>(stmt: &cache::Statement, arguments: &A)
+    -> Result<Bytes, Error>
+    where A: QueryArgs + ?Sized,
+{
+    let value = arguments.to_value(&stmt.input)
+        .context(errors::ArgumentMismatch)?;
+    let mut buf = BytesMut::with_capacity(8);
+    stmt.input_codec.encode(&mut buf, &value).context(errors::Encode)?;
+    Ok(buf.freeze())
+}
+
+#[cfg(test)]
+mod test {
+    use super::is_read_only;
+
+    #[test]
+    fn read_only() {
+        assert!(is_read_only("SELECT User { name }"));
+        assert!(is_read_only("  select 1 + 2"));
+        assert!(!is_read_only("SELECT (INSERT User { name := 'x' })"));
+        assert!(!is_read_only("WITH x := 1 SELECT x"));
+        assert!(!is_read_only("UPDATE User SET { name := 'x' }"));
+        assert!(!is_read_only("SELECT 'insert'"));
+    }
+}
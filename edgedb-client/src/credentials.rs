@@ -0,0 +1,91 @@
+//! Credentials and password files
+//!
+//! Credentials file is a JSON with connection parameters of a single
+//! instance:
+//! ```json
+//! {"host": "localhost", "port": 5656, "user": "edgedb",
+//!  "password": "secret", "database": "edgedb"}
+//! ```
+//! Only `user` is required.
+//!
+//! Password file is like `.pgpass` of PostgreSQL: each line is
+//! `host:port:database:user:password`, any of the first four fields can be
+//! `*` to match anything. Use `\:` and `\\` for colon and backslash in
+//! the fields. First matching line wins.
+use serde::{Serialize, Deserialize};
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Credentials {
+    #[serde(default)]
+    pub host: Option<String>,
+    #[serde(default)]
+    pub port: Option<u16>,
+    pub user: String,
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub database: Option<String>,
+}
+
+/// Finds password for the connection in the password file contents
+pub(crate) fn find_password(data: &str,
+    host: &str, port: u16, database: &str, user: &str)
+    -> Option<String>
+{
+    let port = port.to_string();
+    let expected = [host, &port[..], database, user];
+    for line in data.lines() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields = split_line(line);
+        if fields.len() != 5 {
+            continue;
+        }
+        let matches = fields[..4].iter().zip(&expected)
+            .all(|(field, value)| field == "*" || field == value);
+        if matches {
+            return Some(fields[4].clone());
+        }
+    }
+    None
+}
+
+fn split_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => field.extend(chars.next()),
+            ':' => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+#[cfg(test)]
+mod test {
+    use super::find_password;
+
+    #[test]
+    fn password_file() {
+        let data = "\
+            # comment\n\
+            db.example.com:5656:*:admin:first\n\
+            *:*:main:*:second\n\
+            *:*:*:us\\:er:th\\\\ird\n\
+        ";
+        assert_eq!(find_password(data, "db.example.com", 5656, "x", "admin"),
+                   Some("first".into()));
+        assert_eq!(find_password(data, "db.example.com", 5657, "x", "admin"),
+                   None);
+        assert_eq!(find_password(data, "localhost", 5656, "main", "admin"),
+                   Some("second".into()));
+        assert_eq!(find_password(data, "localhost", 5656, "x", "us:er"),
+                   Some("th\\ird".into()));
+    }
+}
@@ -0,0 +1,147 @@
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use bytes::Bytes;
+use snafu::Snafu;
+
+use edgedb_protocol::errors::{DecodeError, EncodeError, CodecError};
+use edgedb_protocol::queryable::DescriptorMismatch;
+use edgedb_protocol::error_response::error_name;
+use edgedb_protocol::server_message::{ServerMessage, ErrorResponse};
+use edgedb_protocol::server_message::TransactionState as State;
+
+use crate::reader::ReadError;
+
+
+#[derive(Snafu, Debug)]
+#[snafu(visibility(pub(crate)))]
+#[non_exhaustive]
+pub enum Error {
+    #[snafu(display("error connecting to {}:{}: {}", host, port, source))]
+    Connect { host: String, port: u16, source: io::Error },
+    #[snafu(display("error connecting to {}: {}", path.display(), source))]
+    ConnectUnix { path: PathBuf, source: io::Error },
+    #[snafu(display("runstate dir {} not found", path.display()))]
+    RunstateDirNotFound { path: PathBuf },
+    #[snafu(display("invalid DSN: {}", message))]
+    InvalidDsn { message: String },
+    #[snafu(display("error reading {}: {}", path.display(), source))]
+    ReadCredentials { path: PathBuf, source: io::Error },
+    #[snafu(display("invalid credentials file {}: {}",
+                    path.display(), source))]
+    InvalidCredentials { path: PathBuf, source: serde_json::Error },
+    #[snafu(display("error reading {}: {}", path.display(), source))]
+    ReadPasswordFile { path: PathBuf, source: io::Error },
+    #[snafu(display("error reading CA file {}: {}", path.display(), source))]
+    ReadCaFile { path: PathBuf, source: io::Error },
+    #[snafu(display("no valid certificates in CA file {}", path.display()))]
+    InvalidCaFile { path: PathBuf },
+    #[snafu(display("TLS handshake with {:?} failed: {}",
+                    server_name, source))]
+    TlsHandshake { server_name: String, source: io::Error },
+    #[snafu(display("error writing data: {}", source))]
+    Write { source: io::Error },
+    #[snafu(display("{}", source))]
+    Read { source: ReadError },
+    #[snafu(display("error encoding message: {}", source))]
+    Encode { source: EncodeError },
+    #[snafu(display("error decoding type descriptor: {}", source))]
+    Descriptor { source: DecodeError },
+    #[snafu(display("error building codec: {}", source))]
+    Codec { source: CodecError },
+    #[snafu(display("result type mismatch: {}", source))]
+    ResultMismatch { source: DescriptorMismatch },
+    #[snafu(display("arguments mismatch: {}", source))]
+    ArgumentMismatch { source: DescriptorMismatch },
+    #[snafu(display("{}", error))]
+    Server { error: ErrorResponse },
+    #[snafu(display("password is required"))]
+    PasswordRequired,
+    #[snafu(display("no supported authentication methods: {:?}", methods))]
+    UnsupportedAuth { methods: Vec<String> },
+    #[snafu(display("authentication error: {}", message))]
+    Authentication { message: String },
+    #[snafu(display("unexpected message {:?}", message))]
+    UnexpectedMessage { message: ServerMessage },
+    #[snafu(display("no result expected: {}",
+                    String::from_utf8_lossy(&completion_message[..])))]
+    NoResultExpected { completion_message: Bytes },
+    #[snafu(display("no connection available in the pool after {:?}",
+                    timeout))]
+    AcquireTimeout { timeout: Duration },
+    #[snafu(display("connection is not ready for a transaction: {:?}",
+                    state))]
+    TransactionState { state: Option<State> },
+    #[snafu(display("result cardinality mismatch: {}", message))]
+    CardinalityMismatch { message: String },
+    #[snafu(display("query returned no data"))]
+    NoData,
+    #[snafu(display("{} timed out after {:?}", operation, timeout))]
+    Timeout { operation: &'static str, timeout: Duration },
+    #[snafu(display("error starting runtime: {}", source))]
+    Runtime { source: io::Error },
+}
+
+/// Code of `ClientConnectionError` in the server's error hierarchy
+pub const CLIENT_CONNECTION_ERROR: u32 = 0x_FF_01_00_00;
+/// Code of `NoDataError` in the server's error hierarchy
+pub const NO_DATA_ERROR: u32 = 0x_FF_03_00_00;
+
+impl Error {
+    /// Error code compatible with the ones sent by the server
+    ///
+    /// Use `edgedb_protocol::error_response::error_name` to get the name.
+    /// Returns `None` for errors that have no equivalent there.
+    pub fn code(&self) -> Option<u32> {
+        use ReadError::{Io, Eos, Timeout as ReadTimeout};
+
+        match self {
+            Error::Connect { .. } | Error::ConnectUnix { .. } |
+            Error::RunstateDirNotFound { .. } | Error::TlsHandshake { .. } |
+            Error::Write { .. } | Error::Timeout { .. } |
+            Error::Read { source: Io { .. } } |
+            Error::Read { source: Eos } |
+            Error::Read { source: ReadTimeout { .. } }
+            => Some(CLIENT_CONNECTION_ERROR),
+            Error::NoData => Some(NO_DATA_ERROR),
+            _ => self.server_error().map(|e| e.code),
+        }
+    }
+    /// Returns true if the connection is closed or broken
+    ///
+    /// Such connection can't be used anymore, but `Connection::reconnect`
+    /// may succeed.
+    pub fn is_disconnect(&self) -> bool {
+        match self {
+            Error::Write { .. } => true,
+            Error::Read { source } => source.is_disconnect(),
+            _ => false,
+        }
+    }
+    /// Returns true if the request timed out
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            Error::Timeout { .. } => true,
+            Error::Read { source: ReadError::Timeout { .. } } => true,
+            _ => false,
+        }
+    }
+    /// Returns error response if the error is reported by the server
+    pub fn server_error(&self) -> Option<&ErrorResponse> {
+        match self {
+            Error::Server { error } => Some(error),
+            Error::Read { source: ReadError::RequestError { error, .. } }
+            => Some(error),
+            _ => None,
+        }
+    }
+    /// Returns true if the transaction may succeed if run again
+    pub fn is_transaction_conflict(&self) -> bool {
+        match self.server_error().map(|e| error_name(e.code)) {
+            Some("TransactionSerializationError") => true,
+            Some("TransactionDeadlockError") => true,
+            _ => false,
+        }
+    }
+}
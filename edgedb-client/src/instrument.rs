@@ -0,0 +1,230 @@
+//! Tracing spans and metrics of the client operations
+//!
+//! Spans are only emitted with the `tracing` feature, metrics are reported
+//! if `Builder::metrics` is set.
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
+
+use edgedb_protocol::server_message::Cardinality;
+
+use crate::builder::Builder;
+use crate::errors::Error;
+use crate::metrics::{self, Metrics};
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Kind {
+    Connect,
+    Prepare,
+    Describe,
+    Execute,
+}
+
+/// Metrics group of the operation, retries are started with `retry()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Category {
+    Op(Kind),
+    Retry,
+}
+
+/// A single client operation, reported when dropped
+pub(crate) struct Operation {
+    category: Category,
+    started: Instant,
+    rows: u64,
+    failed: bool,
+    metrics: Option<Arc<dyn Metrics>>,
+    #[cfg(feature="tracing")]
+    span: tracing::Span,
+}
+
+
+impl Operation {
+    #[cfg_attr(not(feature="tracing"), allow(unused_variables))]
+    pub(crate) fn new(kind: Kind, params: &Builder, query: Option<&str>)
+        -> Operation
+    {
+        Operation {
+            category: Category::Op(kind),
+            started: Instant::now(),
+            rows: 0,
+            failed: false,
+            metrics: params.metrics.clone(),
+            #[cfg(feature="tracing")]
+            span: span(kind, params, query),
+        }
+    }
+    #[cfg_attr(not(feature="tracing"), allow(unused_variables))]
+    pub(crate) fn retry(params: &Builder, attempt: u32, reason: &Error)
+        -> Operation
+    {
+        Operation {
+            category: Category::Retry,
+            started: Instant::now(),
+            rows: 0,
+            failed: false,
+            metrics: params.metrics.clone(),
+            #[cfg(feature="tracing")]
+            span: tracing::info_span!("edgedb.retry",
+                attempt,
+                reason = %reason,
+                duration_ms = tracing::field::Empty,
+                error = tracing::field::Empty),
+        }
+    }
+    /// Runs the future within the span, marking operation as failed on
+    /// error
+    pub(crate) async fn run<F, T>(&mut self, f: F) -> Result<T, Error>
+        where F: Future<Output=Result<T, Error>>,
+    {
+        #[cfg(feature="tracing")]
+        let result = tracing::Instrument::instrument(f, self.span.clone())
+            .await;
+        #[cfg(not(feature="tracing"))]
+        let result = f.await;
+        if result.is_err() {
+            self.failed = true;
+        }
+        result
+    }
+    #[cfg_attr(not(feature="tracing"), allow(unused_variables))]
+    pub(crate) fn cardinality(&self, cardinality: Cardinality) {
+        #[cfg(feature="tracing")]
+        self.span.record("cardinality",
+                         &tracing::field::debug(cardinality));
+    }
+    pub(crate) fn add_rows(&mut self, rows: usize) {
+        self.rows += rows as u64;
+    }
+    pub(crate) fn fail(&mut self) {
+        self.failed = true;
+    }
+}
+
+impl Drop for Operation {
+    fn drop(&mut self) {
+        use Category::*;
+        use Kind::*;
+
+        let elapsed = self.started.elapsed();
+        #[cfg(feature="tracing")] {
+            self.span.record("duration_ms", &(elapsed.as_millis() as u64));
+            self.span.record("error", &self.failed);
+            if self.category == Op(Execute) {
+                self.span.record("rows", &self.rows);
+            }
+        }
+        let metrics = match self.metrics {
+            Some(ref metrics) => metrics,
+            None => return,
+        };
+        let (count, errors, time) = match self.category {
+            Op(Connect) => (metrics::CONNECTS, metrics::CONNECT_ERRORS,
+                            metrics::CONNECT_TIME),
+            Op(Prepare) | Op(Describe) => (metrics::PREPARES,
+                                           metrics::PREPARE_ERRORS,
+                                           metrics::PREPARE_TIME),
+            Op(Execute) => (metrics::QUERIES, metrics::QUERY_ERRORS,
+                            metrics::QUERY_TIME),
+            Retry => (metrics::RETRIES, metrics::RETRY_ERRORS,
+                      metrics::RETRY_TIME),
+        };
+        metrics.counter(count, 1);
+        if self.failed {
+            metrics.counter(errors, 1);
+        }
+        metrics.histogram(time, elapsed);
+        if self.category == Op(Execute) {
+            metrics.counter(metrics::ROWS, self.rows);
+        }
+    }
+}
+
+#[cfg(feature="tracing")]
+fn span(kind: Kind, params: &Builder, query: Option<&str>) -> tracing::Span {
+    use tracing::field::Empty;
+
+    let query = if params.redact_queries {
+        "<redacted>"
+    } else {
+        query.unwrap_or("<prepared>")
+    };
+    match kind {
+        Kind::Connect => tracing::info_span!("edgedb.connect",
+            host = %params.host,
+            port = params.port,
+            duration_ms = Empty,
+            error = Empty),
+        Kind::Prepare => tracing::info_span!("edgedb.prepare",
+            query,
+            cardinality = Empty,
+            duration_ms = Empty,
+            error = Empty),
+        Kind::Describe => tracing::info_span!("edgedb.describe",
+            query,
+            cardinality = Empty,
+            duration_ms = Empty,
+            error = Empty),
+        Kind::Execute => tracing::info_span!("edgedb.execute",
+            query,
+            cardinality = Empty,
+            rows = Empty,
+            duration_ms = Empty,
+            error = Empty),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use crate::builder::Builder;
+    use crate::errors::Error;
+    use crate::metrics::{self, Metrics};
+    use super::{Operation, Kind};
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<(&'static str, u64)>>);
+
+    impl Metrics for Recorder {
+        fn counter(&self, name: &'static str, value: u64) {
+            self.0.lock().unwrap().push((name, value));
+        }
+        fn histogram(&self, name: &'static str, _value: Duration) {
+            self.0.lock().unwrap().push((name, 0));
+        }
+    }
+
+    #[test]
+    fn execute_metrics() {
+        let recorder = Arc::new(Recorder::default());
+        let mut params = Builder::new();
+        params.metrics(recorder.clone());
+        let mut op = Operation::new(Kind::Execute, &params, Some("SELECT 1"));
+        op.add_rows(2);
+        op.add_rows(3);
+        op.fail();
+        drop(op);
+        assert_eq!(*recorder.0.lock().unwrap(), vec![
+            (metrics::QUERIES, 1),
+            (metrics::QUERY_ERRORS, 1),
+            (metrics::QUERY_TIME, 0),
+            (metrics::ROWS, 5),
+        ]);
+    }
+
+    #[test]
+    fn retry_metrics() {
+        let recorder = Arc::new(Recorder::default());
+        let mut params = Builder::new();
+        params.metrics(recorder.clone());
+        let reason = Error::NoData;
+        drop(Operation::retry(&params, 1, &reason));
+        assert_eq!(*recorder.0.lock().unwrap(), vec![
+            (metrics::RETRIES, 1),
+            (metrics::RETRY_TIME, 0),
+        ]);
+    }
+}
@@ -0,0 +1,51 @@
+//! Async client for the EdgeDB database
+//!
+//! Works with either async-std (`rt-async-std` feature, the default) or
+//! tokio 0.2 (`rt-tokio` feature with default features disabled).
+//!
+//! The `tracing` feature enables spans for connect, prepare, describe,
+//! execute and retries. Metrics are reported to `Builder::metrics`.
+//!
+//! ```rust,no_run
+//! # use futures_util::StreamExt;
+//! # use edgedb_protocol::value::Value;
+//! # async fn example() -> Result<(), edgedb_client::Error> {
+//! let mut conn = edgedb_client::Builder::new()
+//!     .user("edgedb")
+//!     .password("secret")
+//!     .connect().await?;
+//! let mut cli = conn.authenticate().await?;
+//! let mut names = cli.query::<String, _>(
+//!     "SELECT User.name", &Value::empty_tuple()).await?;
+//! while let Some(name) = names.next().await.transpose()
+//!     .map_err(|source| edgedb_client::Error::Read { source })?
+//! {
+//!     println!("{}", name);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+mod backoff;
+pub mod batch;
+pub mod blocking;
+pub mod builder;
+pub mod cancel;
+pub mod capture;
+pub mod client;
+pub mod credentials;
+pub mod errors;
+mod instrument;
+pub mod metrics;
+pub mod pool;
+pub mod query_builder;
+pub mod reader;
+mod rt;
+mod statement_cache;
+pub mod stream;
+pub mod tls;
+pub mod transaction;
+
+pub use builder::Builder;
+pub use client::{Connection, Client};
+pub use errors::Error;
+pub use pool::Pool;
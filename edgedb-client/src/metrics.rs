@@ -0,0 +1,77 @@
+//! Pluggable metrics of the client
+//!
+//! Implement `Metrics` and pass it to `Builder::metrics` to export counters
+//! and histograms to your monitoring system. All names are listed below as
+//! constants.
+//!
+//! ```rust
+//! use std::sync::atomic::{AtomicU64, Ordering};
+//! use std::time::Duration;
+//! use edgedb_client::metrics::{Metrics, QUERIES};
+//!
+//! #[derive(Default)]
+//! struct QueryCounter(AtomicU64);
+//!
+//! impl Metrics for QueryCounter {
+//!     fn counter(&self, name: &'static str, value: u64) {
+//!         if name == QUERIES {
+//!             self.0.fetch_add(value, Ordering::Relaxed);
+//!         }
+//!     }
+//!     fn histogram(&self, _name: &'static str, _value: Duration) {}
+//! }
+//!
+//! let mut params = edgedb_client::Builder::new();
+//! params.metrics(QueryCounter::default());
+//! ```
+use std::sync::Arc;
+use std::time::Duration;
+
+
+/// Connection attempts (counter)
+pub const CONNECTS: &str = "edgedb_client.connects";
+/// Failed connection attempts (counter)
+pub const CONNECT_ERRORS: &str = "edgedb_client.connect_errors";
+/// Time to establish a connection (histogram)
+pub const CONNECT_TIME: &str = "edgedb_client.connect_time";
+/// Statements prepared (counter)
+pub const PREPARES: &str = "edgedb_client.prepares";
+/// Statements failed to prepare (counter)
+pub const PREPARE_ERRORS: &str = "edgedb_client.prepare_errors";
+/// Time of prepare and describe round trip (histogram)
+pub const PREPARE_TIME: &str = "edgedb_client.prepare_time";
+/// Queries executed (counter)
+pub const QUERIES: &str = "edgedb_client.queries";
+/// Queries failed (counter)
+pub const QUERY_ERRORS: &str = "edgedb_client.query_errors";
+/// Time from sending a query to receiving the whole response (histogram)
+pub const QUERY_TIME: &str = "edgedb_client.query_time";
+/// Rows received (counter)
+pub const ROWS: &str = "edgedb_client.rows";
+/// Retries of queries, transactions and reconnects (counter)
+pub const RETRIES: &str = "edgedb_client.retries";
+/// Retries that failed (counter)
+pub const RETRY_ERRORS: &str = "edgedb_client.retry_errors";
+/// Time of the retried operation, not including the delay (histogram)
+pub const RETRY_TIME: &str = "edgedb_client.retry_time";
+
+
+/// Receiver of the client metrics
+///
+/// Methods are called synchronously on each operation, so they should be
+/// cheap, e.g. update an atomic or send to a channel.
+pub trait Metrics: Send + Sync {
+    /// Increments counter `name` by `value`
+    fn counter(&self, name: &'static str, value: u64);
+    /// Records `value` in histogram `name`
+    fn histogram(&self, name: &'static str, value: Duration);
+}
+
+impl<T: Metrics + ?Sized> Metrics for Arc<T> {
+    fn counter(&self, name: &'static str, value: u64) {
+        (**self).counter(name, value)
+    }
+    fn histogram(&self, name: &'static str, value: Duration) {
+        (**self).histogram(name, value)
+    }
+}
@@ -0,0 +1,357 @@
+//! Connection pool
+//!
+//! Connections are opened lazily up to the `max_size`, or in advance by
+//! `Pool::warm_up`. A connection is returned to the pool only if it's
+//! ready for the next command and is not in a transaction block, otherwise
+//! it's closed. Idle connections closed by the server are discarded on
+//! acquire.
+use std::cmp::min;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Poll, Waker};
+use std::time::{Duration, Instant};
+
+use futures_util::future::poll_fn;
+
+use crate::builder::Builder;
+use crate::client::{Client, Connection};
+use crate::errors::{self, Error};
+use crate::rt;
+
+
+/// Pool settings
+#[derive(Debug, Clone)]
+pub struct PoolOptions {
+    min_size: usize,
+    max_size: usize,
+    acquire_timeout: Duration,
+    idle_timeout: Option<Duration>,
+}
+
+/// Shared pool of authenticated connections
+///
+/// Cloning the pool is cheap, clones refer to the same connections.
+#[derive(Clone)]
+pub struct Pool {
+    inner: Arc<Inner>,
+}
+
+/// Connection borrowed from the pool
+///
+/// It's returned to the pool when dropped.
+pub struct PooledConnection {
+    conn: Option<Connection>,
+    pool: Arc<Inner>,
+}
+
+/// Snapshot of the pool counters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Connections waiting in the pool
+    pub idle: usize,
+    /// Connections acquired, including ones being established
+    pub in_use: usize,
+    /// Tasks waiting for a connection
+    pub waiting: usize,
+    /// Connections opened since pool creation
+    pub created: u64,
+    /// Connections closed because they were broken, in a transaction or
+    /// closed by the server while idle
+    pub discarded: u64,
+    /// Connections closed because of `idle_timeout`
+    pub reaped: u64,
+}
+
+struct Inner {
+    params: Builder,
+    options: PoolOptions,
+    state: Mutex<State>,
+}
+
+struct State {
+    /// Most recently used connection is at the back
+    idle: VecDeque<IdleConnection>,
+    in_use: usize,
+    /// Wakers of the pending `acquire` calls by waiter id
+    waiters: Vec<(u64, Waker)>,
+    next_waiter: u64,
+    created: u64,
+    discarded: u64,
+    reaped: u64,
+}
+
+struct IdleConnection {
+    conn: Connection,
+    since: Instant,
+}
+
+/// Permission to open a new connection, returned back unless disarmed
+struct Slot<'a> {
+    pool: &'a Inner,
+    armed: bool,
+}
+
+/// Registration of the pending `acquire`, removed when future is dropped
+struct Waiter<'a> {
+    pool: &'a Inner,
+    id: u64,
+}
+
+
+impl PoolOptions {
+    pub fn new() -> PoolOptions {
+        PoolOptions {
+            min_size: 0,
+            max_size: 10,
+            acquire_timeout: Duration::from_secs(30),
+            idle_timeout: Some(Duration::from_secs(60)),
+        }
+    }
+    /// Number of connections that are not closed by `idle_timeout`
+    ///
+    /// This doesn't open connections by itself: the pool only has this many
+    /// connections after `Pool::warm_up` or after that many were in use at
+    /// the same time. Connections that are broken or closed by the server
+    /// are not replaced until `warm_up` is called again.
+    pub fn min_size(&mut self, min_size: usize) -> &mut Self {
+        self.min_size = min_size;
+        self
+    }
+    /// Maximum number of connections open at the same time
+    pub fn max_size(&mut self, max_size: usize) -> &mut Self {
+        assert!(max_size > 0, "max_size must be positive");
+        self.max_size = max_size;
+        self
+    }
+    /// How long `Pool::acquire` waits for a connection, including the
+    /// time needed to establish a new one
+    pub fn acquire_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.acquire_timeout = timeout;
+        self
+    }
+    /// Close connections that are unused for longer than this
+    pub fn idle_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.idle_timeout = timeout;
+        self
+    }
+    pub fn build(&self, params: &Builder) -> Pool {
+        Pool {
+            inner: Arc::new(Inner {
+                params: params.clone(),
+                options: self.clone(),
+                state: Mutex::new(State {
+                    idle: VecDeque::new(),
+                    in_use: 0,
+                    waiters: Vec::new(),
+                    next_waiter: 0,
+                    created: 0,
+                    discarded: 0,
+                    reaped: 0,
+                }),
+            }),
+        }
+    }
+}
+
+impl Default for PoolOptions {
+    fn default() -> PoolOptions {
+        PoolOptions::new()
+    }
+}
+
+impl Pool {
+    /// Pool with default options
+    pub fn new(params: &Builder) -> Pool {
+        PoolOptions::new().build(params)
+    }
+
+    /// Returns an idle connection or opens a new one
+    ///
+    /// Waits for a connection to be released if `max_size` connections are
+    /// already in use, up to the `acquire_timeout`.
+    pub async fn acquire(&self) -> Result<PooledConnection, Error> {
+        let inner = &*self.inner;
+        let acquire = async {
+            let waiter = inner.waiter();
+            let reserved = poll_fn(|cx| {
+                inner.poll_reserve(&waiter, cx.waker())
+            }).await;
+            drop(waiter);
+            match reserved {
+                Ok(conn) => Ok(conn),
+                Err(slot) => inner.open(slot).await,
+            }
+        };
+        let conn = match rt::timeout(inner.options.acquire_timeout, acquire)
+            .await
+        {
+            Ok(res) => res?,
+            Err(_) => {
+                return errors::AcquireTimeout {
+                    timeout: inner.options.acquire_timeout,
+                }.fail();
+            }
+        };
+        Ok(PooledConnection {
+            conn: Some(conn),
+            pool: self.inner.clone(),
+        })
+    }
+
+    /// Opens connections until the pool has at least `min_size` of them
+    ///
+    /// The pool doesn't open connections in background, so this should be
+    /// called after creating the pool, and may be called periodically to
+    /// replace the connections that were closed.
+    pub async fn warm_up(&self) -> Result<(), Error> {
+        let inner = &*self.inner;
+        while let Some(slot) = inner.reserve_extra() {
+            let conn = inner.open(slot).await?;
+            inner.release(Some(conn));
+        }
+        Ok(())
+    }
+
+    /// Closes connections unused for longer than `idle_timeout`
+    ///
+    /// This is done on every acquire and release, so it only needs to be
+    /// called if the pool may stay unused for a long time.
+    pub fn reap_idle(&self) {
+        self.inner.reap(&mut self.inner.lock());
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        let state = self.inner.lock();
+        PoolStats {
+            idle: state.idle.len(),
+            in_use: state.in_use,
+            waiting: state.waiters.len(),
+            created: state.created,
+            discarded: state.discarded,
+            reaped: state.reaped,
+        }
+    }
+}
+
+impl Inner {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // state is always consistent between the operations, so it's fine
+        // to use it even if some other thread panicked
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn waiter(&self) -> Waiter<'_> {
+        let mut state = self.lock();
+        let id = state.next_waiter;
+        state.next_waiter += 1;
+        Waiter { pool: self, id }
+    }
+
+    /// Takes an idle connection or a slot to open a new one
+    fn poll_reserve(&self, waiter: &Waiter, waker: &Waker)
+        -> Poll<Result<Connection, Slot<'_>>>
+    {
+        let mut state = self.lock();
+        self.reap(&mut state);
+        while let Some(mut idle) = state.idle.pop_back() {
+            if idle.conn.is_alive() {
+                state.in_use += 1;
+                return Poll::Ready(Ok(idle.conn));
+            }
+            state.discarded += 1;
+        }
+        if state.in_use < self.options.max_size {
+            state.in_use += 1;
+            return Poll::Ready(Err(Slot { pool: self, armed: true }));
+        }
+        match state.waiters.iter_mut().find(|(id, _)| *id == waiter.id) {
+            Some((_, old)) => *old = waker.clone(),
+            None => state.waiters.push((waiter.id, waker.clone())),
+        }
+        Poll::Pending
+    }
+
+    /// Takes a slot to open a connection if there are fewer than `min_size`
+    fn reserve_extra(&self) -> Option<Slot<'_>> {
+        let mut state = self.lock();
+        let size = min(self.options.min_size, self.options.max_size);
+        if state.idle.len() + state.in_use >= size {
+            return None;
+        }
+        state.in_use += 1;
+        Some(Slot { pool: self, armed: true })
+    }
+
+    async fn open(&self, mut slot: Slot<'_>) -> Result<Connection, Error> {
+        let mut conn = self.params.connect().await?;
+        conn.authenticate().await?;
+        slot.armed = false;
+        self.lock().created += 1;
+        Ok(conn)
+    }
+
+    fn release(&self, conn: Option<Connection>) {
+        let mut state = self.lock();
+        state.in_use -= 1;
+        match conn {
+            Some(conn) if conn.is_idle() => {
+                state.idle.push_back(IdleConnection {
+                    conn,
+                    since: Instant::now(),
+                });
+            }
+            Some(_) => state.discarded += 1,
+            None => {}
+        }
+        self.reap(&mut state);
+        // waiters may be canceled before polling, so wake all of them
+        for (_, waker) in state.waiters.drain(..) {
+            waker.wake();
+        }
+    }
+
+    fn reap(&self, state: &mut State) {
+        let idle_timeout = match self.options.idle_timeout {
+            Some(timeout) => timeout,
+            None => return,
+        };
+        while state.idle.len() + state.in_use > self.options.min_size {
+            match state.idle.front() {
+                Some(idle) if idle.since.elapsed() > idle_timeout => {
+                    state.idle.pop_front();
+                    state.reaped += 1;
+                }
+                _ => break,
+            }
+        }
+    }
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        if self.armed {
+            self.pool.release(None);
+        }
+    }
+}
+
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        let id = self.id;
+        self.pool.lock().waiters.retain(|(waiter, _)| *waiter != id);
+    }
+}
+
+impl PooledConnection {
+    pub fn client(&mut self) -> Client<'_> {
+        Client {
+            conn: self.conn.as_mut().expect("connection is not released"),
+        }
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        self.pool.release(self.conn.take());
+    }
+}
@@ -0,0 +1,831 @@
+//! Building EdgeQL queries without string formatting
+//!
+//! Names are quoted and values are passed as query arguments (`$0`, `$1`,
+//! ...), so neither typos in quoting nor injections are possible. Raw
+//! EdgeQL can still be embedded with `raw`, which should only be used for
+//! the text written in the source code.
+//!
+//! ```rust
+//! use edgedb_client::query_builder::{select, name, field, Shape, Order};
+//!
+//! let query = select(name("default::User"))
+//!     .shape(Shape::new().field("name").field("email"))
+//!     .filter(field("name").eq("alice"))
+//!     .order_by(field("email"), Order::Asc)
+//!     .limit(10)
+//!     .build();
+//! assert_eq!(query.text(), "SELECT default::User { name, email } \
+//!     FILTER .name = <str>$0 ORDER BY .email ASC LIMIT 10");
+//! ```
+//!
+//! Elements of the shape are in the order they are added, so the result
+//! can be decoded into a `#[derive(Queryable)]` struct with the fields in
+//! the same order:
+//!
+//! ```rust,no_run
+//! # use edgedb_client::query_builder::{select, name, Shape};
+//! # #[derive(edgedb_derive::Queryable)]
+//! # struct User { name: String, email: String }
+//! # async fn example(cli: &mut edgedb_client::Client<'_>)
+//! #     -> Result<(), edgedb_client::Error>
+//! # {
+//! let query = select(name("User"))
+//!     .shape(Shape::new().field("name").field("email"))
+//!     .build();
+//! let users = cli.query::<User, _>(query.text(), query.arguments()).await?;
+//! # Ok(())
+//! # }
+//! ```
+use std::borrow::Cow;
+use std::fmt::Write;
+
+use edgeql_parser::helpers::quote_name;
+use uuid::Uuid;
+
+use edgedb_protocol::value::Value;
+
+
+/// Query text with the arguments
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    text: String,
+    arguments: Value,
+}
+
+/// Fragment of EdgeQL with the parameters not numbered yet
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Param { type_name: String, value: Value },
+}
+
+/// Tracks whether the text is inside a quoted name or a string literal
+#[derive(Default)]
+struct Quotes {
+    quote: Option<char>,
+    escaped: bool,
+}
+
+/// Query or command that can be used in the `WITH` block
+///
+/// Statements are also converted into `Expr` (in parenthesis), so they can
+/// be used as subqueries.
+pub trait Statement {
+    /// Statement text without the parenthesis
+    fn statement_expr(&self) -> Expr;
+}
+
+/// Rust type that can be passed as a query argument
+pub trait Param {
+    /// EdgeQL type used in the cast, e.g. `str` for `<str>$0`
+    fn type_name() -> Cow<'static, str>;
+    fn into_value(self) -> Value;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    Asc,
+    Desc,
+}
+
+/// Shape of the selected objects
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Shape {
+    elements: Vec<Element>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Element {
+    Field(String),
+    Link(String, Shape),
+    Computed(String, Expr),
+}
+
+/// `SELECT` statement, created by `select`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Select {
+    expr: Expr,
+    shape: Option<Shape>,
+    filters: Vec<Expr>,
+    order: Vec<(Expr, Order)>,
+    offset: Option<u64>,
+    limit: Option<u64>,
+}
+
+/// `INSERT` statement, created by `insert`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Insert {
+    type_name: Expr,
+    values: Vec<(String, Expr)>,
+}
+
+/// `UPDATE` statement, created by `update`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Update {
+    type_name: Expr,
+    filters: Vec<Expr>,
+    values: Vec<(String, Expr)>,
+}
+
+/// `DELETE` statement, created by `delete`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delete {
+    select: Select,
+}
+
+/// `WITH` block, created by `with`
+#[derive(Debug, Clone, PartialEq)]
+pub struct With {
+    module: Option<String>,
+    aliases: Vec<(String, Expr)>,
+    body: Option<Expr>,
+}
+
+
+/// Selects `expr`, which is usually a type `name`
+pub fn select(expr: impl Into<Expr>) -> Select {
+    Select {
+        expr: expr.into(),
+        shape: None,
+        filters: Vec::new(),
+        order: Vec::new(),
+        offset: None,
+        limit: None,
+    }
+}
+
+/// Inserts an object of the type `type_name`, e.g. `default::User`
+pub fn insert(type_name: &str) -> Insert {
+    Insert {
+        type_name: name(type_name),
+        values: Vec::new(),
+    }
+}
+
+/// Updates objects of the type `type_name`
+pub fn update(type_name: &str) -> Update {
+    Update {
+        type_name: name(type_name),
+        filters: Vec::new(),
+        values: Vec::new(),
+    }
+}
+
+/// Deletes objects of the type `type_name`
+pub fn delete(type_name: &str) -> Delete {
+    Delete {
+        select: select(name(type_name)),
+    }
+}
+
+/// Starts a `WITH` block, the statement is set by `With::body`
+pub fn with() -> With {
+    With {
+        module: None,
+        aliases: Vec::new(),
+        body: None,
+    }
+}
+
+/// Raw EdgeQL, must not contain any user input
+pub fn raw(text: impl Into<String>) -> Expr {
+    Expr { parts: vec![Part::Text(text.into())] }
+}
+
+/// Possibly qualified name of a type, function or alias, quoted if needed
+pub fn name(name: &str) -> Expr {
+    let quoted = name.split("::")
+        .map(quote_name)
+        .collect::<Vec<_>>()
+        .join("::");
+    raw(quoted)
+}
+
+/// Link or property of the current object, i.e. `.name`
+pub fn field(name: &str) -> Expr {
+    raw(format!(".{}", quote_name(name)))
+}
+
+/// Query argument
+pub fn param<P: Param>(value: P) -> Expr {
+    param_typed(&P::type_name(), value.into_value())
+}
+
+/// Query argument with an explicit type, for values that have no `Param`
+/// implementation (e.g. `Value::Datetime`)
+pub fn param_typed(type_name: &str, value: Value) -> Expr {
+    Expr {
+        parts: vec![Part::Param {
+            type_name: type_name.into(),
+            value,
+        }],
+    }
+}
+
+/// Function call, e.g. `call("str_lower", vec![field("name")])`
+pub fn call(function: &str, arguments: Vec<Expr>) -> Expr {
+    let mut expr = name(function);
+    expr.push_text("(");
+    for (idx, argument) in arguments.into_iter().enumerate() {
+        if idx > 0 {
+            expr.push_text(", ");
+        }
+        expr.append(argument);
+    }
+    expr.push_text(")");
+    expr
+}
+
+/// `NOT expr`
+pub fn not(expr: impl Into<Expr>) -> Expr {
+    let mut result = raw("NOT ");
+    result.append_operand(expr.into());
+    result
+}
+
+/// `EXISTS expr`
+pub fn exists(expr: impl Into<Expr>) -> Expr {
+    let mut result = raw("EXISTS ");
+    result.append_operand(expr.into());
+    result
+}
+
+impl Query {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+    /// Arguments as a tuple, to be passed along with the `text` to
+    /// `Client::query` and similar methods
+    pub fn arguments(&self) -> &Value {
+        &self.arguments
+    }
+    pub fn into_parts(self) -> (String, Value) {
+        (self.text, self.arguments)
+    }
+}
+
+impl Expr {
+    pub fn eq(self, other: impl Into<Expr>) -> Expr {
+        self.binary("=", other.into())
+    }
+    pub fn ne(self, other: impl Into<Expr>) -> Expr {
+        self.binary("!=", other.into())
+    }
+    pub fn lt(self, other: impl Into<Expr>) -> Expr {
+        self.binary("<", other.into())
+    }
+    pub fn le(self, other: impl Into<Expr>) -> Expr {
+        self.binary("<=", other.into())
+    }
+    pub fn gt(self, other: impl Into<Expr>) -> Expr {
+        self.binary(">", other.into())
+    }
+    pub fn ge(self, other: impl Into<Expr>) -> Expr {
+        self.binary(">=", other.into())
+    }
+    pub fn and(self, other: impl Into<Expr>) -> Expr {
+        self.binary("AND", other.into())
+    }
+    pub fn or(self, other: impl Into<Expr>) -> Expr {
+        self.binary("OR", other.into())
+    }
+    pub fn like(self, pattern: impl Into<Expr>) -> Expr {
+        self.binary("LIKE", pattern.into())
+    }
+    pub fn ilike(self, pattern: impl Into<Expr>) -> Expr {
+        self.binary("ILIKE", pattern.into())
+    }
+    pub fn in_(self, set: impl Into<Expr>) -> Expr {
+        self.binary("IN", set.into())
+    }
+    /// String or array concatenation, `++`
+    pub fn concat(self, other: impl Into<Expr>) -> Expr {
+        self.binary("++", other.into())
+    }
+    /// Builds the final query, numbering parameters from `$0`
+    pub fn build(&self) -> Query {
+        let mut text = String::new();
+        let mut arguments = Vec::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(chunk) => text.push_str(chunk),
+                Part::Param { type_name, value } => {
+                    write!(text, "<{}>${}", type_name, arguments.len())
+                        .expect("writing to string succeeds");
+                    arguments.push(value.clone());
+                }
+            }
+        }
+        Query { text, arguments: Value::Tuple(arguments) }
+    }
+    fn binary(self, op: &str, other: Expr) -> Expr {
+        let mut result = Expr { parts: Vec::new() };
+        result.append_operand(self);
+        result.push_text(&format!(" {} ", op));
+        result.append_operand(other);
+        result
+    }
+    /// Names, paths and parameters don't need parenthesis as operands
+    fn is_simple(&self) -> bool {
+        match &self.parts[..] {
+            [Part::Param { .. }] => true,
+            [Part::Text(text)] => {
+                let mut quotes = Quotes::default();
+                text.chars().all(|c| {
+                    !quotes.is_code(c) || !c.is_whitespace()
+                })
+            }
+            _ => false,
+        }
+    }
+    fn push_text(&mut self, text: &str) {
+        if let Some(Part::Text(last)) = self.parts.last_mut() {
+            last.push_str(text);
+        } else {
+            self.parts.push(Part::Text(text.into()));
+        }
+    }
+    fn append(&mut self, other: Expr) {
+        for part in other.parts {
+            match part {
+                Part::Text(text) => self.push_text(&text),
+                param => self.parts.push(param),
+            }
+        }
+    }
+    /// Whole expression is in a single pair of parenthesis
+    fn is_parenthesized(&self) -> bool {
+        let mut depth = 0;
+        let mut quotes = Quotes::default();
+        let mut chars = self.parts.iter().flat_map(|part| match part {
+            Part::Text(text) => text.chars().collect(),
+            Part::Param { .. } => vec!['$'],
+        }).peekable();
+        if chars.peek() != Some(&'(') {
+            return false;
+        }
+        while let Some(c) = chars.next() {
+            let code = quotes.is_code(c);
+            match c {
+                '(' if code => depth += 1,
+                ')' if code => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return chars.next().is_none();
+                    }
+                }
+                _ => {}
+            }
+        }
+        false
+    }
+    fn append_operand(&mut self, other: Expr) {
+        if other.is_simple() || other.is_parenthesized() {
+            self.append(other);
+        } else {
+            self.append_nested(other);
+        }
+    }
+    fn append_nested(&mut self, other: Expr) {
+        self.push_text("(");
+        self.append(other);
+        self.push_text(")");
+    }
+}
+
+impl Quotes {
+    /// Returns true if `c` is outside of quotes, quote characters
+    /// themselves are considered quoted
+    fn is_code(&mut self, c: char) -> bool {
+        match self.quote {
+            None => {
+                if c == '`' || c == '\'' || c == '"' {
+                    self.quote = Some(c);
+                    return false;
+                }
+                return true;
+            }
+            Some(_) if self.escaped => self.escaped = false,
+            // names have no escapes, doubled backtick is just two quotes
+            Some('`') if c == '`' => self.quote = None,
+            Some('`') => {}
+            Some(_) if c == '\\' => self.escaped = true,
+            Some(quote) if c == quote => self.quote = None,
+            Some(_) => {}
+        }
+        false
+    }
+}
+
+impl<P: Param> From<P> for Expr {
+    fn from(value: P) -> Expr {
+        param(value)
+    }
+}
+
+impl Shape {
+    pub fn new() -> Shape {
+        Shape::default()
+    }
+    /// Property or link without a subshape
+    pub fn field(&mut self, name: &str) -> &mut Self {
+        self.elements.push(Element::Field(name.into()));
+        self
+    }
+    /// Link with a subshape
+    pub fn link(&mut self, name: &str, shape: &Shape) -> &mut Self {
+        self.elements.push(Element::Link(name.into(), shape.clone()));
+        self
+    }
+    /// Computable, i.e. `name := expr`
+    pub fn computed(&mut self, name: &str, expr: impl Into<Expr>)
+        -> &mut Self
+    {
+        self.elements.push(Element::Computed(name.into(), expr.into()));
+        self
+    }
+    fn render(&self, expr: &mut Expr) {
+        expr.push_text("{ ");
+        for (idx, element) in self.elements.iter().enumerate() {
+            if idx > 0 {
+                expr.push_text(", ");
+            }
+            match element {
+                Element::Field(name) => expr.push_text(&quote_name(name)),
+                Element::Link(name, shape) => {
+                    expr.push_text(&quote_name(name));
+                    expr.push_text(": ");
+                    shape.render(expr);
+                }
+                Element::Computed(name, value) => {
+                    expr.push_text(&quote_name(name));
+                    expr.push_text(" := ");
+                    expr.append_operand(value.clone());
+                }
+            }
+        }
+        expr.push_text(" }");
+    }
+}
+
+impl Select {
+    pub fn shape(&mut self, shape: &Shape) -> &mut Self {
+        self.shape = Some(shape.clone());
+        self
+    }
+    /// Adds a filter, several filters are joined by `AND`
+    pub fn filter(&mut self, expr: impl Into<Expr>) -> &mut Self {
+        self.filters.push(expr.into());
+        self
+    }
+    /// Adds a sort key, several keys are joined by `THEN`
+    pub fn order_by(&mut self, expr: impl Into<Expr>, order: Order)
+        -> &mut Self
+    {
+        self.order.push((expr.into(), order));
+        self
+    }
+    pub fn offset(&mut self, offset: u64) -> &mut Self {
+        self.offset = Some(offset);
+        self
+    }
+    pub fn limit(&mut self, limit: u64) -> &mut Self {
+        self.limit = Some(limit);
+        self
+    }
+    pub fn build(&self) -> Query {
+        self.to_expr().build()
+    }
+    fn to_expr(&self) -> Expr {
+        let mut expr = raw("SELECT ");
+        expr.append_operand(self.expr.clone());
+        if let Some(ref shape) = self.shape {
+            expr.push_text(" ");
+            shape.render(&mut expr);
+        }
+        render_filters(&mut expr, &self.filters);
+        for (idx, (key, order)) in self.order.iter().enumerate() {
+            expr.push_text(if idx == 0 { " ORDER BY " } else { " THEN " });
+            expr.append(key.clone());
+            expr.push_text(match order {
+                Order::Asc => " ASC",
+                Order::Desc => " DESC",
+            });
+        }
+        if let Some(offset) = self.offset {
+            expr.push_text(&format!(" OFFSET {}", offset));
+        }
+        if let Some(limit) = self.limit {
+            expr.push_text(&format!(" LIMIT {}", limit));
+        }
+        expr
+    }
+}
+
+impl Insert {
+    /// Sets property or link `name` to `value`
+    pub fn set(&mut self, name: &str, value: impl Into<Expr>) -> &mut Self {
+        self.values.push((name.into(), value.into()));
+        self
+    }
+    pub fn build(&self) -> Query {
+        self.to_expr().build()
+    }
+    fn to_expr(&self) -> Expr {
+        let mut expr = raw("INSERT ");
+        expr.append(self.type_name.clone());
+        render_values(&mut expr, &self.values);
+        expr
+    }
+}
+
+impl Update {
+    /// Adds a filter, several filters are joined by `AND`
+    pub fn filter(&mut self, expr: impl Into<Expr>) -> &mut Self {
+        self.filters.push(expr.into());
+        self
+    }
+    /// Sets property or link `name` to `value`
+    pub fn set(&mut self, name: &str, value: impl Into<Expr>) -> &mut Self {
+        self.values.push((name.into(), value.into()));
+        self
+    }
+    pub fn build(&self) -> Query {
+        self.to_expr().build()
+    }
+    fn to_expr(&self) -> Expr {
+        let mut expr = raw("UPDATE ");
+        expr.append(self.type_name.clone());
+        render_filters(&mut expr, &self.filters);
+        expr.push_text(" SET");
+        render_values(&mut expr, &self.values);
+        expr
+    }
+}
+
+impl Delete {
+    /// Adds a filter, several filters are joined by `AND`
+    pub fn filter(&mut self, expr: impl Into<Expr>) -> &mut Self {
+        self.select.filter(expr);
+        self
+    }
+    pub fn build(&self) -> Query {
+        self.to_expr().build()
+    }
+    fn to_expr(&self) -> Expr {
+        let mut expr = raw("DELETE ");
+        expr.append_nested(self.select.to_expr());
+        expr
+    }
+}
+
+impl With {
+    /// Default module of the names in the statement
+    pub fn module(&mut self, module: &str) -> &mut Self {
+        self.module = Some(module.into());
+        self
+    }
+    /// Defines `name := expr`, refer to it using `name(name)`
+    pub fn alias(&mut self, name: &str, expr: impl Into<Expr>)
+        -> &mut Self
+    {
+        self.aliases.push((name.into(), expr.into()));
+        self
+    }
+    /// Statement to run with the aliases, e.g. `select(...)`
+    pub fn body<S: Statement>(&mut self, statement: &S) -> &mut Self {
+        self.body = Some(statement.statement_expr());
+        self
+    }
+    pub fn build(&self) -> Query {
+        self.to_expr().build()
+    }
+    fn to_expr(&self) -> Expr {
+        let mut expr = raw("WITH ");
+        let mut first = true;
+        if let Some(ref module) = self.module {
+            expr.push_text("MODULE ");
+            expr.append(name(module));
+            first = false;
+        }
+        for (alias, value) in &self.aliases {
+            if !first {
+                expr.push_text(", ");
+            }
+            first = false;
+            expr.push_text(&quote_name(alias));
+            expr.push_text(" := ");
+            expr.append_operand(value.clone());
+        }
+        expr.push_text(" ");
+        match self.body {
+            Some(ref body) => expr.append(body.clone()),
+            None => expr.push_text("SELECT {}"),
+        }
+        expr
+    }
+}
+
+fn render_filters(expr: &mut Expr, filters: &[Expr]) {
+    for (idx, filter) in filters.iter().enumerate() {
+        expr.push_text(if idx == 0 { " FILTER " } else { " AND " });
+        if filters.len() > 1 {
+            expr.append_operand(filter.clone());
+        } else {
+            expr.append(filter.clone());
+        }
+    }
+}
+
+fn render_values(expr: &mut Expr, values: &[(String, Expr)]) {
+    expr.push_text(" {");
+    for (idx, (name, value)) in values.iter().enumerate() {
+        expr.push_text(if idx == 0 { " " } else { ", " });
+        expr.push_text(&quote_name(name));
+        expr.push_text(" := ");
+        expr.append_operand(value.clone());
+    }
+    expr.push_text(if values.is_empty() { "}" } else { " }" });
+}
+
+macro_rules! statement_expr {
+    ($($typ:ident),*) => {$(
+        impl Statement for $typ {
+            fn statement_expr(&self) -> Expr {
+                self.to_expr()
+            }
+        }
+        impl From<&$typ> for Expr {
+            fn from(statement: &$typ) -> Expr {
+                let mut expr = Expr { parts: Vec::new() };
+                expr.append_nested(statement.to_expr());
+                expr
+            }
+        }
+        impl From<&mut $typ> for Expr {
+            fn from(statement: &mut $typ) -> Expr {
+                Expr::from(&*statement)
+            }
+        }
+        impl From<$typ> for Expr {
+            fn from(statement: $typ) -> Expr {
+                Expr::from(&statement)
+            }
+        }
+    )*}
+}
+
+statement_expr!(Select, Insert, Update, Delete, With);
+
+macro_rules! scalar_param {
+    ($($typ:ty => $type_name:expr, $variant:ident;)*) => {$(
+        impl Param for $typ {
+            fn type_name() -> Cow<'static, str> {
+                $type_name.into()
+            }
+            fn into_value(self) -> Value {
+                Value::$variant(self.into())
+            }
+        }
+    )*}
+}
+
+scalar_param! {
+    String => "str", Str;
+    &str => "str", Str;
+    i16 => "int16", Int16;
+    i32 => "int32", Int32;
+    i64 => "int64", Int64;
+    f32 => "float32", Float32;
+    f64 => "float64", Float64;
+    bool => "bool", Bool;
+    Uuid => "uuid", Uuid;
+}
+
+impl<T: Param> Param for Vec<T> {
+    fn type_name() -> Cow<'static, str> {
+        format!("array<{}>", T::type_name()).into()
+    }
+    fn into_value(self) -> Value {
+        Value::Array(self.into_iter().map(Param::into_value).collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use edgedb_protocol::value::Value;
+
+    use super::{select, insert, update, delete, with};
+    use super::{name, field, param, raw, call, not, Shape, Order};
+
+    #[test]
+    fn select_shape() {
+        let query = select(name("schema::ObjectType"))
+            .shape(Shape::new()
+                .field("name")
+                .computed("extending", call("to_str", vec![
+                    call("array_agg", vec![raw(".ancestors.name")]),
+                    param(", "),
+                ]))
+                .link("links", Shape::new().field("name")))
+            .filter(not(field("is_from_alias")))
+            .filter(call("re_test", vec![param("^std::"), field("name")]))
+            .order_by(field("name"), Order::Asc)
+            .build();
+        assert_eq!(query.text(), "SELECT schema::ObjectType { name, \
+            `extending` := (to_str(array_agg(.ancestors.name), <str>$0)), \
+            links: { name } } \
+            FILTER (NOT .is_from_alias) \
+            AND (re_test(<str>$1, .name)) \
+            ORDER BY .name ASC");
+        assert_eq!(query.arguments(), &Value::Tuple(vec![
+            Value::Str(", ".into()),
+            Value::Str("^std::".into()),
+        ]));
+    }
+
+    #[test]
+    fn quoting() {
+        let query = select(name("default::select"))
+            .filter(field("weird name").eq("x' OR true"))
+            .build();
+        assert_eq!(query.text(),
+            "SELECT default::`select` FILTER .`weird name` = <str>$0");
+        assert_eq!(query.arguments(), &Value::Tuple(vec![
+            Value::Str("x' OR true".into()),
+        ]));
+    }
+
+    #[test]
+    fn quoted_strings() {
+        let expr = field("name").eq(raw("'a b'"));
+        assert_eq!(expr.build().text(), ".name = 'a b'");
+        let expr = not(raw(r#"('(' ++ "\")") ++ (')')"#));
+        assert_eq!(expr.build().text(),
+            r#"NOT (('(' ++ "\")") ++ (')'))"#);
+    }
+
+    #[test]
+    fn operators() {
+        let query = select(name("User"))
+            .filter(field("age").ge(18i64).and(field("tags").eq(
+                vec![String::from("a")])))
+            .offset(20)
+            .limit(10)
+            .build();
+        assert_eq!(query.text(), "SELECT User \
+            FILTER (.age >= <int64>$0) AND (.tags = <array<str>>$1) \
+            OFFSET 20 LIMIT 10");
+    }
+
+    #[test]
+    fn insert_nested() {
+        let query = insert("Post")
+            .set("title", "hello")
+            .set("author", select(name("User"))
+                 .filter(field("id").eq(uuid::Uuid::from_u128(1)))
+                 .limit(1))
+            .build();
+        assert_eq!(query.text(), "INSERT Post { title := <str>$0, \
+            author := (SELECT User FILTER .id = <uuid>$1 LIMIT 1) }");
+        assert_eq!(query.arguments(), &Value::Tuple(vec![
+            Value::Str("hello".into()),
+            Value::Uuid(uuid::Uuid::from_u128(1)),
+        ]));
+    }
+
+    #[test]
+    fn update_delete() {
+        let query = update("User")
+            .filter(field("name").eq("alice"))
+            .set("age", 31i64)
+            .build();
+        assert_eq!(query.text(),
+            "UPDATE User FILTER .name = <str>$0 SET { age := <int64>$1 }");
+        let query = delete("User")
+            .filter(field("name").eq("alice"))
+            .build();
+        assert_eq!(query.text(),
+            "DELETE (SELECT User FILTER .name = <str>$0)");
+    }
+
+    #[test]
+    fn with_block() {
+        let query = with()
+            .module("schema")
+            .alias("names", select(field("name")).limit(5))
+            .body(select(name("names")).filter(name("names").ne("x")))
+            .build();
+        assert_eq!(query.text(), "WITH MODULE schema, \
+            names := (SELECT .name LIMIT 5) \
+            SELECT names FILTER names != <str>$0");
+        assert_eq!(query.arguments(), &Value::Tuple(vec![
+            Value::Str("x".into()),
+        ]));
+    }
+}
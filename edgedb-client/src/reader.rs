@@ -0,0 +1,527 @@
+use std::io;
+use std::cmp::{min, max};
+use std::convert::TryInto;
+use std::future::{Future};
+use std::marker::PhantomData;
+use std::mem::transmute;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Poll, Context};
+use std::time::Duration;
+
+use bytes::{Bytes, BytesMut, BufMut};
+use futures_util::io::AsyncRead;
+use futures_util::stream::Stream;
+use futures_util::task::noop_waker_ref;
+use snafu::{Snafu, ResultExt, Backtrace};
+
+use edgedb_protocol::server_message::{ServerMessage, ErrorResponse};
+use edgedb_protocol::server_message::{TransactionState, LogMessage};
+use edgedb_protocol::server_message::Cardinality;
+use edgedb_protocol::errors::{DecodeError};
+use edgedb_protocol::queryable::Queryable;
+use edgedb_protocol::codec::Codec;
+use edgedb_protocol::value::Value;
+
+use crate::cancel::CancelHandle;
+use crate::capture::{ConnectionRecorder, Direction};
+use crate::instrument::Operation;
+use crate::rt;
+
+const BUFFER_SIZE: usize = 8192;
+const MAX_BUFFER: usize = 1_048_576;
+
+/// Callback for the log messages, see `Builder::on_log_message`
+pub type LogHandler = Arc<dyn Fn(&LogMessage) + Send + Sync>;
+
+pub struct Reader<T> {
+    stream: T,
+    buf: BytesMut,
+    transaction_state: Option<TransactionState>,
+    /// Number of `ReadyForCommand` messages requested but not received yet
+    pending_ready: usize,
+    pending: Option<ServerMessage>,
+    timeout: Option<Duration>,
+    /// Fires when the current request takes longer than `timeout`
+    deadline: Option<Pin<Box<dyn Future<Output=()> + Send>>>,
+    cancel: Option<CancelHandle>,
+    /// Cancellation of the timed out request, timeout is reported when
+    /// it's complete
+    cancelling: Option<Pin<Box<dyn Future<Output=()> + Send>>>,
+    log_handler: Option<LogHandler>,
+    /// Reported when the current request is complete
+    operation: Option<Operation>,
+    recorder: Option<ConnectionRecorder>,
+}
+
+pub struct MessageFuture<'a, T> {
+    reader: &'a mut Reader<T>,
+}
+
+pub struct QueryResponse<'a, T, D> {
+    reader: &'a mut Reader<T>,
+    result_cardinality: Cardinality,
+    complete: bool,
+    buffer: Vec<Bytes>,
+    decoder: D,
+}
+
+impl<T, D> Unpin for QueryResponse<'_, T, D> where T: Unpin {}
+
+#[derive(Debug, Snafu)]
+#[non_exhaustive]
+pub enum ReadError {
+    #[snafu(display("error decoding message: {}", source))]
+    DecodeErr { source: DecodeError },
+    #[snafu(display("error reading data: {}", source))]
+    Io { source: io::Error },
+    #[snafu(display("server message out of order: {:?}", message))]
+    OutOfOrder { message: ServerMessage, backtrace: Backtrace },
+    #[snafu(display("request error: {}", error))]
+    RequestError { error: ErrorResponse, backtrace: Backtrace },
+    #[snafu(display("end of stream"))]
+    Eos,
+    #[snafu(display("no response from server in {:?}", timeout))]
+    Timeout { timeout: Duration },
+}
+
+impl ReadError {
+    /// Returns true if the connection is closed or broken
+    pub fn is_disconnect(&self) -> bool {
+        match self {
+            ReadError::Io { .. } | ReadError::Eos => true,
+            _ => false,
+        }
+    }
+}
+
+pub trait Decode {
+    type Output;
+    fn decode(&self, msg: Bytes) -> Result<Self::Output, DecodeError>;
+}
+
+pub struct QueryableDecoder<T>(PhantomData<*const T>);
+
+unsafe impl<T> Send for QueryableDecoder<T> {}
+
+impl<T> QueryableDecoder<T> {
+    pub fn new() -> QueryableDecoder<T> {
+        QueryableDecoder(PhantomData)
+    }
+}
+
+impl<T: Queryable> Decode for QueryableDecoder<T> {
+    type Output = T;
+    fn decode(&self, msg: Bytes) -> Result<T, DecodeError> {
+        Queryable::decode(&mut io::Cursor::new(msg))
+    }
+}
+
+impl Decode for Arc<dyn Codec> {
+    type Output = Value;
+    fn decode(&self, msg: Bytes) -> Result<Self::Output, DecodeError> {
+        self.decode_value(&mut io::Cursor::new(msg))
+    }
+}
+
+
+impl<T: AsyncRead + Unpin> Reader<T> {
+    pub fn new(stream: T) -> Reader<T> {
+        return Reader {
+            stream,
+            buf: BytesMut::with_capacity(BUFFER_SIZE),
+            transaction_state: None,
+            pending_ready: 0,
+            pending: None,
+            timeout: None,
+            deadline: None,
+            cancel: None,
+            cancelling: None,
+            log_handler: None,
+            operation: None,
+            recorder: None,
+        }
+    }
+    /// State reported by the last `ReadyForCommand` message
+    ///
+    /// Returns `None` if the request is in progress or reading has failed,
+    /// i.e. if the connection is not ready for the next command.
+    pub fn transaction_state(&self) -> Option<TransactionState> {
+        self.transaction_state
+    }
+    /// Marks the connection as busy until `ready_messages` more
+    /// `ReadyForCommand` messages are received
+    ///
+    /// Pipelined requests are answered by one `ReadyForCommand` each, so
+    /// the connection is ready only after the last one.
+    pub(crate) fn start_request(&mut self, ready_messages: usize) {
+        self.transaction_state = None;
+        self.pending_ready += ready_messages;
+        self.deadline = self.timeout
+            .map(|timeout| Box::pin(rt::sleep(timeout)) as Pin<Box<_>>);
+    }
+    /// Limits the time of each request, query is cancelled using `cancel`
+    /// handle when the time is out
+    pub(crate) fn set_timeout(&mut self, timeout: Option<Duration>,
+        cancel: Option<CancelHandle>)
+    {
+        self.timeout = timeout;
+        self.cancel = cancel;
+    }
+    /// Log messages can arrive at any point of the response, they are
+    /// passed to the `handler` and never returned by `message()`
+    pub(crate) fn set_log_handler(&mut self, handler: Option<LogHandler>) {
+        self.log_handler = handler;
+    }
+    /// Counts rows and errors of the request started last, `operation` is
+    /// reported when the connection is ready for the next command
+    pub(crate) fn start_operation(&mut self, operation: Operation) {
+        self.operation = Some(operation);
+    }
+    /// Writes every received frame to the capture
+    pub(crate) fn set_recorder(&mut self,
+        recorder: Option<ConnectionRecorder>)
+    {
+        self.recorder = recorder;
+    }
+    /// Returns the message back, so it's read again by the next call
+    pub(crate) fn push_back(&mut self, message: ServerMessage) {
+        debug_assert!(self.pending.is_none());
+        self.pending = Some(message);
+    }
+    pub fn message(&mut self) -> MessageFuture<T> {
+        MessageFuture {
+            reader: self,
+        }
+    }
+    pub fn response<D: Decode>(&mut self, decoder: D,
+        result_cardinality: Cardinality)
+        -> QueryResponse<T, D>
+    {
+        QueryResponse {
+            reader: self,
+            result_cardinality,
+            buffer: Vec::new(),
+            complete: false,
+            decoder,
+        }
+    }
+    /// Checks without waiting that the stream of the idle connection is
+    /// not closed and the server hasn't sent anything
+    ///
+    /// Server may send an error before closing the idle connection, so any
+    /// message (except log messages) means connection is not usable.
+    pub(crate) fn is_alive(&mut self) -> bool {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        self.poll_message(&mut cx).is_pending()
+    }
+    pub async fn wait_ready(&mut self) -> Result<(), ReadError> {
+        loop {
+            let msg = self.message().await?;
+            match msg {
+                ServerMessage::ReadyForCommand(..) => return Ok(()),
+                _ => {},
+            }
+        }
+    }
+    fn poll_message(&mut self, cx: &mut Context)
+        -> Poll<Result<ServerMessage, ReadError>>
+    {
+        if let Some(message) = self.pending.take() {
+            return Poll::Ready(Ok(message));
+        }
+        if self.cancelling.is_some() {
+            return self.poll_cancelling(cx);
+        }
+        let result = loop {
+            match self.poll_frame(cx) {
+                Poll::Ready(Ok(ServerMessage::LogMessage(msg))) => {
+                    if let Some(ref handler) = self.log_handler {
+                        handler(&msg);
+                    }
+                }
+                result => break result,
+            }
+        };
+        match result {
+            Poll::Ready(Ok(ServerMessage::ReadyForCommand(ref ready))) => {
+                self.pending_ready = self.pending_ready.saturating_sub(1);
+                if self.pending_ready == 0 {
+                    self.transaction_state = Some(ready.transaction_state);
+                    self.deadline = None;
+                    self.operation = None;
+                }
+            }
+            Poll::Ready(Ok(ServerMessage::Data(ref data))) => {
+                if let Some(ref mut operation) = self.operation {
+                    operation.add_rows(data.data.len());
+                }
+            }
+            Poll::Ready(Ok(ServerMessage::ErrorResponse(_))) => {
+                if let Some(ref mut operation) = self.operation {
+                    operation.fail();
+                }
+            }
+            Poll::Ready(Err(_)) => {
+                self.transaction_state = None;
+                if let Some(mut operation) = self.operation.take() {
+                    operation.fail();
+                }
+            }
+            Poll::Ready(Ok(_)) => {}
+            Poll::Pending => return self.poll_deadline(cx),
+        }
+        result
+    }
+    fn poll_deadline(&mut self, cx: &mut Context)
+        -> Poll<Result<ServerMessage, ReadError>>
+    {
+        let fired = match self.deadline {
+            Some(ref mut deadline) => deadline.as_mut().poll(cx).is_ready(),
+            None => false,
+        };
+        if !fired {
+            return Poll::Pending;
+        }
+        if let Some(mut operation) = self.operation.take() {
+            operation.fail();
+        }
+        match self.cancel.clone() {
+            Some(handle) => {
+                self.cancelling = Some(Box::pin(async move {
+                    // cancel error isn't interesting, timeout is reported
+                    // anyway
+                    handle.cancel().await.ok();
+                }));
+                self.poll_cancelling(cx)
+            }
+            None => Poll::Ready(Err(self.timed_out())),
+        }
+    }
+    fn timed_out(&mut self) -> ReadError {
+        let timeout = self.timeout.expect("deadline is set with timeout");
+        // give the server some time to respond to cancellation, so
+        // `wait_ready` can make connection usable again
+        self.deadline = Some(Box::pin(rt::sleep(timeout)));
+        ReadError::Timeout { timeout }
+    }
+    /// Cancel request is sent before the timeout is returned rather than
+    /// spawned, so it's sent even if the connection is dropped after the
+    /// error, and it works with the runtime of `blocking::Connection`,
+    /// which only runs within `block_on`
+    ///
+    /// Cancellation is limited by the connect timeout.
+    fn poll_cancelling(&mut self, cx: &mut Context)
+        -> Poll<Result<ServerMessage, ReadError>>
+    {
+        let cancelling = self.cancelling.as_mut()
+            .expect("cancellation is in progress");
+        match cancelling.as_mut().poll(cx) {
+            Poll::Ready(()) => {
+                self.cancelling = None;
+                Poll::Ready(Err(self.timed_out()))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+    fn poll_frame(&mut self, cx: &mut Context)
+        -> Poll<Result<ServerMessage, ReadError>>
+    {
+        let Reader { ref mut buf, ref mut stream, .. } = self;
+        let frame_len = loop {
+            let mut next_read = BUFFER_SIZE;
+            let buf_len = buf.len();
+            if buf_len > 5 {
+                let len = u32::from_be_bytes(
+                    buf[1..5].try_into().unwrap())
+                    as usize;
+                if buf_len >= len + 1 {
+                    break len+1;
+                }
+                next_read = max(min(len + 1 - buf_len, MAX_BUFFER),
+                                BUFFER_SIZE);
+                debug_assert!(next_read > 0);
+            }
+
+            buf.reserve(next_read);
+            unsafe {
+                // this is save because the underlying ByteStream always
+                // initializes read bytes
+                let dest: &mut [u8] = transmute(buf.bytes_mut());
+                match Pin::new(&mut *stream).poll_read(cx, dest) {
+                    Poll::Ready(Ok(0)) => {
+                        return Poll::Ready(Err(ReadError::Eos));
+                    }
+                    Poll::Ready(Ok(bytes)) => {
+                        buf.advance_mut(bytes);
+                        continue;
+                    }
+                    Poll::Ready(r @ Err(_)) => { r.context(Io)?; }
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+        };
+        let frame = buf.split_to(frame_len).freeze();
+        if let Some(ref recorder) = self.recorder {
+            recorder.record(Direction::Received, &frame);
+        }
+        let result = ServerMessage::decode(&frame).context(DecodeErr)?;
+        return Poll::Ready(Ok(result));
+    }
+}
+
+impl<'a, T> Future for MessageFuture<'a, T>
+    where T: AsyncRead + Unpin,
+{
+    type Output = Result<ServerMessage, ReadError>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        self.reader.poll_message(cx)
+    }
+}
+
+impl<T, D> QueryResponse<'_, T, D> {
+    /// Cardinality of the result reported by the server on prepare
+    pub fn result_cardinality(&self) -> Cardinality {
+        self.result_cardinality
+    }
+}
+
+impl<'a, T, D> Stream for QueryResponse<'a, T, D>
+    where T: AsyncRead + Unpin,
+          D: Decode,
+{
+    type Item = Result<D::Output, ReadError>;
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context)
+        -> Poll<Option<Self::Item>>
+    {
+        let QueryResponse {
+            ref mut buffer,
+            ref mut complete,
+            ref mut reader,
+            ref decoder,
+            ..
+        } = *self;
+        while buffer.len() == 0 {
+            match reader.poll_message(cx) {
+                Poll::Ready(Ok(ServerMessage::Data(data))) => {
+                    if *complete {
+                        return
+                            OutOfOrder { message: ServerMessage::Data(data) }
+                            .fail()?;
+                    }
+                    buffer.extend(data.data.into_iter().rev());
+                }
+                Poll::Ready(Ok(m @ ServerMessage::CommandComplete(_))) => {
+                    if *complete {
+                        OutOfOrder { message: m }.fail()?;
+                    }
+                    *complete = true;
+                }
+                Poll::Ready(Ok(m @ ServerMessage::ReadyForCommand(_))) => {
+                    if !*complete {
+                        OutOfOrder { message: m }.fail()?;
+                    }
+                    return Poll::Ready(None);
+                }
+                Poll::Ready(Ok(ServerMessage::ErrorResponse(e))) => {
+                    return Poll::Ready(Some(RequestError { error: e }.fail()));
+                }
+                Poll::Ready(Ok(message)) => {
+                    OutOfOrder { message }.fail()?;
+                }
+                Poll::Ready(Err(e)) => {
+                    return Poll::Ready(Some(Err(e)));
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        let chunk = buffer.pop().unwrap();
+        Poll::Ready(Some(decoder.decode(chunk).context(DecodeErr)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::io;
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
+    use std::task::{Poll, Context};
+    use std::time::Duration;
+
+    use bytes::BytesMut;
+    use futures_util::io::{Cursor, AsyncRead};
+
+    use edgedb_protocol::server_message::{ServerMessage, ReadyForCommand};
+    use edgedb_protocol::server_message::TransactionState;
+    use edgedb_protocol::server_message::{LogMessage, MessageSeverity};
+
+    use crate::rt;
+    use super::{Reader, ReadError};
+
+    /// Stream that never returns any data
+    struct Stalled;
+
+    impl AsyncRead for Stalled {
+        fn poll_read(self: Pin<&mut Self>, _cx: &mut Context, _buf: &mut [u8])
+            -> Poll<io::Result<usize>>
+        {
+            Poll::Pending
+        }
+    }
+
+    fn ready() -> ServerMessage {
+        ServerMessage::ReadyForCommand(ReadyForCommand {
+            headers: HashMap::new(),
+            transaction_state: TransactionState::NotInTransaction,
+        })
+    }
+
+    #[test]
+    fn pipelined_ready() {
+        let mut buf = BytesMut::new();
+        ready().encode(&mut buf).unwrap();
+        ready().encode(&mut buf).unwrap();
+        let mut reader = Reader::new(Cursor::new(buf.to_vec()));
+        reader.start_request(2);
+        rt::block_on(reader.wait_ready()).unwrap();
+        assert_eq!(reader.transaction_state(), None);
+        rt::block_on(reader.wait_ready()).unwrap();
+        assert_eq!(reader.transaction_state(),
+                   Some(TransactionState::NotInTransaction));
+    }
+
+    #[test]
+    fn timeout() {
+        let mut reader = Reader::new(Stalled);
+        reader.set_timeout(Some(Duration::from_millis(10)), None);
+        reader.start_request(1);
+        match rt::block_on(reader.wait_ready()) {
+            Err(ReadError::Timeout { .. }) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert_eq!(reader.transaction_state(), None);
+    }
+
+    #[test]
+    fn log_message() {
+        let msg = LogMessage {
+            severity: MessageSeverity::Warning,
+            code: 0xF0_00_00_00,
+            text: "deprecated syntax".into(),
+            attributes: HashMap::new(),
+        };
+        let mut buf = BytesMut::new();
+        ServerMessage::LogMessage(msg.clone()).encode(&mut buf).unwrap();
+        ready().encode(&mut buf).unwrap();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let mut reader = Reader::new(Cursor::new(buf.to_vec()));
+        let handler_received = received.clone();
+        reader.set_log_handler(Some(Arc::new(move |msg: &LogMessage| {
+            handler_received.lock().unwrap().push(msg.clone());
+        })));
+        reader.start_request(1);
+        assert_eq!(rt::block_on(reader.message()).unwrap(), ready());
+        assert_eq!(*received.lock().unwrap(), vec![msg]);
+    }
+}
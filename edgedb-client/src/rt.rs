@@ -0,0 +1,142 @@
+//! Runtime-specific parts: sockets, timers and files
+//!
+//! Everything else in the crate is runtime-independent. Exactly one of the
+//! `rt-async-std` (default) and `rt-tokio` features must be enabled.
+use std::future::Future;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::stream::Stream;
+
+
+#[cfg(all(feature="rt-async-std", feature="rt-tokio"))]
+compile_error!("features `rt-async-std` and `rt-tokio` are exclusive");
+
+#[cfg(not(any(feature="rt-async-std", feature="rt-tokio")))]
+compile_error!("either `rt-async-std` or `rt-tokio` feature is required");
+
+
+/// The future passed to `timeout` has not completed in time
+#[derive(Debug)]
+pub(crate) struct TimedOut;
+
+
+#[cfg(feature="rt-async-std")]
+mod imp {
+    use super::*;
+
+    pub(crate) async fn sleep(duration: Duration) {
+        async_std::task::sleep(duration).await
+    }
+
+    pub(crate) async fn timeout<F: Future>(duration: Duration, f: F)
+        -> Result<F::Output, TimedOut>
+    {
+        async_std::future::timeout(duration, f).await.map_err(|_| TimedOut)
+    }
+
+    pub(crate) async fn read_to_string(path: &Path) -> io::Result<String> {
+        async_std::fs::read_to_string(path).await
+    }
+
+    pub(crate) async fn is_dir(path: &Path) -> bool {
+        async_std::fs::metadata(path).await
+            .map(|meta| meta.is_dir()).unwrap_or(false)
+    }
+
+    pub(crate) async fn connect_tcp(host: &str, port: u16)
+        -> io::Result<Stream>
+    {
+        use async_std::net::TcpStream;
+
+        Ok(TcpStream::connect((host, port)).await?.into())
+    }
+
+    #[cfg(unix)]
+    pub(crate) async fn connect_unix(path: &Path) -> io::Result<Stream> {
+        use async_std::os::unix::net::UnixStream;
+
+        Ok(UnixStream::connect(path).await?.into())
+    }
+
+    pub(crate) struct Runtime;
+
+    impl Runtime {
+        pub(crate) fn new() -> io::Result<Runtime> {
+            Ok(Runtime)
+        }
+        pub(crate) fn block_on<F: Future>(&mut self, f: F) -> F::Output {
+            async_std::task::block_on(f)
+        }
+    }
+}
+
+#[cfg(feature="rt-tokio")]
+mod imp {
+    use super::*;
+
+    pub(crate) async fn sleep(duration: Duration) {
+        tokio::time::delay_for(duration).await
+    }
+
+    pub(crate) async fn timeout<F: Future>(duration: Duration, f: F)
+        -> Result<F::Output, TimedOut>
+    {
+        tokio::time::timeout(duration, f).await.map_err(|_| TimedOut)
+    }
+
+    pub(crate) async fn read_to_string(path: &Path) -> io::Result<String> {
+        tokio::fs::read_to_string(path).await
+    }
+
+    pub(crate) async fn is_dir(path: &Path) -> bool {
+        tokio::fs::metadata(path).await
+            .map(|meta| meta.is_dir()).unwrap_or(false)
+    }
+
+    pub(crate) async fn connect_tcp(host: &str, port: u16)
+        -> io::Result<Stream>
+    {
+        use tokio::net::TcpStream;
+
+        Ok(TcpStream::connect((host, port)).await?.into())
+    }
+
+    #[cfg(unix)]
+    pub(crate) async fn connect_unix(path: &Path) -> io::Result<Stream> {
+        use tokio::net::UnixStream;
+
+        Ok(UnixStream::connect(path).await?.into())
+    }
+
+    /// Sockets are bound to the runtime they were created in, so the
+    /// same runtime must be used for the whole life of the connection
+    pub(crate) struct Runtime(tokio::runtime::Runtime);
+
+    impl Runtime {
+        pub(crate) fn new() -> io::Result<Runtime> {
+            tokio::runtime::Builder::new()
+                .basic_scheduler()
+                .enable_all()
+                .build()
+                .map(Runtime)
+        }
+        pub(crate) fn block_on<F: Future>(&mut self, f: F) -> F::Output {
+            self.0.block_on(f)
+        }
+    }
+}
+
+pub(crate) use imp::*;
+
+#[cfg(test)]
+pub(crate) fn block_on<F: Future>(f: F) -> F::Output {
+    Runtime::new().expect("can create runtime").block_on(f)
+}
+
+#[cfg(not(unix))]
+pub(crate) async fn connect_unix(_path: &Path) -> io::Result<Stream> {
+    Err(io::Error::new(io::ErrorKind::Other,
+        "unix sockets are not supported on this platform"))
+}
@@ -0,0 +1,208 @@
+//! Per-connection cache of prepared statements
+//!
+//! Protocol has no message to close a prepared statement, so evicted
+//! statements are just forgotten. Server has a limit on the number of
+//! statements per connection anyway.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use snafu::ResultExt;
+
+use edgedb_protocol::client_message::{IoFormat, Cardinality};
+use edgedb_protocol::codec::Codec;
+use edgedb_protocol::descriptors::{InputTypedesc, OutputTypedesc};
+use edgedb_protocol::server_message::{CommandDataDescription, ErrorResponse};
+
+use crate::errors::{self, Error};
+
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct Key {
+    pub query: String,
+    pub io_format: IoFormat,
+    pub cardinality: Cardinality,
+}
+
+pub(crate) struct Statement {
+    pub name: Bytes,
+    pub cardinality: Cardinality,
+    pub output: OutputTypedesc,
+    pub input: InputTypedesc,
+    pub input_codec: Arc<dyn Codec>,
+}
+
+pub(crate) struct StatementCache {
+    capacity: usize,
+    statements: HashMap<Key, Entry>,
+    tick: u64,
+    next_id: u64,
+}
+
+struct Entry {
+    statement: Arc<Statement>,
+    last_used: u64,
+}
+
+
+/// Status of the commands after which cached statements may be stale
+///
+/// Besides schema changes, this includes session settings (the same query
+/// text may refer to other objects after `SET MODULE` or `SET ALIAS`) and
+/// rollbacks, which may undo the DDL done in a transaction.
+const INVALIDATING: &[&[u8]] = &[
+    b"CREATE", b"ALTER", b"DROP", b"COMMIT MIGRATION",
+    b"SET", b"RESET", b"CONFIGURE SESSION", b"ROLLBACK",
+];
+
+pub(crate) fn invalidates_cache(status: &[u8]) -> bool {
+    INVALIDATING.iter().any(|prefix| status.starts_with(prefix))
+}
+
+/// Server doesn't know the statement or its type descriptors anymore
+pub(crate) fn is_stale(error: &ErrorResponse) -> bool {
+    // BinaryProtocolError and its subclasses, other protocol errors like
+    // cardinality mismatch or invalid input are not fixed by preparing again
+    error.code & 0xFF_FF_00_00 == 0x03_01_00_00
+}
+
+impl Statement {
+    pub fn new(name: Bytes, description: &CommandDataDescription)
+        -> Result<Statement, Error>
+    {
+        let output = description.output().context(errors::Descriptor)?;
+        let input = description.input().context(errors::Descriptor)?;
+        let input_codec = input.build_codec().context(errors::Codec)?;
+        Ok(Statement {
+            name,
+            cardinality: description.result_cardinality,
+            output, input, input_codec,
+        })
+    }
+}
+
+impl StatementCache {
+    pub fn new(capacity: usize) -> StatementCache {
+        StatementCache {
+            capacity,
+            statements: HashMap::new(),
+            tick: 0,
+            next_id: 0,
+        }
+    }
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+    pub fn get(&mut self, key: &Key) -> Option<Arc<Statement>> {
+        self.tick += 1;
+        let tick = self.tick;
+        self.statements.get_mut(key).map(|entry| {
+            entry.last_used = tick;
+            entry.statement.clone()
+        })
+    }
+    /// Returns unique name for the next statement
+    pub fn next_name(&mut self) -> Bytes {
+        self.next_id += 1;
+        Bytes::from(format!("edgedb_client_{}", self.next_id))
+    }
+    pub fn insert(&mut self, key: Key, statement: Arc<Statement>) {
+        if !self.is_enabled() {
+            return;
+        }
+        if self.statements.len() >= self.capacity &&
+            !self.statements.contains_key(&key)
+        {
+            let oldest = self.statements.iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.statements.remove(&oldest);
+            }
+        }
+        self.tick += 1;
+        self.statements.insert(key, Entry {
+            statement,
+            last_used: self.tick,
+        });
+    }
+    pub fn remove(&mut self, key: &Key) {
+        self.statements.remove(key);
+    }
+    pub fn clear(&mut self) {
+        self.statements.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+    use edgedb_protocol::client_message::{IoFormat, Cardinality};
+    use edgedb_protocol::server_message::CommandDataDescription;
+
+    use super::{Key, Statement, StatementCache, invalidates_cache};
+
+    fn key(query: &str) -> Key {
+        Key {
+            query: query.into(),
+            io_format: IoFormat::Binary,
+            cardinality: Cardinality::Many,
+        }
+    }
+
+    fn statement(cache: &mut StatementCache) -> Arc<Statement> {
+        // empty tuple as an input and no output
+        let desc = CommandDataDescription {
+            headers: Default::default(),
+            result_cardinality: Cardinality::NoResult,
+            input_typedesc_id: "00000000-0000-0000-0000-0000000000FF"
+                .parse().unwrap(),
+            input_typedesc: Bytes::from_static(
+                b"\x04\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\xFF\0\0"),
+            output_typedesc_id: "00000000-0000-0000-0000-000000000000"
+                .parse().unwrap(),
+            output_typedesc: Bytes::new(),
+        };
+        Arc::new(Statement::new(cache.next_name(), &desc).unwrap())
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = StatementCache::new(2);
+        let a = statement(&mut cache);
+        let b = statement(&mut cache);
+        let c = statement(&mut cache);
+        cache.insert(key("a"), a);
+        cache.insert(key("b"), b);
+        assert!(cache.get(&key("a")).is_some());
+        cache.insert(key("c"), c);
+        assert!(cache.get(&key("a")).is_some());
+        assert!(cache.get(&key("b")).is_none());
+        assert!(cache.get(&key("c")).is_some());
+    }
+
+    #[test]
+    fn disabled() {
+        let mut cache = StatementCache::new(0);
+        let a = statement(&mut cache);
+        cache.insert(key("a"), a);
+        assert!(cache.get(&key("a")).is_none());
+    }
+
+    #[test]
+    fn schema_change() {
+        assert!(invalidates_cache(b"CREATE TYPE"));
+        assert!(invalidates_cache(b"COMMIT MIGRATION"));
+        assert!(invalidates_cache(b"SET MODULE"));
+        assert!(invalidates_cache(b"SET ALIAS"));
+        assert!(invalidates_cache(b"RESET ALIAS"));
+        assert!(invalidates_cache(b"CONFIGURE SESSION"));
+        assert!(invalidates_cache(b"ROLLBACK"));
+        assert!(invalidates_cache(b"ROLLBACK TO SAVEPOINT"));
+        assert!(!invalidates_cache(b"SELECT"));
+        assert!(!invalidates_cache(b"COMMIT"));
+        assert!(!invalidates_cache(b"CONFIGURE SYSTEM"));
+    }
+}
@@ -0,0 +1,286 @@
+//! Transport-independent connection stream
+use std::io;
+use std::pin::Pin;
+use std::task::{Poll, Context};
+
+use std::sync::{Arc, Mutex};
+
+use async_tls::client::TlsStream;
+use futures_util::io::{AsyncRead, AsyncWrite};
+
+use crate::capture::Replay;
+
+
+/// Socket the connection is established over, optionally encrypted
+///
+/// Clones refer to the same socket, so one can be used for reading and
+/// another one for writing.
+#[derive(Debug, Clone)]
+pub struct Stream {
+    inner: Inner,
+}
+
+// TLS state can't be shared, so the stream is locked for a single poll.
+// Reads and writes of the connection are done by the same task anyway.
+#[derive(Debug, Clone)]
+enum Inner {
+    Plain(Socket),
+    Tls(Arc<Mutex<TlsStream<Socket>>>),
+    Replay(Arc<Mutex<Replay>>),
+}
+
+#[cfg(feature="rt-async-std")]
+#[derive(Debug, Clone)]
+pub(crate) enum Socket {
+    Tcp(async_std::net::TcpStream),
+    #[cfg(unix)]
+    Unix(Arc<async_std::os::unix::net::UnixStream>),
+}
+
+// Tokio sockets can't be shared, but reads and writes register separate
+// wakers, so a lock that is held only within a single poll is enough.
+#[cfg(feature="rt-tokio")]
+#[derive(Debug, Clone)]
+pub(crate) enum Socket {
+    Tcp(Arc<Mutex<tokio::net::TcpStream>>),
+    #[cfg(unix)]
+    Unix(Arc<Mutex<tokio::net::UnixStream>>),
+}
+
+
+#[cfg(feature="rt-async-std")]
+impl From<async_std::net::TcpStream> for Stream {
+    fn from(stream: async_std::net::TcpStream) -> Stream {
+        Socket::Tcp(stream).into()
+    }
+}
+
+#[cfg(all(feature="rt-async-std", unix))]
+impl From<async_std::os::unix::net::UnixStream> for Stream {
+    fn from(stream: async_std::os::unix::net::UnixStream) -> Stream {
+        Socket::Unix(Arc::new(stream)).into()
+    }
+}
+
+#[cfg(feature="rt-async-std")]
+impl AsyncRead for Socket {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut [u8])
+        -> Poll<io::Result<usize>>
+    {
+        match self.get_mut() {
+            Socket::Tcp(s) => Pin::new(s).poll_read(cx, buf),
+            #[cfg(unix)]
+            Socket::Unix(s) => Pin::new(&mut &**s).poll_read(cx, buf),
+        }
+    }
+}
+
+#[cfg(feature="rt-async-std")]
+impl AsyncWrite for Socket {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8])
+        -> Poll<io::Result<usize>>
+    {
+        match self.get_mut() {
+            Socket::Tcp(s) => Pin::new(s).poll_write(cx, buf),
+            #[cfg(unix)]
+            Socket::Unix(s) => Pin::new(&mut &**s).poll_write(cx, buf),
+        }
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context)
+        -> Poll<io::Result<()>>
+    {
+        match self.get_mut() {
+            Socket::Tcp(s) => Pin::new(s).poll_flush(cx),
+            #[cfg(unix)]
+            Socket::Unix(s) => Pin::new(&mut &**s).poll_flush(cx),
+        }
+    }
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context)
+        -> Poll<io::Result<()>>
+    {
+        match self.get_mut() {
+            Socket::Tcp(s) => Pin::new(s).poll_close(cx),
+            #[cfg(unix)]
+            Socket::Unix(s) => Pin::new(&mut &**s).poll_close(cx),
+        }
+    }
+}
+
+#[cfg(feature="rt-tokio")]
+impl From<tokio::net::TcpStream> for Stream {
+    fn from(stream: tokio::net::TcpStream) -> Stream {
+        Socket::Tcp(Arc::new(Mutex::new(stream))).into()
+    }
+}
+
+#[cfg(all(feature="rt-tokio", unix))]
+impl From<tokio::net::UnixStream> for Stream {
+    fn from(stream: tokio::net::UnixStream) -> Stream {
+        Socket::Unix(Arc::new(Mutex::new(stream))).into()
+    }
+}
+
+#[cfg(feature="rt-tokio")]
+macro_rules! with_socket {
+    ($inner:expr, |$s:ident| $body:expr) => {
+        match $inner {
+            Socket::Tcp(s) => {
+                let mut guard = s.lock().expect("socket is not poisoned");
+                let $s = Pin::new(&mut *guard);
+                $body
+            }
+            #[cfg(unix)]
+            Socket::Unix(s) => {
+                let mut guard = s.lock().expect("socket is not poisoned");
+                let $s = Pin::new(&mut *guard);
+                $body
+            }
+        }
+    }
+}
+
+#[cfg(feature="rt-tokio")]
+impl AsyncRead for Socket {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut [u8])
+        -> Poll<io::Result<usize>>
+    {
+        use tokio::io::AsyncRead;
+        with_socket!(self.get_mut(), |s| s.poll_read(cx, buf))
+    }
+}
+
+#[cfg(feature="rt-tokio")]
+impl AsyncWrite for Socket {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8])
+        -> Poll<io::Result<usize>>
+    {
+        use tokio::io::AsyncWrite;
+        with_socket!(self.get_mut(), |s| s.poll_write(cx, buf))
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context)
+        -> Poll<io::Result<()>>
+    {
+        use tokio::io::AsyncWrite;
+        with_socket!(self.get_mut(), |s| s.poll_flush(cx))
+    }
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context)
+        -> Poll<io::Result<()>>
+    {
+        use tokio::io::AsyncWrite;
+        with_socket!(self.get_mut(), |s| s.poll_shutdown(cx))
+    }
+}
+
+impl From<Socket> for Stream {
+    fn from(socket: Socket) -> Stream {
+        Stream { inner: Inner::Plain(socket) }
+    }
+}
+
+impl From<Replay> for Stream {
+    fn from(replay: Replay) -> Stream {
+        Stream { inner: Inner::Replay(Arc::new(Mutex::new(replay))) }
+    }
+}
+
+impl Stream {
+    /// Returns the underlying socket if TLS is not established yet
+    pub(crate) fn into_socket(self) -> Option<Socket> {
+        match self.inner {
+            Inner::Plain(socket) => Some(socket),
+            Inner::Tls(_) | Inner::Replay(_) => None,
+        }
+    }
+    pub(crate) fn from_tls(stream: TlsStream<Socket>) -> Stream {
+        Stream { inner: Inner::Tls(Arc::new(Mutex::new(stream))) }
+    }
+    /// Returns true if the connection is encrypted
+    pub fn is_tls(&self) -> bool {
+        match self.inner {
+            Inner::Plain(_) | Inner::Replay(_) => false,
+            Inner::Tls(_) => true,
+        }
+    }
+}
+
+macro_rules! with_stream {
+    ($stream:expr, |$s:ident| $body:expr) => {
+        match $stream.get_mut().inner {
+            Inner::Plain(ref mut socket) => {
+                let $s = Pin::new(socket);
+                $body
+            }
+            Inner::Tls(ref tls) => {
+                let mut guard = tls.lock().expect("stream is not poisoned");
+                let $s = Pin::new(&mut *guard);
+                $body
+            }
+            Inner::Replay(ref replay) => {
+                let mut guard = replay.lock()
+                    .expect("stream is not poisoned");
+                let $s = Pin::new(&mut *guard);
+                $body
+            }
+        }
+    }
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut [u8])
+        -> Poll<io::Result<usize>>
+    {
+        with_stream!(self, |s| s.poll_read(cx, buf))
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8])
+        -> Poll<io::Result<usize>>
+    {
+        with_stream!(self, |s| s.poll_write(cx, buf))
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context)
+        -> Poll<io::Result<()>>
+    {
+        with_stream!(self, |s| s.poll_flush(cx))
+    }
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context)
+        -> Poll<io::Result<()>>
+    {
+        with_stream!(self, |s| s.poll_close(cx))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    use futures_util::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::rt;
+
+    #[test]
+    fn tcp_echo() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut sock, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 5];
+            sock.read_exact(&mut buf).unwrap();
+            sock.write_all(&buf).unwrap();
+        });
+        rt::block_on(async {
+            let mut writer = rt::connect_tcp("127.0.0.1", port).await
+                .unwrap();
+            let mut reader = writer.clone();
+            writer.write_all(b"hello").await.unwrap();
+            writer.flush().await.unwrap();
+            let mut buf = [0u8; 5];
+            reader.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"hello");
+        });
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,210 @@
+//! TLS settings of the client connections
+//!
+//! TLS is enabled by `Builder::tls`. By default the server certificate is
+//! checked against the Mozilla's root certificates, use
+//! `Builder::tls_ca_file` to trust a private (or self-signed) CA instead.
+use std::fs;
+use std::io;
+use std::sync::Arc;
+
+use async_tls::TlsConnector;
+use rustls::{ClientConfig, RootCertStore, Certificate, TLSError};
+use rustls::{ServerCertVerifier, ServerCertVerified, WebPKIVerifier};
+use snafu::ResultExt;
+use webpki::DNSNameRef;
+
+use crate::builder::Builder;
+use crate::errors::{self, Error};
+use crate::stream::Stream;
+
+
+/// Protocol name negotiated using ALPN
+pub const ALPN_PROTOCOL: &[u8] = b"edgedb-binary";
+
+/// How the server certificate is verified
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlsVerification {
+    /// Certificate chain is valid and is issued for the host name
+    Full,
+    /// Certificate chain is valid, the host name isn't checked
+    NoHostVerification,
+    /// Any certificate is accepted, this is only useful for testing
+    Insecure,
+}
+
+struct NoHostVerifier;
+
+struct NullVerifier;
+
+
+impl ServerCertVerifier for NoHostVerifier {
+    fn verify_server_cert(&self, roots: &RootCertStore,
+        presented_certs: &[Certificate], dns_name: DNSNameRef,
+        ocsp_response: &[u8])
+        -> Result<ServerCertVerified, TLSError>
+    {
+        let result = WebPKIVerifier::new().verify_server_cert(
+            roots, presented_certs, dns_name, ocsp_response);
+        match result {
+            Err(TLSError::WebPKIError(webpki::Error::CertNotValidForName))
+            => Ok(ServerCertVerified::assertion()),
+            result => result,
+        }
+    }
+}
+
+impl ServerCertVerifier for NullVerifier {
+    fn verify_server_cert(&self, _roots: &RootCertStore,
+        _presented_certs: &[Certificate], _dns_name: DNSNameRef,
+        _ocsp_response: &[u8])
+        -> Result<ServerCertVerified, TLSError>
+    {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+fn connector(params: &Builder) -> Result<TlsConnector, Error> {
+    let mut config = ClientConfig::new();
+    match params.tls_ca_file {
+        Some(ref path) => {
+            let data = fs::read(path)
+                .context(errors::ReadCaFile { path })?;
+            match config.root_store.add_pem_file(&mut io::Cursor::new(data))
+            {
+                Ok((valid, _)) if valid > 0 => {}
+                _ => {
+                    return errors::InvalidCaFile { path }.fail();
+                }
+            }
+        }
+        None => {
+            config.root_store
+                .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+        }
+    }
+    config.set_protocols(&[ALPN_PROTOCOL.to_vec()]);
+    match params.tls_verification {
+        TlsVerification::Full => {}
+        TlsVerification::NoHostVerification => {
+            config.dangerous()
+                .set_certificate_verifier(Arc::new(NoHostVerifier));
+        }
+        TlsVerification::Insecure => {
+            config.dangerous()
+                .set_certificate_verifier(Arc::new(NullVerifier));
+        }
+    }
+    Ok(Arc::new(config).into())
+}
+
+/// Performs TLS handshake over the plain stream
+pub(crate) async fn start_tls(stream: Stream, params: &Builder)
+    -> Result<Stream, Error>
+{
+    let socket = stream.into_socket().expect("stream is not encrypted");
+    let connector = connector(params)?;
+    let name = params.get_tls_server_name();
+    let stream = connector.connect(name, socket).await
+        .context(errors::TlsHandshake { server_name: name })?;
+    Ok(Stream::from_tls(stream))
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::io::{BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::thread;
+
+    use futures_util::io::{AsyncReadExt, AsyncWriteExt};
+    use rustls::{ServerConfig, ServerSession, StreamOwned, NoClientAuth};
+    use rustls::internal::pemfile;
+
+    use crate::builder::Builder;
+    use crate::errors::Error;
+    use crate::rt;
+    use super::{TlsVerification, ALPN_PROTOCOL};
+
+    /// Starts echo server with a self-signed certificate for `localhost`
+    ///
+    /// Returns port and the path to the certificate.
+    fn echo_server(name: &str) -> (u16, PathBuf) {
+        let cert = rcgen::generate_simple_self_signed(
+            vec!["localhost".into()]).unwrap();
+        let cert_pem = cert.serialize_pem().unwrap();
+        let key_pem = cert.serialize_private_key_pem();
+        let path = std::env::temp_dir()
+            .join(format!("edgedb-client-{}-{}.pem",
+                          name, std::process::id()));
+        fs::write(&path, &cert_pem).unwrap();
+
+        let mut config = ServerConfig::new(NoClientAuth::new());
+        let certs = pemfile::certs(
+            &mut BufReader::new(cert_pem.as_bytes())).unwrap();
+        let mut keys = pemfile::pkcs8_private_keys(
+            &mut BufReader::new(key_pem.as_bytes())).unwrap();
+        config.set_single_cert(certs, keys.remove(0)).unwrap();
+        config.set_protocols(&[ALPN_PROTOCOL.to_vec()]);
+        let config = Arc::new(config);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for sock in listener.incoming() {
+                let session = ServerSession::new(&config);
+                let mut stream = StreamOwned::new(session, sock.unwrap());
+                let mut buf = [0u8; 5];
+                if stream.read_exact(&mut buf).is_ok() {
+                    stream.write_all(&buf).ok();
+                    stream.flush().ok();
+                }
+            }
+        });
+        (port, path)
+    }
+
+    async fn echo(params: &Builder) -> Result<Vec<u8>, Error> {
+        let mut stream = params.connect_stream().await?;
+        assert!(stream.is_tls());
+        stream.write_all(b"hello").await.unwrap();
+        stream.flush().await.unwrap();
+        let mut buf = vec![0u8; 5];
+        stream.read_exact(&mut buf).await.unwrap();
+        Ok(buf)
+    }
+
+    #[test]
+    fn self_signed() {
+        let (port, ca_file) = echo_server("self-signed");
+        let mut params = Builder::new();
+        params.host_port("localhost", port).tls(true).tls_ca_file(&ca_file);
+        assert_eq!(rt::block_on(echo(&params)).unwrap(), b"hello");
+
+        params.tls_server_name("example.com");
+        match rt::block_on(echo(&params)) {
+            Err(Error::TlsHandshake { .. }) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        params.tls_verification(TlsVerification::NoHostVerification);
+        assert_eq!(rt::block_on(echo(&params)).unwrap(), b"hello");
+        fs::remove_file(ca_file).ok();
+    }
+
+    #[test]
+    fn untrusted() {
+        let (port, ca_file) = echo_server("untrusted");
+        let mut params = Builder::new();
+        params.host_port("localhost", port).tls(true);
+        match rt::block_on(echo(&params)) {
+            Err(Error::TlsHandshake { .. }) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        params.tls_verification(TlsVerification::Insecure);
+        assert_eq!(rt::block_on(echo(&params)).unwrap(), b"hello");
+        fs::remove_file(ca_file).ok();
+    }
+}
@@ -0,0 +1,277 @@
+//! Transactions with automatic retry
+//!
+//! ```rust,no_run
+//! # use edgedb_protocol::value::Value;
+//! # use edgedb_client::transaction::TransactionOptions;
+//! # async fn example(cli: &mut edgedb_client::Client<'_>)
+//! #     -> Result<(), edgedb_client::Error>
+//! # {
+//! cli.transaction(&TransactionOptions::new(), |tx| Box::pin(async move {
+//!     tx.execute_args("UPDATE Counter SET { value := .value + 1 }",
+//!                     &Value::empty_tuple()).await?;
+//!     Ok(())
+//! })).await?;
+//! # Ok(())
+//! # }
+//! ```
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::time::Duration;
+
+use edgedb_protocol::server_message::TransactionState;
+
+use crate::backoff;
+use crate::builder::Builder;
+use crate::client::Client;
+use crate::errors::{self, Error};
+use crate::instrument::Operation;
+use crate::rt;
+
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output=T> + Send + 'a>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Isolation {
+    Serializable,
+    RepeatableRead,
+}
+
+/// Transaction settings
+#[derive(Debug, Clone)]
+pub struct TransactionOptions {
+    isolation: Isolation,
+    read_only: bool,
+    deferrable: bool,
+    attempts: u32,
+    backoff: Duration,
+    max_backoff: Duration,
+}
+
+/// Client running in a transaction block
+///
+/// Don't commit or roll back the transaction manually, this is done by
+/// `Client::transaction` depending on the result of the closure.
+pub struct Transaction<'a> {
+    cli: Client<'a>,
+}
+
+/// Retry state shared by `Client::transaction` and
+/// `blocking::Connection::transaction`
+pub(crate) struct Attempts<'a> {
+    options: &'a TransactionOptions,
+    retry: u32,
+    /// Reported when the next attempt is done
+    operation: Option<Operation>,
+}
+
+
+impl TransactionOptions {
+    pub fn new() -> TransactionOptions {
+        TransactionOptions {
+            isolation: Isolation::Serializable,
+            read_only: false,
+            deferrable: false,
+            attempts: 3,
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+    pub fn isolation(&mut self, isolation: Isolation) -> &mut Self {
+        self.isolation = isolation;
+        self
+    }
+    pub fn read_only(&mut self, read_only: bool) -> &mut Self {
+        self.read_only = read_only;
+        self
+    }
+    pub fn deferrable(&mut self, deferrable: bool) -> &mut Self {
+        self.deferrable = deferrable;
+        self
+    }
+    /// Number of times the transaction is run on conflicts, including the
+    /// first one
+    pub fn attempts(&mut self, attempts: u32) -> &mut Self {
+        assert!(attempts > 0, "at least one attempt is required");
+        self.attempts = attempts;
+        self
+    }
+    /// Delay before the first retry, it's doubled on each next retry up to
+    /// `max_backoff`
+    pub fn backoff(&mut self, backoff: Duration, max_backoff: Duration)
+        -> &mut Self
+    {
+        self.backoff = backoff;
+        self.max_backoff = max_backoff;
+        self
+    }
+    pub(crate) fn start_statement(&self) -> String {
+        format!("START TRANSACTION ISOLATION {}, {}, {}",
+            match self.isolation {
+                Isolation::Serializable => "SERIALIZABLE",
+                Isolation::RepeatableRead => "REPEATABLE READ",
+            },
+            if self.read_only { "READ ONLY" } else { "READ WRITE" },
+            if self.deferrable { "DEFERRABLE" } else { "NOT DEFERRABLE" })
+    }
+    pub(crate) fn delay(&self, retry: u32) -> Duration {
+        backoff::delay(self.backoff, self.max_backoff, retry)
+    }
+}
+
+impl Default for TransactionOptions {
+    fn default() -> TransactionOptions {
+        TransactionOptions::new()
+    }
+}
+
+impl<'a> Client<'a> {
+    /// Runs `body` in a transaction block
+    ///
+    /// The transaction is committed if `body` returns `Ok` and rolled back
+    /// otherwise. Whole transaction is run again if it fails with
+    /// a serialization or a deadlock error, so `body` may be called
+    /// several times.
+    pub async fn transaction<T, F>(&mut self, options: &TransactionOptions,
+        mut body: F)
+        -> Result<T, Error>
+        where F: for<'t> FnMut(&'t mut Transaction<'_>)
+                 -> BoxFuture<'t, Result<T, Error>>,
+    {
+        let mut attempts = Attempts::new(options);
+        loop {
+            let start = attempts.start(self.transaction_state())?;
+            self.execute(start).await?;
+            let mut tx = Transaction {
+                cli: Client { conn: &mut *self.conn },
+            };
+            let result = match body(&mut tx).await {
+                Ok(value) => self.execute("COMMIT").await.map(|_| value),
+                Err(e) => Err(e),
+            };
+            let err = match result {
+                Ok(value) => return Ok(value),
+                Err(e) => e,
+            };
+            let delay = attempts.failed(&err);
+            // nothing to roll back on a lost connection, and if rollback
+            // fails the original error is more useful
+            if !err.is_disconnect() && self.rollback().await.is_err() {
+                return Err(err);
+            }
+            match delay {
+                Some(delay) => rt::sleep(delay).await,
+                None => return Err(err),
+            }
+            attempts.next(&self.conn.params, &err);
+        }
+    }
+
+    /// Rolls back the transaction if the failed body or commit left it open
+    pub(crate) async fn rollback(&mut self) -> Result<(), Error> {
+        if self.transaction_state().is_none() {
+            self.wait_ready().await?;
+        }
+        match self.transaction_state() {
+            Some(TransactionState::NotInTransaction) => {}
+            _ => {
+                self.execute("ROLLBACK").await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Attempts<'a> {
+    pub(crate) fn new(options: &TransactionOptions) -> Attempts<'_> {
+        Attempts {
+            options,
+            retry: 0,
+            operation: None,
+        }
+    }
+    /// Returns the statement starting the next attempt
+    ///
+    /// Nested transactions are refused, because retrying them would
+    /// require restarting the outer one.
+    pub(crate) fn start(&self, state: Option<TransactionState>)
+        -> Result<String, Error>
+    {
+        if state != Some(TransactionState::NotInTransaction) {
+            return errors::TransactionState { state }.fail();
+        }
+        Ok(self.options.start_statement())
+    }
+    /// Marks the attempt as failed with `err`
+    ///
+    /// Returns the delay before the next attempt, or `None` if the error
+    /// should be returned to the caller. Transaction must be rolled back in
+    /// both cases.
+    pub(crate) fn failed(&mut self, err: &Error) -> Option<Duration> {
+        if let Some(ref mut operation) = self.operation {
+            operation.fail();
+        }
+        self.retry += 1;
+        if !should_retry(err, self.retry, self.options) {
+            return None;
+        }
+        Some(self.options.delay(self.retry - 1))
+    }
+    /// Starts reporting the next attempt
+    pub(crate) fn next(&mut self, params: &Builder, err: &Error) {
+        self.operation = Some(Operation::retry(params, self.retry, err));
+    }
+}
+
+/// Returns true if the transaction failed with `err` after `retry`
+/// attempts should be run again
+pub(crate) fn should_retry(err: &Error, retry: u32,
+    options: &TransactionOptions)
+    -> bool
+{
+    err.is_transaction_conflict() && retry < options.attempts
+}
+
+impl<'a> Deref for Transaction<'a> {
+    type Target = Client<'a>;
+    fn deref(&self) -> &Client<'a> {
+        &self.cli
+    }
+}
+
+impl<'a> DerefMut for Transaction<'a> {
+    fn deref_mut(&mut self) -> &mut Client<'a> {
+        &mut self.cli
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+    use super::{TransactionOptions, Isolation};
+
+    #[test]
+    fn start_statement() {
+        assert_eq!(TransactionOptions::new().start_statement(),
+            "START TRANSACTION ISOLATION SERIALIZABLE, READ WRITE, \
+             NOT DEFERRABLE");
+        assert_eq!(TransactionOptions::new()
+            .isolation(Isolation::RepeatableRead)
+            .read_only(true)
+            .deferrable(true)
+            .start_statement(),
+            "START TRANSACTION ISOLATION REPEATABLE READ, READ ONLY, \
+             DEFERRABLE");
+    }
+
+    #[test]
+    fn backoff() {
+        let mut opt = TransactionOptions::new();
+        opt.backoff(Duration::from_millis(100), Duration::from_secs(1));
+        assert_eq!(opt.delay(0), Duration::from_millis(100));
+        assert_eq!(opt.delay(1), Duration::from_millis(200));
+        assert_eq!(opt.delay(3), Duration::from_millis(800));
+        assert_eq!(opt.delay(4), Duration::from_secs(1));
+        assert_eq!(opt.delay(100), Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,31 @@
+//! Runtime shim, so that async tests run with either runtime feature
+use std::future::Future;
+use std::time::Duration;
+
+
+#[cfg(feature="rt-async-std")]
+pub fn block_on<F: Future>(f: F) -> F::Output {
+    async_std::task::block_on(f)
+}
+
+#[cfg(feature="rt-async-std")]
+#[allow(dead_code)]
+pub async fn sleep(duration: Duration) {
+    async_std::task::sleep(duration).await
+}
+
+#[cfg(feature="rt-tokio")]
+pub fn block_on<F: Future>(f: F) -> F::Output {
+    tokio::runtime::Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .build()
+        .expect("can create runtime")
+        .block_on(f)
+}
+
+#[cfg(feature="rt-tokio")]
+#[allow(dead_code)]
+pub async fn sleep(duration: Duration) {
+    tokio::time::delay_for(duration).await
+}
@@ -0,0 +1,262 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use edgedb_client::Builder;
+use edgedb_client::blocking::Connection;
+use edgedb_client::errors::Error;
+use edgedb_mock::{MockServer, Script, Response, message_name};
+use edgedb_protocol::client_message::ClientMessage;
+use edgedb_protocol::codec::STD_INT64;
+use edgedb_protocol::headers::RequestHeaders;
+use edgedb_protocol::server_message::MessageSeverity;
+use edgedb_protocol::server_message::Cardinality::One;
+use edgedb_protocol::value::Value;
+
+
+fn params(server: &MockServer) -> Builder {
+    let mut params = Builder::new();
+    params.host_port("127.0.0.1", server.port());
+    params.user("edgedb");
+    params
+}
+
+fn int64_rows(values: &[i64]) -> Response {
+    let mut response = Response::base_scalar(STD_INT64);
+    for value in values {
+        response.row(value.to_be_bytes().to_vec());
+    }
+    response.status("SELECT");
+    response
+}
+
+#[test]
+fn scram_and_execute() {
+    let mut script = Script::new();
+    script.password("edgedb", "secret");
+    script.query("CREATE TYPE Foo", Response::new().status("CREATE TYPE"));
+    let server = MockServer::start(script).unwrap();
+    let mut conn = Connection::connect(params(&server).password("secret"))
+        .unwrap();
+    let status = conn.execute("CREATE TYPE Foo").unwrap();
+    assert_eq!(&status[..], b"CREATE TYPE");
+    server.assert_received(&["ClientHandshake",
+        "AuthenticationSaslInitialResponse", "AuthenticationSaslResponse",
+        "ExecuteScript"]);
+}
+
+#[test]
+fn query_rows() {
+    let mut script = Script::new();
+    script.query("SELECT {1, 2}", &int64_rows(&[1, 2]));
+    let server = MockServer::start(script).unwrap();
+    let mut conn = Connection::connect(&params(&server)).unwrap();
+    server.clear_received();
+    let rows = conn.query::<i64, _>("SELECT {1, 2}", &Value::empty_tuple())
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(rows, vec![1, 2]);
+    // prepared statement is cached
+    let rows = conn.query::<i64, _>("SELECT {1, 2}", &Value::empty_tuple())
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(rows, vec![1, 2]);
+    server.assert_received(&["Prepare", "DescribeStatement", "Sync",
+        "Execute", "Sync", "Execute", "Sync"]);
+}
+
+#[test]
+fn server_error() {
+    let mut script = Script::new();
+    script.query("SELECT x", &Response::error(0x_04_01_00_00,
+                                              "object x does not exist"));
+    script.query("SELECT 1", int64_rows(&[1]).cardinality(One));
+    let server = MockServer::start(script).unwrap();
+    let mut conn = Connection::connect(&params(&server)).unwrap();
+    let err = conn.query::<i64, _>("SELECT x", &Value::empty_tuple())
+        .err().unwrap();
+    assert_eq!(err.server_error().unwrap().code, 0x_04_01_00_00);
+    assert!(conn.execute("SELECT x").is_err());
+    // connection is still usable
+    assert_eq!(conn.query_single::<i64, _>("SELECT 1",
+                                            &Value::empty_tuple()).unwrap(),
+               Some(1));
+}
+
+fn assert_cardinality_mismatch(err: Error) {
+    match err {
+        Error::CardinalityMismatch { .. } => {}
+        err => panic!("unexpected error: {}", err),
+    }
+}
+
+#[test]
+fn single_cardinality() {
+    let mut script = Script::new();
+    script.query("SELECT {1, 2}", &int64_rows(&[1, 2]));
+    script.query("SELECT 1", int64_rows(&[1]).cardinality(One));
+    // server doesn't check the actual number of elements in this case
+    script.query("SELECT bad", int64_rows(&[1, 2]).cardinality(One));
+    script.query("SELECT <int64>{}", int64_rows(&[]).cardinality(One));
+    let server = MockServer::start(script).unwrap();
+    let mut conn = Connection::connect(&params(&server)).unwrap();
+    let args = Value::empty_tuple();
+
+    let err = conn.query_single::<i64, _>("SELECT {1, 2}", &args)
+        .unwrap_err();
+    assert_cardinality_mismatch(err);
+    let err = conn.query_required_single::<i64, _>("SELECT {1, 2}", &args)
+        .unwrap_err();
+    assert_cardinality_mismatch(err);
+
+    let err = conn.query_single::<i64, _>("SELECT bad", &args)
+        .unwrap_err();
+    assert_cardinality_mismatch(err);
+
+    assert_eq!(conn.query_single::<i64, _>("SELECT <int64>{}", &args)
+               .unwrap(), None);
+    let err = conn.query_required_single::<i64, _>("SELECT <int64>{}", &args)
+        .unwrap_err();
+    match err {
+        Error::NoData => {}
+        err => panic!("unexpected error: {}", err),
+    }
+
+    // connection is still usable after all the errors
+    assert_eq!(conn.query_required_single::<i64, _>("SELECT 1", &args)
+               .unwrap(), 1);
+}
+
+#[test]
+fn cancel_on_timeout() {
+    let mut script = Script::new();
+    script.query("SELECT sys::_sleep(60)", Response::new().stall());
+    let server = MockServer::start(script).unwrap();
+    let mut conn = Connection::connect(params(&server)
+        .query_timeout(Some(Duration::from_millis(50)))).unwrap();
+    let err = conn.execute("SELECT sys::_sleep(60)").unwrap_err();
+    assert!(err.is_timeout(), "{}", err);
+    // cancel request is sent even if the connection is not used anymore
+    drop(conn);
+    for _ in 0..100 {
+        if server.received().iter()
+            .any(|msg| message_name(msg) == "CancelRequest")
+        {
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    panic!("no cancel request received");
+}
+
+#[test]
+fn reconnect_read_only() {
+    let mut script = Script::new();
+    script.query("SELECT 1", int64_rows(&[1]).cardinality(One).disconnect());
+    let server = MockServer::start(script).unwrap();
+    let mut conn = Connection::connect(params(&server).retry_read_only(true))
+        .unwrap();
+    let value = conn.query_single::<i64, _>("SELECT 1",
+                                            &Value::empty_tuple()).unwrap();
+    assert_eq!(value, Some(1));
+    assert_eq!(server.connections(), 2);
+}
+
+#[test]
+fn reconnect_keeps_headers() {
+    let mut script = Script::new();
+    script.query("SELECT 1", int64_rows(&[1]).cardinality(One).disconnect());
+    let server = MockServer::start(script).unwrap();
+    let mut conn = Connection::connect(params(&server).retry_read_only(true))
+        .unwrap();
+    let mut headers = RequestHeaders::new();
+    headers.implicit_limit(10);
+    conn.set_request_headers(&headers);
+    let value = conn.query_single::<i64, _>("SELECT 1",
+                                            &Value::empty_tuple()).unwrap();
+    assert_eq!(value, Some(1));
+    assert_eq!(server.connections(), 2);
+    let prepared = server.received().into_iter()
+        .filter_map(|msg| match msg {
+            ClientMessage::Prepare(prepare) => Some(prepare.headers),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(prepared, vec![headers.build(), headers.build()]);
+}
+
+#[test]
+fn log_messages() {
+    let mut script = Script::new();
+    script.query("CONFIGURE SYSTEM RESET x", Response::new()
+        .log(MessageSeverity::Warning, "x is deprecated")
+        .status("CONFIGURE SYSTEM"));
+    let server = MockServer::start(script).unwrap();
+    let messages = Arc::new(Mutex::new(Vec::new()));
+    let mut params = params(&server);
+    let log = messages.clone();
+    params.on_log_message(move |msg| {
+        log.lock().unwrap().push(msg.text.clone());
+    });
+    let mut conn = Connection::connect(&params).unwrap();
+    conn.execute("CONFIGURE SYSTEM RESET x").unwrap();
+    assert_eq!(*messages.lock().unwrap(), vec!["x is deprecated"]);
+}
+
+#[test]
+fn invalid_frame_length() {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    let server = MockServer::start(Script::new()).unwrap();
+    let mut sock = TcpStream::connect(("127.0.0.1", server.port())).unwrap();
+    sock.write_all(b"V\0\0\0\x02").unwrap();
+    // connection is closed, but the server keeps working
+    assert_eq!(sock.read(&mut [0u8; 16]).unwrap(), 0);
+    Connection::connect(&params(&server)).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn unix_socket() {
+    let dir = std::env::temp_dir()
+        .join(format!("edgedb-mock-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(".s.EDGEDB.5656");
+    std::fs::remove_file(&path).ok();
+    let mut script = Script::new();
+    script.query("START TRANSACTION", &Response::new());
+    let server = MockServer::start_unix(script, &path).unwrap();
+    let mut params = Builder::new();
+    params.unix_path(&dir);
+    let mut conn = Connection::connect(&params).unwrap();
+    conn.execute("START TRANSACTION").unwrap();
+    std::fs::remove_dir_all(&dir).ok();
+    assert_eq!(server.connections(), 1);
+}
+
+#[test]
+fn pipelined_script_batch() {
+    use edgedb_client::batch::ScriptBatch;
+
+    let mut script = Script::new();
+    script.query("INSERT User", Response::new().status("INSERT"));
+    script.query("INSERT Bad", &Response::error(0x_05_01_00_00,
+                                                "constraint violated"));
+    let server = MockServer::start(script).unwrap();
+    let results = common::block_on(async {
+        let mut conn = params(&server).connect().await?;
+        let mut cli = conn.authenticate().await?;
+        cli.execute_script_batch(ScriptBatch::new()
+            .add("INSERT User")
+            .add("INSERT Bad")
+            .add("INSERT User")).await
+    }).unwrap();
+    assert_eq!(results.len(), 3);
+    assert_eq!(&results[0].as_ref().unwrap()[..], b"INSERT");
+    assert!(results[1].is_err());
+    assert_eq!(&results[2].as_ref().unwrap()[..], b"INSERT");
+    server.assert_received(&["ClientHandshake",
+        "ExecuteScript", "ExecuteScript", "ExecuteScript"]);
+}
@@ -0,0 +1,188 @@
+mod common;
+
+use std::net::TcpListener;
+use std::time::Duration;
+
+use edgedb_client::Builder;
+use edgedb_client::errors::Error;
+use edgedb_client::pool::{Pool, PoolOptions, PoolStats};
+use edgedb_mock::{MockServer, Script, Response};
+use edgedb_protocol::server_message::TransactionState;
+
+
+fn server() -> MockServer {
+    let mut script = Script::new();
+    script.query("SELECT 1", &Response::new());
+    script.query("START TRANSACTION", Response::new()
+        .transaction_state(TransactionState::InTransaction));
+    script.query("BROKEN", Response::new().disconnect());
+    script.query("CLOSE", Response::new().close_after());
+    MockServer::start(script).unwrap()
+}
+
+fn params(port: u16) -> Builder {
+    let mut params = Builder::new();
+    params.host_port("127.0.0.1", port);
+    params.user("edgedb");
+    params
+}
+
+fn stats(idle: usize, in_use: usize, created: u64, discarded: u64,
+    reaped: u64)
+    -> PoolStats
+{
+    PoolStats { idle, in_use, waiting: 0, created, discarded, reaped }
+}
+
+#[test]
+fn reuse_and_stats() {
+    let server = server();
+    let pool = Pool::new(&params(server.port()));
+    common::block_on(async {
+        let mut first = pool.acquire().await?;
+        let second = pool.acquire().await?;
+        assert_eq!(pool.stats(), stats(0, 2, 2, 0, 0));
+        first.client().execute("SELECT 1").await?;
+        drop(first);
+        drop(second);
+        assert_eq!(pool.stats(), stats(2, 0, 2, 0, 0));
+        let _conn = pool.acquire().await?;
+        assert_eq!(pool.stats(), stats(1, 1, 2, 0, 0));
+        Ok::<_, Error>(())
+    }).unwrap();
+    assert_eq!(server.connections(), 2);
+}
+
+#[test]
+fn discard_in_transaction() {
+    let server = server();
+    let pool = Pool::new(&params(server.port()));
+    common::block_on(async {
+        let mut conn = pool.acquire().await?;
+        conn.client().execute("START TRANSACTION").await?;
+        drop(conn);
+        assert_eq!(pool.stats(), stats(0, 0, 1, 1, 0));
+        Ok::<_, Error>(())
+    }).unwrap();
+}
+
+#[test]
+fn discard_broken() {
+    let server = server();
+    let pool = Pool::new(&params(server.port()));
+    common::block_on(async {
+        let mut conn = pool.acquire().await?;
+        assert!(conn.client().execute("BROKEN").await.is_err());
+        drop(conn);
+        assert_eq!(pool.stats(), stats(0, 0, 1, 1, 0));
+        // new connection is opened instead of the broken one
+        pool.acquire().await?.client().execute("SELECT 1").await?;
+        assert_eq!(pool.stats(), stats(1, 0, 2, 1, 0));
+        Ok::<_, Error>(())
+    }).unwrap();
+}
+
+#[test]
+fn discard_closed_by_server() {
+    let server = server();
+    let pool = Pool::new(&params(server.port()));
+    common::block_on(async {
+        pool.acquire().await?.client().execute("CLOSE").await?;
+        assert_eq!(pool.stats(), stats(1, 0, 1, 0, 0));
+        // wait for the end of stream to arrive
+        common::sleep(Duration::from_millis(50)).await;
+        pool.acquire().await?.client().execute("SELECT 1").await?;
+        assert_eq!(pool.stats(), stats(1, 0, 2, 1, 0));
+        Ok::<_, Error>(())
+    }).unwrap();
+    assert_eq!(server.connections(), 2);
+}
+
+#[test]
+fn warm_up() {
+    let server = server();
+    let pool = PoolOptions::new()
+        .min_size(2)
+        .build(&params(server.port()));
+    common::block_on(async {
+        pool.warm_up().await?;
+        assert_eq!(pool.stats(), stats(2, 0, 2, 0, 0));
+        let _conn = pool.acquire().await?;
+        pool.warm_up().await?;
+        assert_eq!(pool.stats(), stats(1, 1, 2, 0, 0));
+        Ok::<_, Error>(())
+    }).unwrap();
+    assert_eq!(server.connections(), 2);
+}
+
+#[test]
+fn reap_idle() {
+    let server = server();
+    let pool = PoolOptions::new()
+        .idle_timeout(Some(Duration::from_millis(10)))
+        .build(&params(server.port()));
+    common::block_on(async {
+        drop(pool.acquire().await?);
+        assert_eq!(pool.stats(), stats(1, 0, 1, 0, 0));
+        common::sleep(Duration::from_millis(50)).await;
+        pool.reap_idle();
+        assert_eq!(pool.stats(), stats(0, 0, 1, 0, 1));
+        Ok::<_, Error>(())
+    }).unwrap();
+}
+
+#[test]
+fn reap_keeps_min_size() {
+    let server = server();
+    let pool = PoolOptions::new()
+        .min_size(1)
+        .idle_timeout(Some(Duration::from_millis(10)))
+        .build(&params(server.port()));
+    common::block_on(async {
+        let first = pool.acquire().await?;
+        let second = pool.acquire().await?;
+        drop(first);
+        drop(second);
+        common::sleep(Duration::from_millis(50)).await;
+        pool.reap_idle();
+        assert_eq!(pool.stats(), stats(1, 0, 2, 0, 1));
+        Ok::<_, Error>(())
+    }).unwrap();
+}
+
+#[test]
+fn acquire_timeout() {
+    let server = server();
+    let pool = PoolOptions::new()
+        .max_size(1)
+        .acquire_timeout(Duration::from_millis(50))
+        .build(&params(server.port()));
+    common::block_on(async {
+        let _conn = pool.acquire().await?;
+        match pool.acquire().await {
+            Err(Error::AcquireTimeout { .. }) => {}
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("connection acquired over max_size"),
+        }
+        // timed out waiter is not counted anymore
+        assert_eq!(pool.stats(), stats(0, 1, 1, 0, 0));
+        Ok::<_, Error>(())
+    }).unwrap();
+}
+
+#[test]
+fn acquire_timeout_on_connect() {
+    // accepts connections into the backlog, but never responds
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let pool = PoolOptions::new()
+        .acquire_timeout(Duration::from_millis(100))
+        .build(&params(port));
+    let res = common::block_on(pool.acquire());
+    match res {
+        Err(Error::AcquireTimeout { .. }) => {}
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("connection acquired"),
+    }
+    assert_eq!(pool.stats(), stats(0, 0, 0, 0, 0));
+}
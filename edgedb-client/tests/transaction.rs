@@ -0,0 +1,188 @@
+mod common;
+
+use edgedb_client::Builder;
+use edgedb_client::errors::Error;
+use edgedb_client::transaction::TransactionOptions;
+use edgedb_mock::{MockServer, Script, Response};
+use edgedb_protocol::server_message::TransactionState;
+
+
+const START: &str = "START TRANSACTION ISOLATION SERIALIZABLE, READ WRITE, \
+                     NOT DEFERRABLE";
+const SERIALIZATION_ERROR: u32 = 0x_05_03_00_01;
+const CONSTRAINT_ERROR: u32 = 0x_05_01_00_00;
+
+fn script() -> Script {
+    let mut script = Script::new();
+    script.query(START, Response::new()
+        .transaction_state(TransactionState::InTransaction)
+        .status("START TRANSACTION"));
+    script.query("COMMIT", Response::new()
+        .transaction_state(TransactionState::NotInTransaction)
+        .status("COMMIT"));
+    script.query("ROLLBACK", Response::new()
+        .transaction_state(TransactionState::NotInTransaction)
+        .status("ROLLBACK"));
+    script.query("INSERT Item", Response::new().status("INSERT"));
+    script.query("INSERT Bad", &Response::error(CONSTRAINT_ERROR,
+                                                "constraint violated"));
+    script
+}
+
+fn params(server: &MockServer) -> Builder {
+    let mut params = Builder::new();
+    params.host_port("127.0.0.1", server.port());
+    params.user("edgedb");
+    params
+}
+
+fn no_backoff() -> TransactionOptions {
+    let mut options = TransactionOptions::new();
+    options.backoff(Default::default(), Default::default());
+    options
+}
+
+#[test]
+fn commit() {
+    let server = MockServer::start(script()).unwrap();
+    let value = common::block_on(async {
+        let mut conn = params(&server).connect().await?;
+        let mut cli = conn.authenticate().await?;
+        server.clear_received();
+        cli.transaction(&no_backoff(), |tx| Box::pin(async move {
+            tx.execute("INSERT Item").await?;
+            Ok(7)
+        })).await
+    }).unwrap();
+    assert_eq!(value, 7);
+    server.assert_received(&["ExecuteScript", "ExecuteScript",
+                             "ExecuteScript"]);
+}
+
+#[test]
+fn rollback_on_error() {
+    let server = MockServer::start(script()).unwrap();
+    let res = common::block_on(async {
+        let mut conn = params(&server).connect().await?;
+        let mut cli = conn.authenticate().await?;
+        let res = cli.transaction(&no_backoff(), |tx| Box::pin(async move {
+            tx.execute("INSERT Item").await?;
+            tx.execute("INSERT Bad").await?;
+            Ok(())
+        })).await;
+        assert_eq!(cli.transaction_state(),
+                   Some(TransactionState::NotInTransaction));
+        Ok::<_, Error>(res)
+    }).unwrap();
+    let err = res.unwrap_err();
+    assert_eq!(err.server_error().unwrap().code, CONSTRAINT_ERROR);
+    assert!(server.received().iter().any(|msg| {
+        format!("{:?}", msg).contains("ROLLBACK")
+    }));
+}
+
+#[test]
+fn rollback_error_keeps_original() {
+    let mut script = script();
+    script.query_once("ROLLBACK", &Response::error(CONSTRAINT_ERROR + 1,
+                                                   "rollback failed"));
+    let server = MockServer::start(script).unwrap();
+    let res = common::block_on(async {
+        let mut conn = params(&server).connect().await?;
+        let mut cli = conn.authenticate().await?;
+        Ok::<_, Error>(cli.transaction(&no_backoff(), |tx| {
+            Box::pin(async move {
+                tx.execute("INSERT Bad").await?;
+                Ok(())
+            })
+        }).await)
+    }).unwrap();
+    let err = res.unwrap_err();
+    assert_eq!(err.server_error().unwrap().code, CONSTRAINT_ERROR);
+}
+
+#[test]
+fn no_rollback_on_disconnect() {
+    let mut script = script();
+    script.query("INSERT Lost", Response::new().disconnect());
+    let server = MockServer::start(script).unwrap();
+    let res = common::block_on(async {
+        let mut conn = params(&server).connect().await?;
+        let mut cli = conn.authenticate().await?;
+        Ok::<_, Error>(cli.transaction(&no_backoff(), |tx| {
+            Box::pin(async move {
+                tx.execute("INSERT Lost").await?;
+                Ok(())
+            })
+        }).await)
+    }).unwrap();
+    assert!(res.unwrap_err().is_disconnect());
+    assert!(!server.received().iter().any(|msg| {
+        format!("{:?}", msg).contains("ROLLBACK")
+    }));
+}
+
+#[test]
+fn retry_serialization_error() {
+    let mut script = script();
+    script.query_once("COMMIT", &Response::error(SERIALIZATION_ERROR,
+                                                 "could not serialize"));
+    let server = MockServer::start(script).unwrap();
+    let mut attempts = 0;
+    common::block_on(async {
+        let mut conn = params(&server).connect().await?;
+        let mut cli = conn.authenticate().await?;
+        cli.transaction(&no_backoff(), |tx| {
+            attempts += 1;
+            Box::pin(async move {
+                tx.execute("INSERT Item").await?;
+                Ok(())
+            })
+        }).await
+    }).unwrap();
+    assert_eq!(attempts, 2);
+    assert_eq!(server.connections(), 1);
+}
+
+#[test]
+fn retry_gives_up() {
+    let mut script = script();
+    let error = Response::error(SERIALIZATION_ERROR, "could not serialize");
+    script.query_once("COMMIT", &error).query_once("COMMIT", &error);
+    let server = MockServer::start(script).unwrap();
+    let mut attempts = 0;
+    let res = common::block_on(async {
+        let mut conn = params(&server).connect().await?;
+        let mut cli = conn.authenticate().await?;
+        let mut options = no_backoff();
+        options.attempts(2);
+        Ok::<_, Error>(cli.transaction(&options, |_tx| {
+            attempts += 1;
+            Box::pin(async move { Ok(()) })
+        }).await)
+    }).unwrap();
+    assert_eq!(attempts, 2);
+    assert!(res.unwrap_err().is_transaction_conflict());
+}
+
+#[test]
+fn refuse_nested() {
+    let server = MockServer::start(script()).unwrap();
+    let mut called = false;
+    let res = common::block_on(async {
+        let mut conn = params(&server).connect().await?;
+        let mut cli = conn.authenticate().await?;
+        cli.execute(START).await?;
+        Ok::<_, Error>(cli.transaction(&no_backoff(), |_tx| {
+            called = true;
+            Box::pin(async move { Ok(()) })
+        }).await)
+    }).unwrap();
+    assert!(!called);
+    match res {
+        Err(Error::TransactionState { state }) => {
+            assert_eq!(state, Some(TransactionState::InTransaction));
+        }
+        res => panic!("unexpected result {:?}", res),
+    }
+}
@@ -0,0 +1,100 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn;
+
+use crate::attrib::FieldAttrs;
+
+
+pub fn derive_args(s: &syn::ItemStruct) -> syn::Result<TokenStream> {
+    let name = &s.ident;
+    let (impl_generics, ty_generics, where_clause) =
+        s.generics.split_for_impl();
+    let fields = match s.fields {
+        syn::Fields::Named(ref named) => named,
+        _ => {
+            return Err(syn::Error::new_spanned(&s.fields,
+                "only named fields are supported"));
+        }
+    };
+    let mut fieldname = Vec::new();
+    let mut fieldtype = Vec::new();
+    let mut argname = Vec::new();
+    let mut required = Vec::new();
+    for field in &fields.named {
+        let attrs = FieldAttrs::from_syn(&field.attrs)?;
+        if attrs.flatten {
+            return Err(syn::Error::new_spanned(field,
+                "flatten is not supported for query arguments"));
+        }
+        let ident = field.ident.clone().unwrap();
+        argname.push(attrs.rename.unwrap_or_else(|| {
+            syn::LitStr::new(&ident.to_string(), ident.span())
+        }));
+        required.push(!attrs.skip_if_absent);
+        fieldname.push(ident);
+        fieldtype.push(field.ty.clone());
+    }
+    let nfields = fieldname.len();
+    let argname = &argname;
+    let required = argname.iter().zip(required)
+        .filter(|(_, req)| *req).map(|(name, _)| name)
+        .collect::<Vec<_>>();
+
+    let expanded = quote! {
+        impl #impl_generics ::edgedb_protocol::query_args::QueryArgs
+            for #name #ty_generics
+            #where_clause
+        {
+            fn to_value(&self,
+                desc: &::edgedb_protocol::descriptors::InputTypedesc)
+                -> Result<
+                    ::edgedb_protocol::value::Value,
+                    ::edgedb_protocol::queryable::DescriptorMismatch,
+                >
+            {
+                use ::edgedb_protocol::descriptors::Descriptor::NamedTuple;
+                use ::edgedb_protocol::query_args::QueryArg;
+
+                let ctx = desc.as_queryable_context();
+                let elements = match desc.root() {
+                    NamedTuple(tuple) => &tuple.elements[..],
+                    _ if desc.is_empty_tuple() => &[][..],
+                    root => {
+                        return Err(ctx.wrong_type(root, "named arguments"));
+                    }
+                };
+                let mut fields = Vec::with_capacity(#nfields);
+                for el in elements {
+                    match &el.name[..] {
+                        #(
+                            #argname => {
+                                <#fieldtype as QueryArg>::check_descriptor(
+                                    &ctx, el.type_pos)
+                                    .map_err(|e| {
+                                        ctx.wrong_argument(#argname, e)
+                                    })?;
+                                fields.push(
+                                    QueryArg::to_value(&self.#fieldname));
+                            }
+                        )*
+                        _ => return Err(ctx.missing_argument(&el.name)),
+                    }
+                }
+                #(
+                    if !elements.iter().any(|el| el.name == #required) {
+                        return Err(ctx.extra_argument(#required));
+                    }
+                )*
+                if elements.is_empty() {
+                    return Ok(::edgedb_protocol::value::Value::Tuple(
+                        Vec::new()));
+                }
+                Ok(::edgedb_protocol::value::Value::NamedTuple {
+                    shape: elements.into(),
+                    fields,
+                })
+            }
+        }
+    };
+    Ok(expanded)
+}
@@ -0,0 +1,93 @@
+use syn::{self, Meta, NestedMeta, Lit};
+
+
+#[derive(Default)]
+pub struct FieldAttrs {
+    pub rename: Option<syn::LitStr>,
+    pub skip_if_absent: bool,
+    pub flatten: bool,
+}
+
+#[derive(Default)]
+pub struct ContainerAttrs {
+    pub type_name: Option<syn::LitStr>,
+}
+
+fn edgedb_attrs(attrs: &[syn::Attribute])
+    -> syn::Result<Vec<NestedMeta>>
+{
+    let mut res = Vec::new();
+    for attr in attrs {
+        if !attr.path.is_ident("edgedb") {
+            continue;
+        }
+        match attr.parse_meta()? {
+            Meta::List(list) => res.extend(list.nested),
+            meta => {
+                return Err(syn::Error::new_spanned(meta,
+                    "expected `#[edgedb(...)]`"));
+            }
+        }
+    }
+    Ok(res)
+}
+
+impl ContainerAttrs {
+    pub fn from_syn(attrs: &[syn::Attribute])
+        -> syn::Result<ContainerAttrs>
+    {
+        let mut res = ContainerAttrs::default();
+        for item in edgedb_attrs(attrs)? {
+            match item {
+                NestedMeta::Meta(Meta::NameValue(ref nv))
+                if nv.path.is_ident("type")
+                => match nv.lit {
+                    Lit::Str(ref s) => res.type_name = Some(s.clone()),
+                    ref lit => {
+                        return Err(syn::Error::new_spanned(lit,
+                            "string literal expected"));
+                    }
+                },
+                item => {
+                    return Err(syn::Error::new_spanned(item,
+                        "unknown edgedb attribute"));
+                }
+            }
+        }
+        Ok(res)
+    }
+}
+
+impl FieldAttrs {
+    pub fn from_syn(attrs: &[syn::Attribute]) -> syn::Result<FieldAttrs> {
+        let mut res = FieldAttrs::default();
+        for item in edgedb_attrs(attrs)? {
+            match item {
+                NestedMeta::Meta(Meta::Path(ref p))
+                if p.is_ident("skip_if_absent")
+                => {
+                    res.skip_if_absent = true;
+                }
+                NestedMeta::Meta(Meta::Path(ref p))
+                if p.is_ident("flatten")
+                => {
+                    res.flatten = true;
+                }
+                NestedMeta::Meta(Meta::NameValue(ref nv))
+                if nv.path.is_ident("rename")
+                => match nv.lit {
+                    Lit::Str(ref s) => res.rename = Some(s.clone()),
+                    ref lit => {
+                        return Err(syn::Error::new_spanned(lit,
+                            "string literal expected"));
+                    }
+                },
+                item => {
+                    return Err(syn::Error::new_spanned(item,
+                        "unknown edgedb attribute"));
+                }
+            }
+        }
+        Ok(res)
+    }
+}
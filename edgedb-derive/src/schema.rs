@@ -0,0 +1,226 @@
+//! Compile-time checking of structs against a schema snapshot
+//!
+//! The snapshot is a JSON file produced by `edgedb schema-snapshot`. It's
+//! looked up at `$EDGEDB_SCHEMA_SNAPSHOT` or at `edgedb-schema.json` in the
+//! root of the crate being compiled.
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use proc_macro2::{TokenStream, Span};
+use quote::quote;
+use serde::Deserialize;
+use syn;
+
+
+#[derive(Deserialize, Debug)]
+pub struct Snapshot {
+    pub object_types: Vec<ObjectType>,
+    #[serde(default)]
+    pub scalar_types: Vec<ScalarType>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ObjectType {
+    pub name: String,
+    #[serde(default)]
+    pub properties: Vec<Pointer>,
+    #[serde(default)]
+    pub links: Vec<Pointer>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Pointer {
+    pub name: String,
+    pub target: String,
+    #[serde(default)]
+    pub cardinality: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ScalarType {
+    pub name: String,
+    #[serde(default)]
+    pub ancestors: Vec<String>,
+    #[serde(default)]
+    pub enum_values: Option<Vec<String>>,
+}
+
+pub struct Field<'a> {
+    pub name: &'a syn::LitStr,
+    pub ty: &'a syn::Type,
+}
+
+/// Absolute path to the snapshot, relative paths in the environment are
+/// resolved against the crate root (as `include_bytes!` resolves them
+/// against the source file)
+fn snapshot_path() -> PathBuf {
+    let crate_root: PathBuf = env::var_os("CARGO_MANIFEST_DIR")
+        .map(|x| x.into())
+        .unwrap_or_default();
+    match env::var_os("EDGEDB_SCHEMA_SNAPSHOT") {
+        Some(path) => crate_root.join(path),
+        None => crate_root.join("edgedb-schema.json"),
+    }
+}
+
+fn load(span: Span) -> syn::Result<(PathBuf, Snapshot)> {
+    let path = snapshot_path();
+    let data = fs::read(&path).map_err(|e| {
+        syn::Error::new(span,
+            format!("can't read schema snapshot {:?}: {}", path, e))
+    })?;
+    let snapshot = serde_json::from_slice(&data).map_err(|e| {
+        syn::Error::new(span,
+            format!("can't parse schema snapshot {:?}: {}", path, e))
+    })?;
+    Ok((path, snapshot))
+}
+
+/// Name of the scalar type in the schema that rust type is decoded from
+fn rust_scalar(ty: &syn::Type) -> Option<&'static str> {
+    let path = match ty {
+        syn::Type::Path(path) if path.qself.is_none() => &path.path,
+        _ => return None,
+    };
+    let ident = path.segments.last()?.ident.to_string();
+    let name = match &ident[..] {
+        "String" => "std::str",
+        "Uuid" => "std::uuid",
+        "bool" => "std::bool",
+        "i16" => "std::int16",
+        "i32" => "std::int32",
+        "i64" => "std::int64",
+        "f32" => "std::float32",
+        "f64" => "std::float64",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Returns `T` if the type is `wrapper<T>`, e.g. `Vec<T>` or `Option<T>`
+fn unwrap_generic<'x>(ty: &'x syn::Type, wrapper: &str)
+    -> Option<&'x syn::Type>
+{
+    let path = match ty {
+        syn::Type::Path(path) if path.qself.is_none() => &path.path,
+        _ => return None,
+    };
+    let last = path.segments.last()?;
+    if last.ident != wrapper {
+        return None;
+    }
+    match &last.arguments {
+        syn::PathArguments::AngleBracketed(args) if args.args.len() == 1 => {
+            match &args.args[0] {
+                syn::GenericArgument::Type(inner) => Some(inner),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+impl Snapshot {
+    /// Resolves user-defined scalars to the base type they're encoded as
+    fn base_scalar<'x>(&'x self, name: &'x str) -> &'x str {
+        let name = if name.starts_with("array<") && name.ends_with(">") {
+            &name["array<".len()..name.len()-1]
+        } else {
+            name
+        };
+        if name.starts_with("std::") || name.starts_with("cal::") {
+            return name;
+        }
+        let scalar = match self.scalar_types.iter().find(|s| s.name == name) {
+            Some(scalar) => scalar,
+            None => return name,
+        };
+        if scalar.enum_values.is_some() {
+            return "enum";
+        }
+        scalar.ancestors.iter()
+            .find(|a| a.starts_with("std::") && *a != "std::anyscalar")
+            .map(|a| &a[..])
+            .unwrap_or(name)
+    }
+}
+
+pub fn check(type_name: &syn::LitStr, fields: &[Field])
+    -> syn::Result<TokenStream>
+{
+    let (path, snapshot) = load(type_name.span())?;
+    let type_name_str = type_name.value();
+    let obj = snapshot.object_types.iter()
+        .find(|t| t.name == type_name_str)
+        .ok_or_else(|| syn::Error::new_spanned(type_name,
+            format!("type {} is not found in schema snapshot {:?}",
+                type_name_str, path)))?;
+    let mut errors: Option<syn::Error> = None;
+    let mut add_error = |e: syn::Error| match errors {
+        Some(ref mut errors) => errors.combine(e),
+        None => errors = Some(e),
+    };
+    for field in fields {
+        let name = field.name.value();
+        if name == "__tid__" {
+            continue;
+        }
+        let option = unwrap_generic(field.ty, "Option");
+        let ty = option.unwrap_or(field.ty);
+        let vec_element = unwrap_generic(ty, "Vec");
+        let elty = vec_element.unwrap_or(ty);
+        let pointer = obj.properties.iter().chain(&obj.links)
+            .find(|p| p.name == name);
+        if let Some(pointer) = pointer {
+            let multi = pointer.cardinality.as_ref()
+                .map(|c| c == "MANY").unwrap_or(false);
+            if multi && vec_element.is_none() {
+                add_error(syn::Error::new_spanned(field.ty,
+                    format!("{}.{} is a multi pointer, Vec is expected",
+                        obj.name, name)));
+                continue;
+            }
+            if !multi && !pointer.required && option.is_none() {
+                add_error(syn::Error::new_spanned(field.ty,
+                    format!("{}.{} is not required, Option is expected",
+                        obj.name, name)));
+                continue;
+            }
+        }
+        if let Some(prop) = obj.properties.iter().find(|p| p.name == name) {
+            if let Some(rust) = rust_scalar(elty) {
+                let base = snapshot.base_scalar(&prop.target);
+                if base != rust {
+                    add_error(syn::Error::new_spanned(field.ty,
+                        format!("property {}.{} has type {}, \
+                            which can't be decoded into {}",
+                            obj.name, name, prop.target, rust)));
+                }
+            }
+        } else if obj.links.iter().any(|p| p.name == name) {
+            if rust_scalar(elty).is_some() {
+                add_error(syn::Error::new_spanned(field.ty,
+                    format!("{}.{} is a link, but scalar type is used",
+                        obj.name, name)));
+            }
+        } else {
+            add_error(syn::Error::new_spanned(field.name,
+                format!("type {} has no property or link {:?}",
+                    obj.name, name)));
+        }
+    }
+    if let Some(errors) = errors {
+        return Err(errors);
+    }
+    // Makes cargo rebuild the crate when snapshot changes
+    let include = path.to_str().map(|p| {
+        let path = syn::LitStr::new(p, type_name.span());
+        quote! {
+            const _: &[u8] = include_bytes!(#path);
+        }
+    });
+    Ok(quote! { #include })
+}
@@ -0,0 +1,333 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn;
+
+use crate::attrib::{ContainerAttrs, FieldAttrs};
+use crate::schema;
+
+
+struct Field {
+    name: syn::Ident,
+    str_name: syn::LitStr,
+    ty: syn::Type,
+    flatten: bool,
+}
+
+fn skip_element() -> TokenStream {
+    quote! {
+        ::snafu::ensure!(
+            ::bytes::buf::Buf::remaining(buf) >= 8,
+            ::edgedb_protocol::errors::Underflow);
+        let _reserved = ::bytes::buf::Buf::get_i32(buf);
+        let len = ::bytes::buf::Buf::get_u32(buf) as usize;
+        ::snafu::ensure!(
+            ::bytes::buf::Buf::remaining(buf) >= len,
+            ::edgedb_protocol::errors::Underflow);
+        ::bytes::buf::Buf::advance(buf, len);
+    }
+}
+
+fn is_type_param(ty: &syn::Type, param: &syn::Ident) -> bool {
+    match ty {
+        syn::Type::Path(path) => {
+            path.qself.is_none() && path.path.is_ident(param)
+        }
+        _ => false,
+    }
+}
+
+fn uses_type_param(ty: &syn::Type, param: &syn::Ident) -> bool {
+    use syn::{Type, PathArguments, GenericArgument};
+    match ty {
+        Type::Path(path) => {
+            if is_type_param(ty, param) {
+                return true;
+            }
+            if let Some(qself) = &path.qself {
+                if uses_type_param(&qself.ty, param) {
+                    return true;
+                }
+            }
+            path.path.segments.iter().any(|seg| match &seg.arguments {
+                PathArguments::AngleBracketed(args) => {
+                    args.args.iter().any(|arg| match arg {
+                        GenericArgument::Type(ty) => {
+                            uses_type_param(ty, param)
+                        }
+                        _ => false,
+                    })
+                }
+                _ => false,
+            })
+        }
+        Type::Array(arr) => uses_type_param(&arr.elem, param),
+        Type::Slice(slice) => uses_type_param(&slice.elem, param),
+        Type::Reference(r) => uses_type_param(&r.elem, param),
+        Type::Paren(p) => uses_type_param(&p.elem, param),
+        Type::Group(g) => uses_type_param(&g.elem, param),
+        Type::Tuple(tuple) => {
+            tuple.elems.iter().any(|ty| uses_type_param(ty, param))
+        }
+        _ => false,
+    }
+}
+
+fn add_bounds(s: &syn::ItemStruct, fields: &[Field]) -> syn::Generics {
+    let mut generics = s.generics.clone();
+    let params = generics.type_params()
+        .map(|p| p.ident.clone())
+        .collect::<Vec<_>>();
+    let where_clause = generics.make_where_clause();
+    for param in &params {
+        // parameters only used in projections like `T::Value` are left
+        // alone, the type itself doesn't need to be queryable
+        let used = fields.iter().any(|f| uses_type_param(&f.ty, param));
+        if used {
+            where_clause.predicates.push(syn::parse_quote! {
+                #param: ::edgedb_protocol::queryable::Queryable
+            });
+        }
+        let flattened = fields.iter()
+            .any(|f| f.flatten && is_type_param(&f.ty, param));
+        if flattened {
+            where_clause.predicates.push(syn::parse_quote! {
+                #param: ::edgedb_protocol::queryable::FlattenFields
+            });
+        }
+    }
+    generics
+}
+
+pub fn derive_struct(s: &syn::ItemStruct) -> syn::Result<TokenStream> {
+    let name = &s.ident;
+    let named = match s.fields {
+        syn::Fields::Named(ref named) => named,
+        _ => {
+            return Err(syn::Error::new_spanned(&s.fields,
+                "only named fields are supported"));
+        }
+    };
+    let container = ContainerAttrs::from_syn(&s.attrs)?;
+    let mut fields = Vec::with_capacity(named.named.len());
+    for field in &named.named {
+        let attrs = FieldAttrs::from_syn(&field.attrs)?;
+        if attrs.skip_if_absent {
+            return Err(syn::Error::new_spanned(field,
+                "skip_if_absent is only supported for query arguments"));
+        }
+        let name = field.ident.clone().unwrap();
+        fields.push(Field {
+            str_name: attrs.rename.unwrap_or_else(|| {
+                syn::LitStr::new(&name.to_string(), name.span())
+            }),
+            name,
+            ty: field.ty.clone(),
+            flatten: attrs.flatten,
+        });
+    }
+    let schema_check = match container.type_name {
+        Some(ref type_name) => {
+            let plain = fields.iter()
+                .filter(|f| !f.flatten)
+                .map(|f| schema::Field { name: &f.str_name, ty: &f.ty })
+                .collect::<Vec<_>>();
+            Some(schema::check(type_name, &plain)?)
+        }
+        None => None,
+    };
+    let generics = add_bounds(s, &fields);
+    let (impl_generics, ty_generics, where_clause) =
+        generics.split_for_impl();
+
+    let nplain = fields.iter().filter(|f| !f.flatten).count();
+    let plain_names = fields.iter()
+        .filter(|f| !f.flatten)
+        .map(|f| &f.str_name)
+        .collect::<Vec<_>>();
+    let flat_types = fields.iter()
+        .filter(|f| f.flatten)
+        .map(|f| &f.ty)
+        .collect::<Vec<_>>();
+    // explicit `id` and `__tid__` may come from flattened fields, so
+    // whether they are also sent implicitly is only known at runtime
+    let implicit_fields = quote! {
+        let has_type_id = <Self as
+            ::edgedb_protocol::queryable::FlattenFields>
+            ::has_field("__tid__");
+        let has_id = <Self as
+            ::edgedb_protocol::queryable::FlattenFields>
+            ::has_field("id");
+        let implicit_fields =
+            if has_type_id { 0 } else { 1 } +
+            if has_id { 0 } else { 1 };
+    };
+    let skip_element = skip_element();
+    let field_count = quote! {
+        #nplain #(
+            + <#flat_types as ::edgedb_protocol::queryable::FlattenFields>
+                ::field_count()
+        )*
+    };
+    let fieldname = fields.iter().map(|f| &f.name).collect::<Vec<_>>();
+    let decode_fields = fields.iter().map(|field| {
+        let Field { name, ty, .. } = field;
+        if field.flatten {
+            quote! {
+                let #name =
+                    <#ty as ::edgedb_protocol::queryable::FlattenFields>
+                    ::decode_fields(buf)?;
+            }
+        } else {
+            quote! {
+                ::snafu::ensure!(
+                    ::bytes::buf::Buf::remaining(buf) >= 8,
+                    ::edgedb_protocol::errors::Underflow);
+                let _reserved = ::bytes::buf::Buf::get_i32(buf);
+                let len = ::bytes::buf::Buf::get_i32(buf);
+                let #name = if(len < 0) {
+                    <#ty as ::edgedb_protocol::queryable::Queryable>
+                        ::decode_missing()?
+                } else {
+                    let len = len as usize;
+                    ::snafu::ensure!(
+                        ::bytes::buf::Buf::remaining(buf) >= len,
+                        ::edgedb_protocol::errors::Underflow);
+                    let off = ::std::io::Cursor::position(buf) as usize;
+                    let mut chunk = ::std::io::Cursor::new(
+                        buf.get_ref().slice(off..off + len));
+                    ::bytes::buf::Buf::advance(buf, len);
+                    <#ty as ::edgedb_protocol::queryable::Queryable>
+                        ::decode(&mut chunk)?
+                };
+            }
+        }
+    });
+    let check_fields = fields.iter().map(|field| {
+        let Field { str_name, ty, .. } = field;
+        if field.flatten {
+            quote! {
+                let n = <#ty as ::edgedb_protocol::queryable::FlattenFields>
+                    ::field_count();
+                <#ty as ::edgedb_protocol::queryable::FlattenFields>
+                    ::check_fields(ctx, &elements[pos..pos+n])?;
+                pos += n;
+            }
+        } else {
+            quote! {
+                let el = &elements[pos];
+                if(el.name != #str_name) {
+                    return Err(ctx.wrong_field(&el.name, #str_name));
+                }
+                <#ty as ::edgedb_protocol::queryable::Queryable>
+                    ::check_descriptor(ctx, el.type_pos)?;
+                pos += 1;
+            }
+        }
+    });
+
+    let expanded = quote! {
+        #schema_check
+        impl #impl_generics ::edgedb_protocol::queryable::FlattenFields
+            for #name #ty_generics
+            #where_clause
+        {
+            fn field_count() -> usize {
+                #field_count
+            }
+            fn has_field(name: &str) -> bool {
+                false #(|| name == #plain_names)* #(
+                    || <#flat_types as
+                        ::edgedb_protocol::queryable::FlattenFields>
+                        ::has_field(name)
+                )*
+            }
+            fn decode_fields(buf: &mut ::std::io::Cursor<::bytes::Bytes>)
+                -> Result<Self, ::edgedb_protocol::errors::DecodeError>
+            {
+                #(#decode_fields)*
+                Ok(#name {
+                    #(
+                        #fieldname,
+                    )*
+                })
+            }
+            fn check_fields(
+                ctx: &::edgedb_protocol::queryable::DescriptorContext,
+                elements: &[::edgedb_protocol::descriptors::ShapeElement])
+                -> Result<(), ::edgedb_protocol::queryable::DescriptorMismatch>
+            {
+                let expected = #field_count;
+                if(elements.len() != expected) {
+                    return Err(ctx.field_number(elements.len(), expected));
+                }
+                #[allow(unused_mut)]
+                let mut pos = 0;
+                #(#check_fields)*
+                debug_assert_eq!(pos, expected);
+                Ok(())
+            }
+        }
+        impl #impl_generics ::edgedb_protocol::queryable::Queryable
+            for #name #ty_generics
+            #where_clause
+        {
+            fn decode_raw(buf: &mut ::std::io::Cursor<::bytes::Bytes>)
+                -> Result<Self, ::edgedb_protocol::errors::DecodeError>
+            {
+                ::snafu::ensure!(
+                    ::bytes::buf::Buf::remaining(buf) >= 4,
+                    ::edgedb_protocol::errors::Underflow);
+                let size = ::bytes::buf::Buf::get_u32(buf) as usize;
+                #implicit_fields
+                let nfields = implicit_fields + #field_count;
+                ::snafu::ensure!(size == nfields,
+                    ::edgedb_protocol::errors::ObjectSizeMismatch);
+
+                if(!has_type_id) {
+                    #skip_element
+                }
+                if(!has_id) {
+                    #skip_element
+                }
+
+                <Self as ::edgedb_protocol::queryable::FlattenFields>
+                    ::decode_fields(buf)
+            }
+            fn check_descriptor(
+                ctx: &::edgedb_protocol::queryable::DescriptorContext,
+                type_pos: ::edgedb_protocol::descriptors::TypePos)
+                -> Result<(), ::edgedb_protocol::queryable::DescriptorMismatch>
+            {
+                use ::edgedb_protocol::descriptors::Descriptor::ObjectShape;
+                let desc = ctx.get(type_pos)?;
+                let shape = match desc {
+                    ObjectShape(shape) => shape,
+                    _ => {
+                        return Err(ctx.wrong_type(desc, "str"))
+                    }
+                };
+
+                // TODO(tailhook) cache shape.id somewhere
+
+                #implicit_fields
+                let nfields = implicit_fields + #field_count;
+                if(shape.elements.len() != nfields) {
+                    return Err(ctx.field_number(
+                        shape.elements.len(), nfields));
+                }
+                if(!has_type_id && !shape.elements[0].flag_implicit) {
+                    return Err(ctx.expected("implicit __tid__"));
+                }
+                if(!has_id) {
+                    let n = if has_type_id { 0 } else { 1 };
+                    if(!shape.elements[n].flag_implicit) {
+                        return Err(ctx.expected("implicit id"));
+                    }
+                }
+                <Self as ::edgedb_protocol::queryable::FlattenFields>
+                    ::check_fields(ctx, &shape.elements[implicit_fields..])
+            }
+        }
+    };
+    Ok(expanded)
+}
@@ -0,0 +1,171 @@
+use std::error::Error;
+
+use bytes::{Bytes, BytesMut, BufMut};
+
+use edgedb_derive::QueryArgs;
+use edgedb_protocol::descriptors::InputTypedesc;
+use edgedb_protocol::query_args::QueryArgs;
+use edgedb_protocol::queryable::DescriptorMismatch;
+use edgedb_protocol::server_message::{CommandDataDescription, Cardinality};
+use edgedb_protocol::value::Value;
+
+
+#[derive(QueryArgs)]
+struct Search {
+    name: String,
+    #[edgedb(rename="limit")]
+    max_items: i64,
+}
+
+#[derive(QueryArgs)]
+struct Filter {
+    name: String,
+    #[edgedb(skip_if_absent)]
+    limit: i64,
+}
+
+#[derive(QueryArgs)]
+struct Rename {
+    name: String,
+    new_name: Option<String>,
+}
+
+fn base_scalar(buf: &mut BytesMut, id: u128) {
+    buf.put_u8(2);
+    buf.extend(&id.to_be_bytes()[..]);
+}
+
+fn named_tuple(buf: &mut BytesMut, elements: &[(&str, u16)]) {
+    buf.put_u8(5);
+    buf.extend(&0x1000u128.to_be_bytes()[..]);
+    buf.put_u16(elements.len() as u16);
+    for (name, pos) in elements {
+        buf.put_u32(name.len() as u32);
+        buf.extend(name.as_bytes());
+        buf.put_u16(*pos);
+    }
+}
+
+/// Input descriptor with str at position 0 and int64 at position 1
+fn input(elements: &[(&str, u16)]) -> Result<InputTypedesc, Box<dyn Error>> {
+    let mut buf = BytesMut::new();
+    base_scalar(&mut buf, 0x101);
+    base_scalar(&mut buf, 0x105);
+    named_tuple(&mut buf, elements);
+    let desc = CommandDataDescription {
+        headers: Default::default(),
+        result_cardinality: Cardinality::Many,
+        input_typedesc_id: "00000000-0000-0000-0000-000000001000".parse()?,
+        input_typedesc: buf.freeze(),
+        output_typedesc_id: "00000000-0000-0000-0000-000000000000".parse()?,
+        output_typedesc: Bytes::new(),
+    };
+    Ok(desc.input()?)
+}
+
+#[test]
+fn encode() -> Result<(), Box<dyn Error>> {
+    let desc = input(&[("limit", 1), ("name", 0)])?;
+    let args = Search { name: "user".into(), max_items: 10 };
+    let value = args.to_value(&desc)?;
+    match &value {
+        Value::NamedTuple { shape, fields } => {
+            assert_eq!(shape.elements.iter().map(|e| &e.name[..])
+                       .collect::<Vec<_>>(),
+                       vec!["limit", "name"]);
+            assert_eq!(fields,
+                &vec![Value::Int64(10), Value::Str("user".into())]);
+        }
+        _ => panic!("unexpected value {:?}", value),
+    }
+    let mut buf = BytesMut::new();
+    desc.build_codec()?.encode(&mut buf, &value)?;
+    assert_eq!(&buf[..],
+        &b"\0\0\0\x02\0\0\0\x08\0\0\0\0\0\0\0\x0a\0\0\0\x04user"[..]);
+    Ok(())
+}
+
+#[test]
+fn missing() -> Result<(), Box<dyn Error>> {
+    let desc = input(&[("name", 0), ("offset", 1), ("limit", 1)])?;
+    let args = Search { name: "user".into(), max_items: 10 };
+    match args.to_value(&desc) {
+        Err(DescriptorMismatch::MissingArgument { name }) => {
+            assert_eq!(name, "offset");
+        }
+        res => panic!("unexpected result {:?}", res),
+    }
+    Ok(())
+}
+
+#[test]
+fn extra() -> Result<(), Box<dyn Error>> {
+    let desc = input(&[("name", 0)])?;
+    let args = Search { name: "user".into(), max_items: 10 };
+    match args.to_value(&desc) {
+        Err(DescriptorMismatch::ExtraArgument { name }) => {
+            assert_eq!(name, "limit");
+        }
+        res => panic!("unexpected result {:?}", res),
+    }
+    Ok(())
+}
+
+#[test]
+fn wrong_type() -> Result<(), Box<dyn Error>> {
+    let desc = input(&[("name", 1), ("limit", 1)])?;
+    let args = Search { name: "user".into(), max_items: 10 };
+    match args.to_value(&desc) {
+        Err(DescriptorMismatch::WrongArgumentType { name, expected, .. }) => {
+            assert_eq!(name, "name");
+            assert_eq!(expected, "str");
+        }
+        res => panic!("unexpected result {:?}", res),
+    }
+    Ok(())
+}
+
+#[test]
+fn skip_if_absent() -> Result<(), Box<dyn Error>> {
+    let args = Filter { name: "user".into(), limit: 10 };
+    let value = args.to_value(&input(&[("name", 0)])?)?;
+    match &value {
+        Value::NamedTuple { fields, .. } => {
+            assert_eq!(fields, &vec![Value::Str("user".into())]);
+        }
+        _ => panic!("unexpected value {:?}", value),
+    }
+    let value = args.to_value(&input(&[("name", 0), ("limit", 1)])?)?;
+    match &value {
+        Value::NamedTuple { fields, .. } => {
+            assert_eq!(fields.len(), 2);
+        }
+        _ => panic!("unexpected value {:?}", value),
+    }
+    Ok(())
+}
+
+#[test]
+fn optional_value() -> Result<(), Box<dyn Error>> {
+    let desc = input(&[("name", 0), ("new_name", 0)])?;
+    let args = Rename { name: "user".into(), new_name: None };
+    let value = args.to_value(&desc)?;
+    match &value {
+        Value::NamedTuple { fields, .. } => {
+            assert_eq!(fields,
+                &vec![Value::Str("user".into()), Value::Nothing]);
+        }
+        _ => panic!("unexpected value {:?}", value),
+    }
+    let mut buf = BytesMut::new();
+    desc.build_codec()?.encode(&mut buf, &value)?;
+    assert_eq!(&buf[..],
+        &b"\0\0\0\x02\0\0\0\x04user\xff\xff\xff\xff"[..]);
+
+    let args = Rename { name: "user".into(), new_name: Some("x".into()) };
+    let mut buf = BytesMut::new();
+    desc.build_codec()?.encode(&mut buf, &args.to_value(&desc)?)?;
+    assert_eq!(&buf[..],
+        &b"\0\0\0\x02\0\0\0\x04user\0\0\0\x01x"[..]);
+    Ok(())
+}
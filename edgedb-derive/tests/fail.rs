@@ -1,5 +1,8 @@
 #[test]
 fn fail() {
+    // test cases are compiled as a separate crate, without a snapshot
+    std::env::set_var("EDGEDB_SCHEMA_SNAPSHOT",
+        concat!(env!("CARGO_MANIFEST_DIR"), "/edgedb-schema.json"));
     let t = trybuild::TestCases::new();
     t.compile_fail("tests/fail/*.rs");
 }
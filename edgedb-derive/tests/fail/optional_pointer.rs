@@ -0,0 +1,11 @@
+use edgedb_derive::Queryable;
+
+#[derive(Queryable)]
+#[edgedb(type="default::User")]
+struct User {
+    name: String,
+    nickname: String,
+}
+
+fn main() {
+}
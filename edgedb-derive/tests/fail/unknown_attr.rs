@@ -0,0 +1,10 @@
+use edgedb_derive::QueryArgs;
+
+#[derive(QueryArgs)]
+struct Args {
+    #[edgedb(skip)]
+    name: String,
+}
+
+fn main() {
+}
@@ -0,0 +1,267 @@
+use std::error::Error;
+use std::io::Cursor;
+
+use bytes::{Bytes, BytesMut, BufMut};
+
+use edgedb_derive::Queryable;
+use edgedb_protocol::descriptors::{OutputTypedesc, TypePos};
+use edgedb_protocol::queryable::{Queryable, DescriptorMismatch};
+use edgedb_protocol::server_message::{CommandDataDescription, Cardinality};
+
+
+#[derive(Queryable, Debug, PartialEq)]
+struct Timestamps {
+    created_at: i64,
+    updated_at: i64,
+}
+
+#[derive(Queryable, Debug, PartialEq)]
+struct Item {
+    name: String,
+    #[edgedb(flatten)]
+    timestamps: Timestamps,
+}
+
+#[derive(Queryable, Debug, PartialEq)]
+struct Page<T> {
+    items: Vec<T>,
+    total: i64,
+}
+
+#[derive(Queryable, Debug, PartialEq)]
+struct Wrapper<T> {
+    #[edgedb(flatten)]
+    inner: T,
+}
+
+fn uuid(buf: &mut BytesMut, id: u128) {
+    buf.extend(&id.to_be_bytes()[..]);
+}
+
+fn shape(buf: &mut BytesMut, id: u128, elements: &[(&str, bool, u16)]) {
+    buf.put_u8(1);
+    uuid(buf, id);
+    buf.put_u16(elements.len() as u16);
+    for &(name, implicit, pos) in elements {
+        buf.put_u8(if implicit { 1 } else { 0 });
+        buf.put_u32(name.len() as u32);
+        buf.extend(name.as_bytes());
+        buf.put_u16(pos);
+    }
+}
+
+/// Typedesc of `Page { items: { name, created_at, updated_at }, total }`
+fn output(item_fields: &[(&str, bool, u16)])
+    -> Result<OutputTypedesc, Box<dyn Error>>
+{
+    let mut buf = BytesMut::new();
+    for id in &[0x100, 0x101, 0x105] {
+        buf.put_u8(2);
+        uuid(&mut buf, *id);
+    }
+    shape(&mut buf, 0x1000, item_fields);
+    buf.put_u8(0);
+    uuid(&mut buf, 0x1001);
+    buf.put_u16(3);
+    shape(&mut buf, 0x1002, &[
+        ("__tid__", true, 0),
+        ("id", true, 0),
+        ("items", false, 4),
+        ("total", false, 2),
+    ]);
+    let desc = CommandDataDescription {
+        headers: Default::default(),
+        result_cardinality: Cardinality::Many,
+        input_typedesc_id: "00000000-0000-0000-0000-0000000000ff".parse()?,
+        input_typedesc: Bytes::new(),
+        output_typedesc_id: "00000000-0000-0000-0000-000000001002".parse()?,
+        output_typedesc: buf.freeze(),
+    };
+    Ok(desc.output()?)
+}
+
+const ITEM_FIELDS: &[(&str, bool, u16)] = &[
+    ("__tid__", true, 0),
+    ("id", true, 0),
+    ("name", false, 1),
+    ("created_at", false, 2),
+    ("updated_at", false, 2),
+];
+
+fn element(buf: &mut BytesMut, data: &[u8]) {
+    buf.put_i32(0);
+    buf.put_u32(data.len() as u32);
+    buf.extend(data);
+}
+
+fn item(name: &str, created_at: i64, updated_at: i64) -> BytesMut {
+    let mut buf = BytesMut::new();
+    buf.put_u32(5);
+    element(&mut buf, &[0; 16]);
+    element(&mut buf, &[0; 16]);
+    element(&mut buf, name.as_bytes());
+    element(&mut buf, &created_at.to_be_bytes());
+    element(&mut buf, &updated_at.to_be_bytes());
+    buf
+}
+
+#[test]
+fn check_generic() -> Result<(), Box<dyn Error>> {
+    let desc = output(ITEM_FIELDS)?;
+    Page::<Item>::check_descriptor(
+        &desc.as_queryable_context(), desc.root_pos().unwrap())?;
+    Ok(())
+}
+
+#[test]
+fn check_flatten_mismatch() -> Result<(), Box<dyn Error>> {
+    let desc = output(&[
+        ("__tid__", true, 0),
+        ("id", true, 0),
+        ("name", false, 1),
+        ("created_at", false, 2),
+        ("modified_at", false, 2),
+    ])?;
+    let res = Page::<Item>::check_descriptor(
+        &desc.as_queryable_context(), desc.root_pos().unwrap());
+    match res {
+        Err(DescriptorMismatch::WrongField { .. }) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+    Ok(())
+}
+
+#[test]
+fn decode_generic() -> Result<(), Box<dyn Error>> {
+    let items = [item("first", 1, 2), item("second", 3, 4)];
+    let mut set = BytesMut::new();
+    set.put_u32(1);  // ndims
+    set.put_u32(0);  // reserved0
+    set.put_u32(0);  // reserved1
+    set.put_u32(items.len() as u32);
+    set.put_u32(1);  // lower
+    for item in &items {
+        set.put_u32(item.len() as u32);
+        set.extend(&item[..]);
+    }
+    let mut buf = BytesMut::new();
+    buf.put_u32(4);
+    element(&mut buf, &[0; 16]);
+    element(&mut buf, &[0; 16]);
+    element(&mut buf, &set[..]);
+    element(&mut buf, &2i64.to_be_bytes());
+
+    let page = Page::<Item>::decode(&mut Cursor::new(buf.freeze()))?;
+    assert_eq!(page, Page {
+        items: vec![
+            Item {
+                name: "first".into(),
+                timestamps: Timestamps { created_at: 1, updated_at: 2 },
+            },
+            Item {
+                name: "second".into(),
+                timestamps: Timestamps { created_at: 3, updated_at: 4 },
+            },
+        ],
+        total: 2,
+    });
+    Ok(())
+}
+
+#[test]
+fn flatten_generic() -> Result<(), Box<dyn Error>> {
+    let item = item("first", 1, 2);
+    let value = Wrapper::<Item>::decode(&mut Cursor::new(item.freeze()))?;
+    assert_eq!(value.inner.name, "first");
+    assert_eq!(value.inner.timestamps.updated_at, 2);
+    Ok(())
+}
+
+#[derive(Queryable, Debug, PartialEq)]
+struct Nickname {
+    nickname: Option<String>,
+}
+
+#[test]
+fn decode_optional() -> Result<(), Box<dyn Error>> {
+    let mut buf = BytesMut::new();
+    buf.put_u32(3);
+    element(&mut buf, &[0; 16]);
+    element(&mut buf, &[0; 16]);
+    buf.put_i32(0);
+    buf.put_i32(-1);
+    let value = Nickname::decode(&mut Cursor::new(buf.freeze()))?;
+    assert_eq!(value, Nickname { nickname: None });
+
+    let mut buf = BytesMut::new();
+    buf.put_u32(3);
+    element(&mut buf, &[0; 16]);
+    element(&mut buf, &[0; 16]);
+    element(&mut buf, b"nick");
+    let value = Nickname::decode(&mut Cursor::new(buf.freeze()))?;
+    assert_eq!(value, Nickname { nickname: Some("nick".into()) });
+    Ok(())
+}
+
+#[derive(Queryable, Debug, PartialEq)]
+struct Ident {
+    id: uuid::Uuid,
+}
+
+#[derive(Queryable, Debug, PartialEq)]
+struct Named {
+    #[edgedb(flatten)]
+    ident: Ident,
+    name: String,
+}
+
+#[test]
+fn flatten_id() -> Result<(), Box<dyn Error>> {
+    let desc = output(&[
+        ("__tid__", true, 0),
+        ("id", false, 0),
+        ("name", false, 1),
+    ])?;
+    // the item shape is the fourth descriptor, after three scalars
+    Named::check_descriptor(&desc.as_queryable_context(), TypePos(3))?;
+
+    let mut buf = BytesMut::new();
+    buf.put_u32(3);
+    element(&mut buf, &[0; 16]);
+    element(&mut buf, &7u128.to_be_bytes());
+    element(&mut buf, b"seven");
+    let value = Named::decode(&mut Cursor::new(buf.freeze()))?;
+    assert_eq!(value, Named {
+        ident: Ident { id: uuid::Uuid::from_u128(7) },
+        name: "seven".into(),
+    });
+    Ok(())
+}
+
+trait Kind {
+    type Value: Queryable;
+}
+
+#[derive(Debug, PartialEq)]
+struct Text;
+
+impl Kind for Text {
+    type Value = String;
+}
+
+#[derive(Queryable, Debug, PartialEq)]
+struct Tagged<K: Kind> {
+    value: K::Value,
+}
+
+#[test]
+fn projection_param() -> Result<(), Box<dyn Error>> {
+    let mut buf = BytesMut::new();
+    buf.put_u32(3);
+    element(&mut buf, &[0; 16]);
+    element(&mut buf, &[0; 16]);
+    element(&mut buf, b"text");
+    let value = Tagged::<Text>::decode(&mut Cursor::new(buf.freeze()))?;
+    assert_eq!(value.value, "text");
+    Ok(())
+}
@@ -0,0 +1,27 @@
+//! Structs are checked against `edgedb-schema.json` in the crate root
+use edgedb_derive::Queryable;
+
+
+#[derive(Queryable)]
+#[edgedb(type="default::User")]
+#[allow(dead_code)]
+struct User {
+    id: uuid::Uuid,
+    name: String,
+    #[edgedb(rename="age")]
+    user_age: i32,
+    nickname: Option<String>,
+    tags: Vec<String>,
+}
+
+#[derive(Queryable)]
+#[edgedb(type="default::User")]
+#[allow(dead_code)]
+struct Friends {
+    name: String,
+    friends: Vec<User>,
+}
+
+#[test]
+fn compiles() {
+}
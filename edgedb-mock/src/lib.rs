@@ -0,0 +1,31 @@
+//! In-process fake EdgeDB server for testing clients
+//!
+//! The server listens on a random port (or a unix socket), performs the
+//! handshake and, optionally, SCRAM authentication, and then answers
+//! queries using the responses from the `Script`. Queries are matched by
+//! their exact text. All messages received from clients are stored, so the
+//! test can check them afterwards.
+//!
+//! Server runs on the async-std executor, and it's started synchronously,
+//! so it works with any runtime (or blocking) client.
+//!
+//! ```rust,no_run
+//! use edgedb_protocol::codec::STD_INT64;
+//! use edgedb_mock::{MockServer, Script, Response};
+//!
+//! let mut script = Script::new();
+//! script.password("edgedb", "secret");
+//! script.query("SELECT 1", Response::base_scalar(STD_INT64)
+//!     .row(1i64.to_be_bytes().to_vec())
+//!     .status("SELECT"));
+//! let server = MockServer::start(script).unwrap();
+//! // connect a client to `server.port()` and run the query
+//! server.assert_received(&["ClientHandshake",
+//!     "AuthenticationSaslInitialResponse", "AuthenticationSaslResponse",
+//!     "Prepare", "DescribeStatement", "Sync", "Execute", "Sync"]);
+//! ```
+mod script;
+mod server;
+
+pub use script::{Script, Response};
+pub use server::{MockServer, Address, message_name};
@@ -0,0 +1,233 @@
+use std::collections::{HashMap, VecDeque};
+
+use bytes::{Bytes, BytesMut, BufMut};
+use uuid::Uuid;
+
+use edgedb_protocol::server_message::{Cardinality, CommandDataDescription};
+use edgedb_protocol::server_message::{ErrorResponse, ErrorSeverity};
+use edgedb_protocol::server_message::{LogMessage, MessageSeverity};
+use edgedb_protocol::server_message::TransactionState;
+
+
+/// Id of the empty tuple, the input of the queries without arguments
+const EMPTY_TUPLE: Uuid = Uuid::from_u128(0xFF);
+
+/// Behavior of the mock server
+///
+/// Queries that are not in the script are answered by an error.
+#[derive(Debug, Clone)]
+pub struct Script {
+    pub(crate) password: Option<(String, String)>,
+    pub(crate) params: Vec<(Bytes, Bytes)>,
+    pub(crate) key_data: [u8; 32],
+    pub(crate) responses: HashMap<String, Response>,
+    pub(crate) queued: HashMap<String, VecDeque<Response>>,
+}
+
+/// Response to a query, used both for prepared statements and scripts
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub(crate) description: CommandDataDescription,
+    pub(crate) rows: Vec<Bytes>,
+    pub(crate) status: Bytes,
+    pub(crate) error: Option<ErrorResponse>,
+    pub(crate) logs: Vec<LogMessage>,
+    pub(crate) transaction_state: Option<TransactionState>,
+    pub(crate) disconnect: bool,
+    pub(crate) close_after: bool,
+    pub(crate) stall: bool,
+}
+
+
+impl Script {
+    pub fn new() -> Script {
+        Script {
+            password: None,
+            params: Vec::new(),
+            key_data: [0x5a; 32],
+            responses: HashMap::new(),
+            queued: HashMap::new(),
+        }
+    }
+    /// Require SCRAM-SHA-256 authentication, by default any user is
+    /// accepted without a password
+    pub fn password(&mut self, user: impl Into<String>,
+        password: impl Into<String>)
+        -> &mut Self
+    {
+        self.password = Some((user.into(), password.into()));
+        self
+    }
+    /// Send `ParameterStatus` on connect
+    pub fn server_param(&mut self, name: impl Into<Bytes>,
+        value: impl Into<Bytes>)
+        -> &mut Self
+    {
+        self.params.push((name.into(), value.into()));
+        self
+    }
+    /// Key data sent on connect, used by clients to cancel queries
+    pub fn key_data(&mut self, key_data: [u8; 32]) -> &mut Self {
+        self.key_data = key_data;
+        self
+    }
+    /// Answer `query` with `response`, replaces previous response if any
+    pub fn query(&mut self, query: impl Into<String>, response: &Response)
+        -> &mut Self
+    {
+        self.responses.insert(query.into(), response.clone());
+        self
+    }
+    /// Answer the next execution of `query` with `response`, subsequent
+    /// executions get the next queued response or the one set by `query`
+    ///
+    /// Prepare and describe always use the response set by `query`. The
+    /// queue is shared by all connections.
+    pub fn query_once(&mut self, query: impl Into<String>,
+        response: &Response)
+        -> &mut Self
+    {
+        self.queued.entry(query.into()).or_insert_with(VecDeque::new)
+            .push_back(response.clone());
+        self
+    }
+}
+
+impl Default for Script {
+    fn default() -> Script {
+        Script::new()
+    }
+}
+
+impl Response {
+    /// Statement that returns no data and has `OK` status
+    pub fn new() -> Response {
+        Response {
+            description: CommandDataDescription {
+                headers: HashMap::new(),
+                result_cardinality: Cardinality::NoResult,
+                input_typedesc_id: EMPTY_TUPLE,
+                input_typedesc: empty_tuple_descriptor(),
+                output_typedesc_id: Uuid::from_u128(0),
+                output_typedesc: Bytes::new(),
+            },
+            rows: Vec::new(),
+            status: Bytes::from_static(b"OK"),
+            error: None,
+            logs: Vec::new(),
+            transaction_state: None,
+            disconnect: false,
+            close_after: false,
+            stall: false,
+        }
+    }
+    /// Query returning a set of the base scalar type, e.g.
+    /// `codec::STD_INT64`
+    pub fn base_scalar(type_id: Uuid) -> Response {
+        let mut descriptor = BytesMut::with_capacity(17);
+        descriptor.put_u8(2);
+        descriptor.extend_from_slice(type_id.as_bytes());
+        let mut response = Response::new();
+        response.output(Cardinality::Many, type_id, descriptor.freeze());
+        response
+    }
+    /// Query that fails with an error
+    ///
+    /// Error is returned on prepare, like the compilation errors, or by
+    /// the script.
+    pub fn error(code: u32, message: impl Into<String>) -> Response {
+        let mut response = Response::new();
+        response.error = Some(ErrorResponse {
+            severity: ErrorSeverity::Error,
+            code,
+            message: message.into(),
+            attributes: HashMap::new(),
+        });
+        response
+    }
+    /// Type descriptor of the result
+    pub fn output(&mut self, cardinality: Cardinality, type_id: Uuid,
+        descriptor: impl Into<Bytes>)
+        -> &mut Self
+    {
+        self.description.result_cardinality = cardinality;
+        self.description.output_typedesc_id = type_id;
+        self.description.output_typedesc = descriptor.into();
+        self
+    }
+    /// Result cardinality reported on prepare
+    pub fn cardinality(&mut self, cardinality: Cardinality) -> &mut Self {
+        self.description.result_cardinality = cardinality;
+        self
+    }
+    /// Type descriptor of the arguments (empty tuple by default)
+    pub fn input(&mut self, type_id: Uuid, descriptor: impl Into<Bytes>)
+        -> &mut Self
+    {
+        self.description.input_typedesc_id = type_id;
+        self.description.input_typedesc = descriptor.into();
+        self
+    }
+    /// Add an encoded element of the result, each sent in its own `Data`
+    pub fn row(&mut self, data: impl Into<Bytes>) -> &mut Self {
+        self.rows.push(data.into());
+        self
+    }
+    /// Status of the `CommandComplete` (`OK` by default)
+    pub fn status(&mut self, status: impl Into<Bytes>) -> &mut Self {
+        self.status = status.into();
+        self
+    }
+    /// Send a log message before the result
+    pub fn log(&mut self, severity: MessageSeverity, text: impl Into<String>)
+        -> &mut Self
+    {
+        self.logs.push(LogMessage {
+            severity,
+            code: 0,
+            text: text.into(),
+            attributes: HashMap::new(),
+        });
+        self
+    }
+    /// Transaction state after the query, e.g. for `START TRANSACTION`
+    pub fn transaction_state(&mut self, state: TransactionState)
+        -> &mut Self
+    {
+        self.transaction_state = Some(state);
+        self
+    }
+    /// Close the connection instead of executing the query, only once
+    ///
+    /// This is once per server, not per connection: subsequent executions
+    /// on any connection (e.g. after reconnect) return the response.
+    pub fn disconnect(&mut self) -> &mut Self {
+        self.disconnect = true;
+        self
+    }
+    /// Close the connection after the response is complete, e.g. like a
+    /// server closing an idle connection
+    pub fn close_after(&mut self) -> &mut Self {
+        self.close_after = true;
+        self
+    }
+    /// Never answer the script, e.g. to test timeouts
+    pub fn stall(&mut self) -> &mut Self {
+        self.stall = true;
+        self
+    }
+}
+
+impl Default for Response {
+    fn default() -> Response {
+        Response::new()
+    }
+}
+
+fn empty_tuple_descriptor() -> Bytes {
+    let mut descriptor = BytesMut::with_capacity(19);
+    descriptor.put_u8(4);
+    descriptor.extend_from_slice(EMPTY_TUPLE.as_bytes());
+    descriptor.put_u16(0);
+    descriptor.freeze()
+}
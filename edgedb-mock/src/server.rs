@@ -0,0 +1,513 @@
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::io;
+use std::net::SocketAddr;
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+use std::str;
+use std::sync::{Arc, Mutex};
+
+use async_std::io::{Read, Write};
+use async_std::io::prelude::{ReadExt, WriteExt};
+use async_std::task;
+use bytes::{Bytes, BytesMut};
+use scram::{ScramServer, AuthenticationProvider, AuthenticationStatus};
+use scram::{PasswordInfo, hash_password};
+
+use edgedb_protocol::client_message::ClientMessage;
+use edgedb_protocol::server_message::{ServerMessage, Authentication};
+use edgedb_protocol::server_message::{ReadyForCommand, TransactionState};
+use edgedb_protocol::server_message::{ServerKeyData, ParameterStatus};
+use edgedb_protocol::server_message::{CommandComplete, PrepareComplete};
+use edgedb_protocol::server_message::{Data, ErrorResponse, ErrorSeverity};
+
+use crate::script::{Script, Response};
+
+
+const PROTOCOL_ERROR: u32 = 0x_03_00_00_00;
+const AUTHENTICATION_ERROR: u32 = 0x_07_01_00_00;
+const SCRAM_ITERATIONS: u16 = 4096;
+const SCRAM_SALT: &[u8] = b"edgedb-mock-salt";
+
+/// Address the mock server listens on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Address {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+/// Running mock server
+///
+/// The server is started on a background task and stops when the process
+/// exits.
+#[derive(Debug)]
+pub struct MockServer {
+    address: Address,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug)]
+struct State {
+    script: Script,
+    received: Vec<ClientMessage>,
+    connections: usize,
+}
+
+struct Provider {
+    user: String,
+    password: String,
+}
+
+struct Connection<S> {
+    stream: S,
+    state: Arc<Mutex<State>>,
+    outbuf: BytesMut,
+    transaction_state: TransactionState,
+    /// Query texts of the prepared statements by name
+    statements: HashMap<Bytes, String>,
+    /// Skipping messages up to `Sync` after error
+    skip_to_sync: bool,
+    /// Closing after the next `ReadyForCommand`
+    closing: bool,
+}
+
+
+impl MockServer {
+    /// Starts the server on a random port of `127.0.0.1`
+    pub fn start(script: Script) -> io::Result<MockServer> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let address = Address::Tcp(listener.local_addr()?);
+        let listener = async_std::net::TcpListener::from(listener);
+        let server = MockServer::new(address, script);
+        let state = server.state.clone();
+        task::spawn(async move {
+            loop {
+                let (stream, _) = match listener.accept().await {
+                    Ok(pair) => pair,
+                    Err(_) => continue,
+                };
+                task::spawn(Connection::new(stream, state.clone()).run());
+            }
+        });
+        Ok(server)
+    }
+    /// Starts the server on the unix socket at `path`
+    #[cfg(unix)]
+    pub fn start_unix(script: Script, path: impl Into<PathBuf>)
+        -> io::Result<MockServer>
+    {
+        let path = path.into();
+        let listener = std::os::unix::net::UnixListener::bind(&path)?;
+        let listener = async_std::os::unix::net::UnixListener::from(
+            listener);
+        let server = MockServer::new(Address::Unix(path), script);
+        let state = server.state.clone();
+        task::spawn(async move {
+            loop {
+                let (stream, _) = match listener.accept().await {
+                    Ok(pair) => pair,
+                    Err(_) => continue,
+                };
+                task::spawn(Connection::new(stream, state.clone()).run());
+            }
+        });
+        Ok(server)
+    }
+    fn new(address: Address, script: Script) -> MockServer {
+        MockServer {
+            address,
+            state: Arc::new(Mutex::new(State {
+                script,
+                received: Vec::new(),
+                connections: 0,
+            })),
+        }
+    }
+    pub fn address(&self) -> &Address {
+        &self.address
+    }
+    /// TCP port of the server
+    ///
+    /// # Panics
+    ///
+    /// If server listens on a unix socket
+    pub fn port(&self) -> u16 {
+        match self.address {
+            Address::Tcp(addr) => addr.port(),
+            #[cfg(unix)]
+            Address::Unix(_) => panic!("mock server listens on unix socket"),
+        }
+    }
+    /// Number of connections accepted so far
+    pub fn connections(&self) -> usize {
+        self.state().connections
+    }
+    /// All messages received from all the connections in order
+    pub fn received(&self) -> Vec<ClientMessage> {
+        self.state().received.clone()
+    }
+    /// Checks the names of the received messages (see `message_name`)
+    ///
+    /// # Panics
+    ///
+    /// If the sequence is different
+    pub fn assert_received(&self, expected: &[&str]) {
+        let received = self.state().received.iter()
+            .map(message_name)
+            .collect::<Vec<_>>();
+        assert_eq!(received, expected, "messages received by mock server");
+    }
+    /// Forgets received messages, e.g. the ones sent on connect
+    pub fn clear_received(&self) {
+        self.state().received.clear();
+    }
+    fn state(&self) -> std::sync::MutexGuard<State> {
+        self.state.lock().expect("mock state is not poisoned")
+    }
+}
+
+/// Name of the message variant, e.g. `Prepare`
+pub fn message_name(message: &ClientMessage) -> &'static str {
+    use ClientMessage::*;
+
+    match message {
+        ClientHandshake(_) => "ClientHandshake",
+        ExecuteScript(_) => "ExecuteScript",
+        Prepare(_) => "Prepare",
+        DescribeStatement(_) => "DescribeStatement",
+        Execute(_) => "Execute",
+        UnknownMessage(..) => "UnknownMessage",
+        AuthenticationSaslInitialResponse(_)
+            => "AuthenticationSaslInitialResponse",
+        AuthenticationSaslResponse(_) => "AuthenticationSaslResponse",
+        Sync => "Sync",
+        Flush => "Flush",
+        Terminate => "Terminate",
+        CancelRequest(_) => "CancelRequest",
+        _ => "Unknown",
+    }
+}
+
+impl AuthenticationProvider for Provider {
+    fn get_password_for(&self, user: &str) -> Option<PasswordInfo> {
+        if user != self.user {
+            return None;
+        }
+        let iterations = NonZeroU32::new(SCRAM_ITERATIONS.into())
+            .expect("iterations are non-zero");
+        let hashed = hash_password(&self.password, iterations, SCRAM_SALT);
+        Some(PasswordInfo::new(hashed.to_vec(), SCRAM_ITERATIONS,
+                               SCRAM_SALT.to_vec()))
+    }
+}
+
+impl<S: Read + Write + Unpin> Connection<S> {
+    fn new(stream: S, state: Arc<Mutex<State>>) -> Connection<S> {
+        Connection {
+            stream,
+            state,
+            outbuf: BytesMut::with_capacity(8192),
+            transaction_state: TransactionState::NotInTransaction,
+            statements: HashMap::new(),
+            skip_to_sync: false,
+            closing: false,
+        }
+    }
+    fn state(&self) -> std::sync::MutexGuard<State> {
+        self.state.lock().expect("mock state is not poisoned")
+    }
+    async fn run(mut self) {
+        self.state().connections += 1;
+        // connection errors are visible to the client anyway
+        self._run().await.ok();
+    }
+    async fn _run(&mut self) -> io::Result<()> {
+        match self.message().await? {
+            Some(ClientMessage::ClientHandshake(_)) => {}
+            // cancel requests are only recorded
+            Some(_) | None => return Ok(()),
+        }
+        let password = self.state().script.password.clone();
+        if let Some((user, password)) = password {
+            if !self.scram(Provider { user, password }).await? {
+                return Ok(());
+            }
+        }
+        self.send(ServerMessage::Authentication(Authentication::Ok));
+        let (key_data, params) = {
+            let state = self.state();
+            (state.script.key_data, state.script.params.clone())
+        };
+        self.send(ServerMessage::ServerKeyData(ServerKeyData {
+            data: key_data,
+        }));
+        for (name, value) in params {
+            self.send(ServerMessage::ParameterStatus(ParameterStatus {
+                name,
+                value,
+            }));
+        }
+        self.send_ready();
+        self.flush().await?;
+        while let Some(message) = self.message().await? {
+            if !self.process(message)? {
+                self.flush().await?;
+                return Ok(());
+            }
+            self.flush().await?;
+        }
+        Ok(())
+    }
+    /// Returns `false` if the connection should be closed
+    fn process(&mut self, message: ClientMessage) -> io::Result<bool> {
+        use ClientMessage::*;
+
+        match message {
+            Sync => {
+                self.skip_to_sync = false;
+                self.send_ready();
+                if self.closing {
+                    return Ok(false);
+                }
+            }
+            _ if self.skip_to_sync => {}
+            ExecuteScript(script) => {
+                let response = match self.execution(&script.script_text) {
+                    Some(response) => response,
+                    None => {
+                        self.send_ready();
+                        return Ok(true);
+                    }
+                };
+                if self.disconnect_once(&script.script_text) {
+                    return Ok(false);
+                }
+                if response.stall {
+                    return Ok(true);
+                }
+                self.send_logs(&response);
+                match response.error {
+                    Some(error) => {
+                        self.send(ServerMessage::ErrorResponse(error));
+                    }
+                    None => self.complete(&response),
+                }
+                self.send_ready();
+                if self.closing {
+                    return Ok(false);
+                }
+            }
+            Prepare(prepare) => {
+                let response = match self.response(&prepare.command_text) {
+                    Some(response) => response,
+                    None => {
+                        self.skip_to_sync = true;
+                        return Ok(true);
+                    }
+                };
+                if let Some(error) = response.error {
+                    self.send(ServerMessage::ErrorResponse(error));
+                    self.skip_to_sync = true;
+                    return Ok(true);
+                }
+                let desc = &response.description;
+                self.send(ServerMessage::PrepareComplete(PrepareComplete {
+                    headers: HashMap::new(),
+                    cardinality: desc.result_cardinality,
+                    input_typedesc_id: desc.input_typedesc_id,
+                    output_typedesc_id: desc.output_typedesc_id,
+                }));
+                self.statements.insert(prepare.statement_name,
+                                       prepare.command_text);
+            }
+            DescribeStatement(describe) => {
+                let response = self.statement(&describe.statement_name)
+                    .and_then(|query| self.response(&query));
+                match response {
+                    Some(response) => {
+                        self.send(ServerMessage::CommandDataDescription(
+                            response.description));
+                    }
+                    None => self.skip_to_sync = true,
+                }
+            }
+            Execute(execute) => {
+                let query = self.statement(&execute.statement_name);
+                let response = match query.as_ref()
+                    .and_then(|query| self.execution(query))
+                {
+                    Some(response) => response,
+                    None => {
+                        self.skip_to_sync = true;
+                        return Ok(true);
+                    }
+                };
+                if self.disconnect_once(query.as_ref().unwrap()) {
+                    return Ok(false);
+                }
+                self.send_logs(&response);
+                for row in &response.rows {
+                    self.send(ServerMessage::Data(Data {
+                        data: vec![row.clone()],
+                    }));
+                }
+                self.complete(&response);
+            }
+            Flush => {}
+            Terminate => return Ok(false),
+            _ => {
+                self.error(PROTOCOL_ERROR, "unexpected message");
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+    /// Looks up the response, sends an error if there is none
+    fn response(&mut self, query: &str) -> Option<Response> {
+        let result = self.state().script.responses.get(query).cloned();
+        if result.is_none() {
+            self.error(PROTOCOL_ERROR, &format!(
+                "mock server has no response for {:?}", query));
+        }
+        result
+    }
+    /// Looks up the response to the execution, queued ones go first
+    fn execution(&mut self, query: &str) -> Option<Response> {
+        let queued = self.state().script.queued.get_mut(query)
+            .and_then(|queue| queue.pop_front());
+        queued.or_else(|| self.response(query))
+    }
+    /// Returns `true` if the connection should be closed instead of
+    /// executing `query`, the flag is reset so it's closed only once
+    /// (the flag is in the script, so this is once per server rather than
+    /// once per connection)
+    fn disconnect_once(&mut self, query: &str) -> bool {
+        match self.state().script.responses.get_mut(query) {
+            Some(response) => {
+                let disconnect = response.disconnect;
+                response.disconnect = false;
+                disconnect
+            }
+            None => false,
+        }
+    }
+    /// Returns the query text of the prepared statement
+    fn statement(&mut self, name: &Bytes) -> Option<String> {
+        let query = self.statements.get(name).cloned();
+        if query.is_none() {
+            self.error(PROTOCOL_ERROR, "statement is not prepared");
+        }
+        query
+    }
+    async fn scram(&mut self, provider: Provider) -> io::Result<bool> {
+        self.send(ServerMessage::Authentication(Authentication::Sasl {
+            methods: vec!["SCRAM-SHA-256".into()],
+        }));
+        self.flush().await?;
+        let first = match self.message().await? {
+            Some(ClientMessage::AuthenticationSaslInitialResponse(msg))
+            if msg.method == "SCRAM-SHA-256" => msg.data,
+            _ => return self.auth_error("SCRAM-SHA-256 expected").await,
+        };
+        let first = str::from_utf8(&first[..]).map_err(invalid_data)?;
+        let server = ScramServer::new(provider);
+        let server = match server.handle_client_first(first) {
+            Ok(server) => server,
+            Err(e) => return self.auth_error(&e.to_string()).await,
+        };
+        let (server, data) = server.server_first();
+        self.send(ServerMessage::Authentication(
+            Authentication::SaslContinue { data: data.into() }));
+        self.flush().await?;
+        let data = match self.message().await? {
+            Some(ClientMessage::AuthenticationSaslResponse(msg)) => msg.data,
+            _ => return self.auth_error("SASL response expected").await,
+        };
+        let data = str::from_utf8(&data[..]).map_err(invalid_data)?;
+        let server = match server.handle_client_final(data) {
+            Ok(server) => server,
+            Err(e) => return self.auth_error(&e.to_string()).await,
+        };
+        match server.server_final() {
+            (AuthenticationStatus::Authenticated, data) => {
+                self.send(ServerMessage::Authentication(
+                    Authentication::SaslFinal { data: data.into() }));
+                Ok(true)
+            }
+            _ => self.auth_error("authentication failed").await,
+        }
+    }
+    async fn auth_error(&mut self, message: &str) -> io::Result<bool> {
+        self.error(AUTHENTICATION_ERROR, message);
+        self.flush().await?;
+        Ok(false)
+    }
+    fn complete(&mut self, response: &Response) {
+        self.send(ServerMessage::CommandComplete(CommandComplete {
+            headers: HashMap::new(),
+            status_data: response.status.clone(),
+        }));
+        if let Some(state) = response.transaction_state {
+            self.transaction_state = state;
+        }
+        self.closing = response.close_after;
+    }
+    fn send_logs(&mut self, response: &Response) {
+        for log in &response.logs {
+            self.send(ServerMessage::LogMessage(log.clone()));
+        }
+    }
+    fn send_ready(&mut self) {
+        self.send(ServerMessage::ReadyForCommand(ReadyForCommand {
+            headers: HashMap::new(),
+            transaction_state: self.transaction_state,
+        }));
+    }
+    fn error(&mut self, code: u32, message: &str) {
+        self.send(ServerMessage::ErrorResponse(ErrorResponse {
+            severity: ErrorSeverity::Error,
+            code,
+            message: message.into(),
+            attributes: HashMap::new(),
+        }));
+    }
+    fn send(&mut self, message: ServerMessage) {
+        message.encode(&mut self.outbuf)
+            .expect("mock server sends valid messages");
+    }
+    async fn flush(&mut self) -> io::Result<()> {
+        self.stream.write_all(&self.outbuf[..]).await?;
+        self.outbuf.truncate(0);
+        Ok(())
+    }
+    /// Reads next message, returns `None` if the connection is closed
+    async fn message(&mut self) -> io::Result<Option<ClientMessage>> {
+        let mut header = [0u8; 5];
+        match self.stream.read_exact(&mut header).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Ok(None);
+            }
+            Err(e) => return Err(e),
+        }
+        let len = u32::from_be_bytes(header[1..5].try_into().unwrap());
+        if len < 4 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("message length {} is too small", len)));
+        }
+        let mut frame = vec![0u8; len as usize + 1];
+        frame[..5].copy_from_slice(&header);
+        self.stream.read_exact(&mut frame[5..]).await?;
+        let message = ClientMessage::decode(&Bytes::from(frame))
+            .map_err(invalid_data)?;
+        self.state().received.push(message.clone());
+        Ok(Some(message))
+    }
+}
+
+fn invalid_data(e: impl std::error::Error + Send + Sync + 'static)
+    -> io::Error
+{
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
@@ -25,6 +25,7 @@ pub enum ClientMessage {
     Sync,
     Flush,
     Terminate,
+    CancelRequest(CancelRequest),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -75,12 +76,19 @@ pub struct Execute {
     pub arguments: Bytes,
 }
 
+/// Sent as the first message of a separate connection to cancel the query
+/// running on the connection identified by the `ServerKeyData`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CancelRequest {
+    pub key_data: [u8; 32],
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum DescribeAspect {
     DataDescription = 0x54,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum IoFormat {
     Binary = 0x62,
     Json = 0x6a,
@@ -103,6 +111,7 @@ impl ClientMessage {
             Sync => encode(buf, 0x53, &Empty),
             Flush => encode(buf, 0x48, &Empty),
             Terminate => encode(buf, 0x58, &Empty),
+            CancelRequest(h) => encode(buf, 0x43, h),
 
             UnknownMessage(_, _) => {
                 errors::UnknownMessageCantBeEncoded.fail()?
@@ -129,6 +138,7 @@ impl ClientMessage {
             0x53 => Ok(M::Sync),
             0x48 => Ok(M::Flush),
             0x58 => Ok(M::Terminate),
+            0x43 => CancelRequest::decode(&mut data).map(M::CancelRequest),
             0x44 => {
                 DescribeStatement::decode(&mut data).map(M::DescribeStatement)
             }
@@ -331,6 +341,11 @@ impl Encode for DescribeStatement {
         buf.reserve(7);
         buf.put_u16(u16::try_from(self.headers.len()).ok()
             .context(errors::TooManyHeaders)?);
+        for (&name, value) in &self.headers {
+            buf.reserve(2);
+            buf.put_u16(name);
+            value.encode(buf)?;
+        }
         buf.reserve(5);
         buf.put_u8(self.aspect as u8);
         self.statement_name.encode(buf)?;
@@ -368,6 +383,11 @@ impl Encode for Execute {
         buf.reserve(10);
         buf.put_u16(u16::try_from(self.headers.len()).ok()
             .context(errors::TooManyHeaders)?);
+        for (&name, value) in &self.headers {
+            buf.reserve(2);
+            buf.put_u16(name);
+            value.encode(buf)?;
+        }
         self.statement_name.encode(buf)?;
         self.arguments.encode(buf)?;
         Ok(())
@@ -392,3 +412,22 @@ impl Decode for Execute {
         })
     }
 }
+
+impl Encode for CancelRequest {
+    fn encode(&self, buf: &mut BytesMut)
+        -> Result<(), EncodeError>
+    {
+        buf.extend(&self.key_data[..]);
+        Ok(())
+    }
+}
+
+impl Decode for CancelRequest {
+    fn decode(buf: &mut Cursor<Bytes>) -> Result<Self, DecodeError> {
+        ensure!(buf.remaining() >= 32, errors::Underflow);
+        let mut key_data = [0u8; 32];
+        key_data.copy_from_slice(&buf.bytes()[..32]);
+        buf.advance(32);
+        Ok(CancelRequest { key_data })
+    }
+}
@@ -1009,7 +1009,13 @@ impl Codec for InputTuple {
         let mut items = Vec::with_capacity(size);
         for codec in &self.elements {
             ensure!(buf.remaining() >= 4, errors::Underflow);
-            let len = buf.get_u32() as usize;
+            let len = buf.get_i32();
+            if len == -1 {
+                // missing optional argument
+                items.push(Value::Nothing);
+                continue;
+            }
+            let len = len as usize;
             ensure!(buf.remaining() >= len, errors::Underflow);
             let off = buf.position() as usize;
             let mut chunk = Cursor::new(buf.get_ref().slice(off..off + len));
@@ -1032,6 +1038,10 @@ impl Codec for InputTuple {
                     .ok().context(errors::TooManyElements)?);
         for (codec, item) in self.elements.iter().zip(items) {
             buf.reserve(4);
+            if let Value::Nothing = item {
+                buf.put_i32(-1);
+                continue;
+            }
             let pos = buf.len();
             buf.put_u32(0);  // replaced after serializing a value
             codec.encode(buf, item)?;
@@ -1102,7 +1112,13 @@ impl Codec for InputNamedTuple {
         let mut fields = Vec::with_capacity(size);
         for codec in &self.codecs {
             ensure!(buf.remaining() >= 4, errors::Underflow);
-            let len = buf.get_u32() as usize;
+            let len = buf.get_i32();
+            if len == -1 {
+                // missing optional argument
+                fields.push(Value::Nothing);
+                continue;
+            }
+            let len = len as usize;
             ensure!(buf.remaining() >= len, errors::Underflow);
             let off = buf.position() as usize;
             let mut chunk = Cursor::new(buf.get_ref().slice(off..off + len));
@@ -1130,6 +1146,10 @@ impl Codec for InputNamedTuple {
                     .ok().context(errors::TooManyElements)?);
         for (codec, field) in self.codecs.iter().zip(fields) {
             buf.reserve(4);
+            if let Value::Nothing = field {
+                buf.put_i32(-1);
+                continue;
+            }
             let pos = buf.len();
             buf.put_u32(0);  // replaced after serializing a value
             codec.encode(buf, field)?;
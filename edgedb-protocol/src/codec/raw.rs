@@ -43,3 +43,22 @@ impl RawCodec for bool {
         Ok(res)
     }
 }
+
+macro_rules! implement_number {
+    ($typ: ty, $method: ident, $size: expr) => {
+        impl RawCodec for $typ {
+            fn decode_raw(buf: &mut Cursor<Bytes>)
+                -> Result<Self, DecodeError>
+            {
+                ensure!(buf.remaining() >= $size, errors::Underflow);
+                Ok(buf.$method())
+            }
+        }
+    }
+}
+
+implement_number!(i16, get_i16, 2);
+implement_number!(i32, get_i32, 4);
+implement_number!(i64, get_i64, 8);
+implement_number!(f32, get_f32, 4);
+implement_number!(f64, get_f64, 8);
@@ -28,6 +28,7 @@ pub enum Descriptor {
     TypeAnnotation(TypeAnnotationDescriptor),
 }
 
+#[derive(Debug, Clone)]
 pub struct OutputTypedesc {
     pub(crate) array: Vec<Descriptor>,
     #[allow(dead_code)] // TODO
@@ -35,6 +36,7 @@ pub struct OutputTypedesc {
     pub(crate) root_pos: Option<TypePos>,
 }
 
+#[derive(Debug, Clone)]
 pub struct InputTypedesc {
     pub(crate) array: Vec<Descriptor>,
     #[allow(dead_code)] // TODO
@@ -128,6 +130,9 @@ impl OutputTypedesc {
 }
 
 impl InputTypedesc {
+    pub fn as_queryable_context(&self) -> queryable::DescriptorContext {
+        queryable::DescriptorContext::new(self.descriptors())
+    }
     pub fn descriptors(&self) -> &[Descriptor] {
         &self.array
     }
@@ -54,6 +54,12 @@ pub enum DecodeError {
     TooManyDescriptors { backtrace: Backtrace, index: usize },
     #[snafu(display("uuid {} not found", uuid))]
     UuidNotFound { backtrace: Backtrace, uuid: uuid::Uuid },
+    #[snafu(display("required element is missing"))]
+    MissingRequiredElement { backtrace: Backtrace },
+    #[snafu(display("signature record contains unexpected message"))]
+    InvalidSignatureRecord { backtrace: Backtrace },
+    #[snafu(display("invalid value of header 0x{:04x}", code))]
+    InvalidHeader { backtrace: Backtrace, code: u16 },
 }
 
 #[derive(Snafu, Debug)]
@@ -0,0 +1,167 @@
+//! Typed access to the message headers
+//!
+//! Headers are sent as a map of `u16` codes to raw bytes. `RequestHeaders`
+//! builds headers for `Prepare`, `Execute` and `ExecuteScript`, while
+//! `ResponseHeaders` decodes the ones on `CommandComplete` and
+//! `PrepareComplete`.
+use std::convert::TryInto;
+use std::ops::{BitOr, BitOrAssign};
+use std::str;
+
+use bytes::Bytes;
+use snafu::{OptionExt, ResultExt};
+
+use crate::errors::{self, DecodeError};
+use crate::server_message::{CommandComplete, PrepareComplete};
+pub use crate::encoding::Headers;
+
+
+/// Limit on the number of elements returned by the query (request)
+pub const IMPLICIT_LIMIT: u16 = 0x_FF_01;
+/// Add `__tname__` to the shapes of the result (request)
+pub const IMPLICIT_TYPENAMES: u16 = 0x_FF_02;
+/// Add `__tid__` to the shapes of the result (request)
+pub const IMPLICIT_TYPEIDS: u16 = 0x_FF_03;
+/// Capabilities that the query is allowed to use (request)
+pub const ALLOW_CAPABILITIES: u16 = 0x_FF_04;
+/// Return `id` only when it's requested explicitly (request)
+pub const EXPLICIT_OBJECTIDS: u16 = 0x_FF_05;
+/// Capabilities the query uses (response)
+pub const CAPABILITIES: u16 = 0x_10_01;
+
+
+/// Set of the capabilities of a query
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Capabilities(u64);
+
+/// Builder of the request headers
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequestHeaders {
+    headers: Headers,
+}
+
+/// Decoded headers of `CommandComplete` or `PrepareComplete`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResponseHeaders {
+    pub capabilities: Option<Capabilities>,
+    /// Headers that aren't known to this version of the library
+    pub unknown: Headers,
+}
+
+
+impl Capabilities {
+    pub const NONE: Capabilities = Capabilities(0);
+    pub const MODIFICATIONS: Capabilities = Capabilities(0b0001);
+    pub const SESSION_CONFIG: Capabilities = Capabilities(0b0010);
+    pub const TRANSACTION: Capabilities = Capabilities(0b0100);
+    pub const DDL: Capabilities = Capabilities(0b1000);
+    pub const PERSISTENT_CONFIG: Capabilities = Capabilities(0b1_0000);
+    pub const ALL: Capabilities = Capabilities(u64::MAX);
+
+    pub fn from_bits(bits: u64) -> Capabilities {
+        Capabilities(bits)
+    }
+    pub fn bits(&self) -> u64 {
+        self.0
+    }
+    pub fn contains(&self, other: Capabilities) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Capabilities {
+    type Output = Capabilities;
+    fn bitor(self, other: Capabilities) -> Capabilities {
+        Capabilities(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for Capabilities {
+    fn bitor_assign(&mut self, other: Capabilities) {
+        self.0 |= other.0;
+    }
+}
+
+impl RequestHeaders {
+    pub fn new() -> RequestHeaders {
+        RequestHeaders::default()
+    }
+    pub fn implicit_limit(&mut self, limit: u64) -> &mut Self {
+        self.raw(IMPLICIT_LIMIT, limit.to_string())
+    }
+    pub fn implicit_typenames(&mut self, value: bool) -> &mut Self {
+        self.raw(IMPLICIT_TYPENAMES, encode_bool(value))
+    }
+    pub fn implicit_typeids(&mut self, value: bool) -> &mut Self {
+        self.raw(IMPLICIT_TYPEIDS, encode_bool(value))
+    }
+    pub fn allow_capabilities(&mut self, caps: Capabilities) -> &mut Self {
+        self.raw(ALLOW_CAPABILITIES, caps.0.to_be_bytes().to_vec())
+    }
+    pub fn explicit_objectids(&mut self, value: bool) -> &mut Self {
+        self.raw(EXPLICIT_OBJECTIDS, encode_bool(value))
+    }
+    /// Sets a header that has no typed setter
+    pub fn raw(&mut self, code: u16, value: impl Into<Bytes>) -> &mut Self {
+        self.headers.insert(code, value.into());
+        self
+    }
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty()
+    }
+    pub fn build(&self) -> Headers {
+        self.headers.clone()
+    }
+}
+
+impl ResponseHeaders {
+    pub fn decode(headers: &Headers) -> Result<ResponseHeaders, DecodeError> {
+        let mut result = ResponseHeaders::default();
+        for (&code, value) in headers {
+            match code {
+                CAPABILITIES => {
+                    let bytes = value[..].try_into().ok()
+                        .context(errors::InvalidHeader { code })?;
+                    result.capabilities = Some(Capabilities(
+                        u64::from_be_bytes(bytes)));
+                }
+                _ => {
+                    result.unknown.insert(code, value.clone());
+                }
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl CommandComplete {
+    pub fn decode_headers(&self) -> Result<ResponseHeaders, DecodeError> {
+        ResponseHeaders::decode(&self.headers)
+    }
+}
+
+impl PrepareComplete {
+    pub fn decode_headers(&self) -> Result<ResponseHeaders, DecodeError> {
+        ResponseHeaders::decode(&self.headers)
+    }
+}
+
+fn encode_bool(value: bool) -> &'static str {
+    if value { "true" } else { "false" }
+}
+
+/// Decodes a boolean request header, as the server does
+pub fn decode_bool(code: u16, value: &Bytes) -> Result<bool, DecodeError> {
+    match &value[..] {
+        b"true" => Ok(true),
+        b"false" => Ok(false),
+        _ => errors::InvalidHeader { code }.fail(),
+    }
+}
+
+/// Decodes `IMPLICIT_LIMIT` header
+pub fn decode_limit(value: &Bytes) -> Result<u64, DecodeError> {
+    let text = str::from_utf8(&value[..]).context(errors::InvalidUtf8)?;
+    text.parse().ok()
+        .context(errors::InvalidHeader { code: IMPLICIT_LIMIT })
+}
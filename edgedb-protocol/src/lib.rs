@@ -3,8 +3,11 @@ mod common;
 pub mod client_message;
 pub mod server_message;
 pub mod errors;
+pub mod headers;
 pub mod error_response;
 pub mod descriptors;
 pub mod value;
 pub mod codec;
 pub mod queryable;
+pub mod query_args;
+pub mod signatures;
@@ -0,0 +1,108 @@
+use uuid::Uuid;
+
+use crate::codec;
+use crate::descriptors::{Descriptor, InputTypedesc, TypePos};
+use crate::queryable::{DescriptorContext, DescriptorMismatch};
+use crate::value::Value;
+
+
+/// A set of query arguments that can be converted to a value matching
+/// the input type descriptor of the query
+///
+/// Usually derived for structs with `#[derive(QueryArgs)]`, in which case
+/// field names are matched against the names of the arguments (`$name`).
+/// Arguments declared as `<optional T>$name` map to `Option<T>` fields,
+/// where `None` is sent as an empty set. Every field must be used by the
+/// query unless it's marked with `#[edgedb(skip_if_absent)]`.
+pub trait QueryArgs {
+    fn to_value(&self, desc: &InputTypedesc)
+        -> Result<Value, DescriptorMismatch>;
+}
+
+/// A single query argument
+pub trait QueryArg {
+    fn to_value(&self) -> Value;
+    fn check_descriptor(ctx: &DescriptorContext, type_pos: TypePos)
+        -> Result<(), DescriptorMismatch>;
+}
+
+impl QueryArgs for Value {
+    fn to_value(&self, _desc: &InputTypedesc)
+        -> Result<Value, DescriptorMismatch>
+    {
+        // Value is checked by the codec when it's encoded
+        Ok(self.clone())
+    }
+}
+
+fn check_scalar(ctx: &DescriptorContext, type_pos: TypePos,
+    type_id: Uuid, name: &str)
+    -> Result<(), DescriptorMismatch>
+{
+    use crate::descriptors::Descriptor::{Scalar, BaseScalar};
+    let desc = ctx.get(type_pos)?;
+    match desc {
+        Scalar(scalar) => {
+            return check_scalar(ctx, scalar.base_type_pos, type_id, name);
+        }
+        BaseScalar(base) if base.id == type_id => {
+            return Ok(());
+        }
+        _ => {}
+    }
+    Err(ctx.wrong_type(desc, name))
+}
+
+macro_rules! implement_scalar {
+    ($typ: ty, $variant: ident, $type_id: expr, $name: expr) => {
+        impl QueryArg for $typ {
+            fn to_value(&self) -> Value {
+                Value::$variant(self.clone())
+            }
+            fn check_descriptor(ctx: &DescriptorContext, type_pos: TypePos)
+                -> Result<(), DescriptorMismatch>
+            {
+                check_scalar(ctx, type_pos, $type_id, $name)
+            }
+        }
+    }
+}
+
+implement_scalar!(String, Str, codec::STD_STR, "str");
+implement_scalar!(Uuid, Uuid, codec::STD_UUID, "uuid");
+implement_scalar!(bool, Bool, codec::STD_BOOL, "bool");
+implement_scalar!(i16, Int16, codec::STD_INT16, "int16");
+implement_scalar!(i32, Int32, codec::STD_INT32, "int32");
+implement_scalar!(i64, Int64, codec::STD_INT64, "int64");
+implement_scalar!(f32, Float32, codec::STD_FLOAT32, "float32");
+implement_scalar!(f64, Float64, codec::STD_FLOAT64, "float64");
+
+impl<T: QueryArg> QueryArg for Vec<T> {
+    fn to_value(&self) -> Value {
+        Value::Array(self.iter().map(|x| x.to_value()).collect())
+    }
+    fn check_descriptor(ctx: &DescriptorContext, type_pos: TypePos)
+        -> Result<(), DescriptorMismatch>
+    {
+        let desc = ctx.get(type_pos)?;
+        match desc {
+            Descriptor::Array(arr) => T::check_descriptor(ctx, arr.type_pos),
+            _ => Err(ctx.wrong_type(desc, "array")),
+        }
+    }
+}
+
+/// Optional argument (`<optional str>$name`), `None` is sent as a null
+impl<T: QueryArg> QueryArg for Option<T> {
+    fn to_value(&self) -> Value {
+        match self {
+            Some(value) => value.to_value(),
+            None => Value::Nothing,
+        }
+    }
+    fn check_descriptor(ctx: &DescriptorContext, type_pos: TypePos)
+        -> Result<(), DescriptorMismatch>
+    {
+        T::check_descriptor(ctx, type_pos)
+    }
+}
@@ -7,7 +7,7 @@ use uuid::Uuid;
 use crate::errors::{self, DecodeError};
 use crate::codec::raw::RawCodec;
 use crate::codec;
-use crate::descriptors::{Descriptor, TypePos};
+use crate::descriptors::{Descriptor, TypePos, ShapeElement};
 
 
 #[derive(Snafu, Debug)]
@@ -23,6 +23,13 @@ pub enum DescriptorMismatch {
     Expected { expected: String },
     #[snafu(display("invalid type descriptor"))]
     InvalidDescriptor,
+    #[snafu(display("query argument ${} is not provided", name))]
+    MissingArgument { name: String },
+    #[snafu(display("argument ${} is not used in the query", name))]
+    ExtraArgument { name: String },
+    #[snafu(display("argument ${} has type {}, expected {}",
+                    name, unexpected, expected))]
+    WrongArgumentType { name: String, unexpected: String, expected: String },
 }
 
 pub struct DescriptorContext<'a> {
@@ -36,10 +43,27 @@ pub trait Queryable: Sized {
         Ok(result)
     }
     fn decode_raw(buf: &mut Cursor<Bytes>) -> Result<Self, DecodeError>;
+    /// Called instead of `decode_raw` when the object field is empty
+    fn decode_missing() -> Result<Self, DecodeError> {
+        errors::MissingRequiredElement.fail()
+    }
     fn check_descriptor(ctx: &DescriptorContext, type_pos: TypePos)
         -> Result<(), DescriptorMismatch>;
 }
 
+/// A set of object fields that can be spliced into the parent shape
+///
+/// Implemented by `#[derive(Queryable)]` so that the struct can be used
+/// in a field marked with `#[edgedb(flatten)]`.
+pub trait FlattenFields: Sized {
+    fn field_count() -> usize;
+    /// Whether a field with this name is one of the (flattened) fields
+    fn has_field(name: &str) -> bool;
+    fn decode_fields(buf: &mut Cursor<Bytes>) -> Result<Self, DecodeError>;
+    fn check_fields(ctx: &DescriptorContext, elements: &[ShapeElement])
+        -> Result<(), DescriptorMismatch>;
+}
+
 impl DescriptorContext<'_> {
     pub(crate) fn new(descriptors: &[Descriptor]) -> DescriptorContext {
         DescriptorContext { descriptors }
@@ -77,6 +101,27 @@ impl DescriptorContext<'_> {
     {
         DescriptorMismatch::Expected { expected: expected.into() }
     }
+    pub fn missing_argument(&self, name: &str) -> DescriptorMismatch {
+        DescriptorMismatch::MissingArgument { name: name.into() }
+    }
+    pub fn extra_argument(&self, name: &str) -> DescriptorMismatch {
+        DescriptorMismatch::ExtraArgument { name: name.into() }
+    }
+    /// Attaches argument name to the type mismatch error
+    pub fn wrong_argument(&self, name: &str, error: DescriptorMismatch)
+        -> DescriptorMismatch
+    {
+        match error {
+            DescriptorMismatch::WrongType { unexpected, expected } => {
+                DescriptorMismatch::WrongArgumentType {
+                    name: name.into(),
+                    unexpected,
+                    expected,
+                }
+            }
+            error => error,
+        }
+    }
 }
 
 impl Queryable for String {
@@ -144,3 +189,92 @@ impl Queryable for bool {
         Err(ctx.wrong_type(desc, "bool"))
     }
 }
+
+macro_rules! implement_scalar {
+    ($typ: ty, $type_id: expr, $name: expr) => {
+        impl Queryable for $typ {
+            fn decode_raw(buf: &mut Cursor<Bytes>)
+                -> Result<Self, DecodeError>
+            {
+                RawCodec::decode_raw(buf)
+            }
+            fn check_descriptor(ctx: &DescriptorContext, type_pos: TypePos)
+                -> Result<(), DescriptorMismatch>
+            {
+                use crate::descriptors::Descriptor::{Scalar, BaseScalar};
+                let desc = ctx.get(type_pos)?;
+                match desc {
+                    Scalar(scalar) => {
+                        return Self::check_descriptor(ctx,
+                            scalar.base_type_pos);
+                    }
+                    BaseScalar(base) if base.id == $type_id => {
+                        return Ok(());
+                    }
+                    _ => {}
+                }
+                Err(ctx.wrong_type(desc, $name))
+            }
+        }
+    }
+}
+
+implement_scalar!(i16, codec::STD_INT16, "int16");
+implement_scalar!(i32, codec::STD_INT32, "int32");
+implement_scalar!(i64, codec::STD_INT64, "int64");
+implement_scalar!(f32, codec::STD_FLOAT32, "float32");
+implement_scalar!(f64, codec::STD_FLOAT64, "float64");
+
+impl<T: Queryable> Queryable for Vec<T> {
+    fn decode_raw(buf: &mut Cursor<Bytes>) -> Result<Self, DecodeError> {
+        ensure!(buf.remaining() >= 12, errors::Underflow);
+        let ndims = buf.get_u32();
+        let _reserved0 = buf.get_u32();
+        let _reserved1 = buf.get_u32();
+        if ndims == 0 {
+            return Ok(Vec::new());
+        }
+        ensure!(ndims == 1, errors::InvalidArrayShape);
+        ensure!(buf.remaining() >= 8, errors::Underflow);
+        let size = buf.get_u32() as usize;
+        let lower = buf.get_u32();
+        ensure!(lower == 1, errors::InvalidArrayShape);
+        // every element takes at least 4 bytes, don't trust the size
+        let mut items = Vec::with_capacity(size.min(buf.remaining() / 4));
+        for _ in 0..size {
+            ensure!(buf.remaining() >= 4, errors::Underflow);
+            let len = buf.get_u32() as usize;
+            ensure!(buf.remaining() >= len, errors::Underflow);
+            let off = buf.position() as usize;
+            let mut chunk = Cursor::new(buf.get_ref().slice(off..off + len));
+            buf.advance(len);
+            items.push(T::decode(&mut chunk)?);
+        }
+        Ok(items)
+    }
+    fn check_descriptor(ctx: &DescriptorContext, type_pos: TypePos)
+        -> Result<(), DescriptorMismatch>
+    {
+        use crate::descriptors::Descriptor::{Set, Array};
+        let desc = ctx.get(type_pos)?;
+        match desc {
+            Set(set) => T::check_descriptor(ctx, set.type_pos),
+            Array(arr) => T::check_descriptor(ctx, arr.type_pos),
+            _ => Err(ctx.wrong_type(desc, "array or set")),
+        }
+    }
+}
+
+impl<T: Queryable> Queryable for Option<T> {
+    fn decode_raw(buf: &mut Cursor<Bytes>) -> Result<Self, DecodeError> {
+        T::decode_raw(buf).map(Some)
+    }
+    fn decode_missing() -> Result<Self, DecodeError> {
+        Ok(None)
+    }
+    fn check_descriptor(ctx: &DescriptorContext, type_pos: TypePos)
+        -> Result<(), DescriptorMismatch>
+    {
+        T::check_descriptor(ctx, type_pos)
+    }
+}
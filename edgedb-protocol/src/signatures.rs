@@ -0,0 +1,151 @@
+//! Recorded type descriptors of queries
+//!
+//! This allows checking that rust types still match the queries without
+//! connecting to the database, e.g. in unit tests. The file is written by
+//! `edgedb record-signatures`. It is a sequence of records, each of them is
+//! a query name, query text and a `CommandDataDescription` message.
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
+
+use bytes::{Bytes, BytesMut, Buf};
+use snafu::{Snafu, ResultExt, ensure};
+
+use crate::encoding::{Encode, Decode};
+use crate::errors::{self, DecodeError, EncodeError, CodecError};
+use crate::query_args::QueryArgs;
+use crate::queryable::{Queryable, DescriptorMismatch};
+use crate::server_message::{ServerMessage, CommandDataDescription};
+
+
+#[derive(Snafu, Debug)]
+#[snafu(visibility(pub(crate)))]
+#[non_exhaustive]
+pub enum SignatureError {
+    #[snafu(display("can't read signatures from {:?}: {}", path, source))]
+    ReadFile { path: PathBuf, source: io::Error },
+    #[snafu(display("invalid signatures file: {}", source))]
+    InvalidFile { source: DecodeError },
+    #[snafu(display("query {:?} is not recorded", name))]
+    NotRecorded { name: String },
+    #[snafu(display("query {:?} has invalid descriptor: {}", name, source))]
+    InvalidDescriptor { name: String, source: DecodeError },
+    #[snafu(display("query {:?} returns no result", name))]
+    NoResult { name: String },
+    #[snafu(display("result of query {:?} doesn't match: {}", name, source))]
+    ResultMismatch { name: String, source: DescriptorMismatch },
+    #[snafu(display("arguments of query {:?} don't match: {}",
+                    name, source))]
+    ArgumentMismatch { name: String, source: DescriptorMismatch },
+    #[snafu(display("can't build codec for query {:?}: {}", name, source))]
+    ArgumentCodec { name: String, source: CodecError },
+    #[snafu(display("can't encode arguments of query {:?}: {}",
+                    name, source))]
+    ArgumentEncoding { name: String, source: EncodeError },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub name: String,
+    pub query: String,
+    pub description: CommandDataDescription,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Signatures {
+    items: Vec<Signature>,
+}
+
+impl Signatures {
+    pub fn new() -> Signatures {
+        Signatures::default()
+    }
+    pub fn read(path: impl AsRef<Path>) -> Result<Signatures, SignatureError>
+    {
+        let path = path.as_ref();
+        let data = fs::read(path).context(ReadFile { path })?;
+        Signatures::decode(&Bytes::from(data)).context(InvalidFile)
+    }
+    /// Adds signature replacing the one with the same name
+    pub fn add(&mut self, signature: Signature) {
+        match self.items.iter_mut().find(|s| s.name == signature.name) {
+            Some(old) => *old = signature,
+            None => self.items.push(signature),
+        }
+    }
+    pub fn get(&self, name: &str) -> Option<&Signature> {
+        self.items.iter().find(|s| s.name == name)
+    }
+    pub fn iter(&self) -> impl Iterator<Item=&Signature> {
+        self.items.iter()
+    }
+    pub fn encode(&self, buf: &mut BytesMut) -> Result<(), EncodeError> {
+        for item in &self.items {
+            item.name.encode(buf)?;
+            item.query.encode(buf)?;
+            ServerMessage::CommandDataDescription(item.description.clone())
+                .encode(buf)?;
+        }
+        Ok(())
+    }
+    pub fn decode(buf: &Bytes) -> Result<Signatures, DecodeError> {
+        let mut cur = Cursor::new(buf.clone());
+        let mut items = Vec::new();
+        while cur.has_remaining() {
+            let name = <String as Decode>::decode(&mut cur)?;
+            let query = <String as Decode>::decode(&mut cur)?;
+            ensure!(cur.remaining() >= 5, errors::Underflow);
+            let pos = cur.position() as usize;
+            let len = u32::from_be_bytes([
+                buf[pos+1], buf[pos+2], buf[pos+3], buf[pos+4],
+            ]) as usize;
+            let end = pos + 1 + len;
+            ensure!(buf.len() >= end, errors::Underflow);
+            let frame = buf.slice(pos..end);
+            cur.set_position(end as u64);
+            let description = match ServerMessage::decode(&frame)? {
+                ServerMessage::CommandDataDescription(d) => d,
+                _ => errors::InvalidSignatureRecord.fail()?,
+            };
+            items.push(Signature { name, query, description });
+        }
+        Ok(Signatures { items })
+    }
+    fn find(&self, name: &str) -> Result<&Signature, SignatureError> {
+        self.get(name).ok_or_else(|| SignatureError::NotRecorded {
+            name: name.into(),
+        })
+    }
+    /// Checks that `args` can be encoded for the recorded query
+    pub fn check_args<A>(&self, name: &str, args: &A)
+        -> Result<(), SignatureError>
+        where A: QueryArgs + ?Sized,
+    {
+        let sig = self.find(name)?;
+        let desc = sig.description.input()
+            .context(InvalidDescriptor { name })?;
+        let value = args.to_value(&desc)
+            .context(ArgumentMismatch { name })?;
+        let codec = desc.build_codec()
+            .context(ArgumentCodec { name })?;
+        codec.encode(&mut BytesMut::new(), &value)
+            .context(ArgumentEncoding { name })?;
+        Ok(())
+    }
+    /// Checks both arguments and the result type of the recorded query
+    pub fn check<R, A>(&self, name: &str, args: &A)
+        -> Result<(), SignatureError>
+        where R: Queryable,
+              A: QueryArgs + ?Sized,
+    {
+        self.check_args(name, args)?;
+        let sig = self.find(name)?;
+        let desc = sig.description.output()
+            .context(InvalidDescriptor { name })?;
+        let root_pos = desc.root_pos()
+            .ok_or_else(|| SignatureError::NoResult { name: name.into() })?;
+        R::check_descriptor(&desc.as_queryable_context(), root_pos)
+            .context(ResultMismatch { name })?;
+        Ok(())
+    }
+}
@@ -9,6 +9,7 @@ use edgedb_protocol::client_message::{Prepare, IoFormat, Cardinality};
 use edgedb_protocol::client_message::{DescribeStatement, DescribeAspect};
 use edgedb_protocol::client_message::{SaslInitialResponse};
 use edgedb_protocol::client_message::{SaslResponse};
+use edgedb_protocol::client_message::{CancelRequest};
 
 mod base;
 
@@ -77,6 +78,23 @@ fn execute() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+#[test]
+fn headers() -> Result<(), Box<dyn Error>> {
+    let mut headers = HashMap::new();
+    headers.insert(0xFF01, Bytes::from_static(b"10"));
+    encoding_eq!(ClientMessage::DescribeStatement(DescribeStatement {
+        headers: headers.clone(),
+        aspect: DescribeAspect::DataDescription,
+        statement_name: Bytes::from_static(b"example"),
+    }), b"D\0\0\0\x1a\0\x01\xff\x01\0\0\0\x0210T\0\0\0\x07example");
+    encoding_eq!(ClientMessage::Execute(Execute {
+        headers: headers.clone(),
+        statement_name: Bytes::from_static(b"example"),
+        arguments: Bytes::new(),
+    }), b"E\0\0\0\x1d\0\x01\xff\x01\0\0\0\x0210\0\0\0\x07example\0\0\0\0");
+    Ok(())
+}
+
 #[test]
 fn sync() -> Result<(), Box<dyn Error>> {
     encoding_eq!(ClientMessage::Sync, b"S\0\0\0\x04");
@@ -95,6 +113,17 @@ fn terminate() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+#[test]
+fn cancel_request() -> Result<(), Box<dyn Error>> {
+    encoding_eq!(ClientMessage::CancelRequest(CancelRequest {
+        key_data: [7; 32],
+    }), b"C\0\0\0\x24\x07\x07\x07\x07\x07\x07\x07\x07\
+          \x07\x07\x07\x07\x07\x07\x07\x07\
+          \x07\x07\x07\x07\x07\x07\x07\x07\
+          \x07\x07\x07\x07\x07\x07\x07\x07");
+    Ok(())
+}
+
 #[test]
 fn authentication() -> Result<(), Box<dyn Error>> {
     encoding_eq!(ClientMessage::AuthenticationSaslInitialResponse(
@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+use bytes::Bytes;
+
+use edgedb_protocol::headers::{RequestHeaders, ResponseHeaders, Capabilities};
+use edgedb_protocol::headers::{decode_bool, decode_limit};
+use edgedb_protocol::headers::{IMPLICIT_LIMIT, IMPLICIT_TYPENAMES};
+use edgedb_protocol::headers::{ALLOW_CAPABILITIES, CAPABILITIES};
+use edgedb_protocol::server_message::CommandComplete;
+
+
+#[test]
+fn request_headers() -> Result<(), Box<dyn Error>> {
+    let headers = RequestHeaders::new()
+        .implicit_limit(100)
+        .implicit_typenames(true)
+        .allow_capabilities(Capabilities::MODIFICATIONS | Capabilities::DDL)
+        .build();
+    assert_eq!(headers.len(), 3);
+    assert_eq!(decode_limit(&headers[&IMPLICIT_LIMIT])?, 100);
+    assert!(decode_bool(IMPLICIT_TYPENAMES, &headers[&IMPLICIT_TYPENAMES])?);
+    assert_eq!(&headers[&ALLOW_CAPABILITIES][..],
+               b"\0\0\0\0\0\0\0\x09");
+    assert!(decode_bool(IMPLICIT_TYPENAMES,
+                        &Bytes::from_static(b"yes")).is_err());
+    Ok(())
+}
+
+#[test]
+fn response_headers() -> Result<(), Box<dyn Error>> {
+    let mut headers = HashMap::new();
+    headers.insert(CAPABILITIES, Bytes::from_static(b"\0\0\0\0\0\0\0\x03"));
+    headers.insert(0x1234, Bytes::from_static(b"custom"));
+    let complete = CommandComplete {
+        headers,
+        status_data: Bytes::from_static(b"INSERT"),
+    };
+    let decoded = complete.decode_headers()?;
+    let caps = decoded.capabilities.unwrap();
+    assert!(caps.contains(Capabilities::MODIFICATIONS));
+    assert!(caps.contains(Capabilities::SESSION_CONFIG));
+    assert!(!caps.contains(Capabilities::DDL));
+    assert_eq!(decoded.unknown.len(), 1);
+    assert_eq!(&decoded.unknown[&0x1234][..], b"custom");
+
+    let mut headers = HashMap::new();
+    headers.insert(CAPABILITIES, Bytes::from_static(b"\x03"));
+    assert!(ResponseHeaders::decode(&headers).is_err());
+    Ok(())
+}
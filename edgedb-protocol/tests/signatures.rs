@@ -0,0 +1,75 @@
+use std::error::Error;
+
+use bytes::{Bytes, BytesMut};
+
+use edgedb_protocol::server_message::{CommandDataDescription, Cardinality};
+use edgedb_protocol::signatures::{Signatures, Signature, SignatureError};
+use edgedb_protocol::value::Value;
+
+mod base;
+
+
+/// `SELECT 'hello'`
+fn select_str() -> Result<Signature, Box<dyn Error>> {
+    Ok(Signature {
+        name: "hello".into(),
+        query: "SELECT 'hello'".into(),
+        description: CommandDataDescription {
+            headers: Default::default(),
+            result_cardinality: Cardinality::One,
+            input_typedesc_id: "00000000-0000-0000-0000-0000000000ff"
+                .parse()?,
+            input_typedesc: Bytes::from_static(
+                b"\x04\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\xff\0\0"),
+            output_typedesc_id: "00000000-0000-0000-0000-000000000101"
+                .parse()?,
+            output_typedesc: Bytes::from_static(
+                b"\x02\0\0\0\0\0\0\0\0\0\0\0\0\0\0\x01\x01"),
+        },
+    })
+}
+
+fn roundtrip(sigs: &Signatures) -> Result<Signatures, Box<dyn Error>> {
+    let mut buf = BytesMut::new();
+    sigs.encode(&mut buf)?;
+    Ok(Signatures::decode(&buf.freeze())?)
+}
+
+#[test]
+fn encode_decode() -> Result<(), Box<dyn Error>> {
+    let mut sigs = Signatures::new();
+    sigs.add(select_str()?);
+    let mut other = select_str()?;
+    other.name = "other".into();
+    sigs.add(other);
+    let decoded = roundtrip(&sigs)?;
+    assert_eq!(decoded, sigs);
+    assert_eq!(decoded.iter().map(|s| &s.name[..]).collect::<Vec<_>>(),
+               vec!["hello", "other"]);
+    Ok(())
+}
+
+#[test]
+fn check() -> Result<(), Box<dyn Error>> {
+    let mut sigs = Signatures::new();
+    sigs.add(select_str()?);
+    let sigs = roundtrip(&sigs)?;
+    sigs.check::<String, _>("hello", &Value::empty_tuple())?;
+    match sigs.check::<i64, _>("hello", &Value::empty_tuple()) {
+        Err(SignatureError::ResultMismatch { name, .. }) => {
+            assert_eq!(name, "hello");
+        }
+        res => panic!("unexpected result {:?}", res),
+    }
+    match sigs.check::<String, _>("missing", &Value::empty_tuple()) {
+        Err(SignatureError::NotRecorded { name }) => {
+            assert_eq!(name, "missing");
+        }
+        res => panic!("unexpected result {:?}", res),
+    }
+    match sigs.check_args("hello", &Value::Str("x".into())) {
+        Err(SignatureError::ArgumentEncoding { .. }) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+    Ok(())
+}
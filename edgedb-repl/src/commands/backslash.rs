@@ -434,9 +434,9 @@ pub async fn execute<'x>(cli: &mut Client<'x>, cmd: Command,
             commands::list_roles(cli, &options, &pattern, case_sensitive).await
         }
         PostgresAddr => {
-            match cli.params.get::<PostgresAddress>() {
+            match PostgresAddress::from_client(cli) {
                 Some(addr) => {
-                    println!("{}", serde_json::to_string_pretty(addr)?);
+                    println!("{}", serde_json::to_string_pretty(&addr)?);
                 }
                 None => {
                     eprintln!("\\pgaddr requires EdgeDB to run in DEV mode");
@@ -489,8 +489,13 @@ pub async fn execute<'x>(cli: &mut Client<'x>, cmd: Command,
         }
         LastError => {
             if let Some(ref err) = prompt.last_error {
-                if let Some(ref err) = err.downcast_ref::<ErrorResponse>() {
-                    println!("{}", err.display_verbose());
+                let response = err.downcast_ref::<ErrorResponse>()
+                    .or_else(|| {
+                        err.downcast_ref::<edgedb_client::Error>()
+                            .and_then(|e| e.server_error())
+                    });
+                if let Some(response) = response {
+                    println!("{}", response.display_verbose());
                 } else {
                     println!("{:#?}", err);
                 }
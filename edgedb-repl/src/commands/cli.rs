@@ -1,8 +1,8 @@
 use async_std::task;
 
 use edgeql_parser::helpers::quote_name;
-use crate::options::{Options, Command};
-use crate::client::Connection;
+use crate::options::{Options, Command, GenerateCommand, DebugCommand};
+use crate::client;
 use crate::commands;
 use crate::server_params::PostgresAddress;
 
@@ -14,8 +14,8 @@ pub fn main(options: Options) -> Result<(), anyhow::Error> {
     match options.subcommand.as_ref().expect("subcommand is present") {
         Command::CreateDatabase(d) => {
             task::block_on(async {
-                let mut conn = Connection::from_options(&options).await?;
-                let mut cli = conn.authenticate(&options).await?;
+                let mut conn = client::connect(&options).await?;
+                let mut cli = conn.authenticate().await?;
                 let res = cli.execute(&format!("CREATE DATABASE {}",
                                      quote_name(&d.database_name))).await?;
                 eprintln!("  -> {}: Ok",
@@ -25,8 +25,8 @@ pub fn main(options: Options) -> Result<(), anyhow::Error> {
         },
         Command::ListAliases(t) => {
             task::block_on(async {
-                let mut conn = Connection::from_options(&options).await?;
-                let mut cli = conn.authenticate(&options).await?;
+                let mut conn = client::connect(&options).await?;
+                let mut cli = conn.authenticate().await?;
                 commands::list_aliases(&mut cli, &cmdopt,
                     &t.pattern, t.system, t.case_sensitive, t.verbose).await?;
                 Ok(())
@@ -34,8 +34,8 @@ pub fn main(options: Options) -> Result<(), anyhow::Error> {
         },
         Command::ListCasts(t) => {
             task::block_on(async {
-                let mut conn = Connection::from_options(&options).await?;
-                let mut cli = conn.authenticate(&options).await?;
+                let mut conn = client::connect(&options).await?;
+                let mut cli = conn.authenticate().await?;
                 commands::list_casts(&mut cli, &cmdopt,
                     &t.pattern, t.case_sensitive).await?;
                 Ok(())
@@ -43,8 +43,8 @@ pub fn main(options: Options) -> Result<(), anyhow::Error> {
         },
         Command::ListIndexes(t) => {
             task::block_on(async {
-                let mut conn = Connection::from_options(&options).await?;
-                let mut cli = conn.authenticate(&options).await?;
+                let mut conn = client::connect(&options).await?;
+                let mut cli = conn.authenticate().await?;
                 commands::list_indexes(&mut cli, &cmdopt,
                     &t.pattern, t.system, t.case_sensitive, t.verbose).await?;
                 Ok(())
@@ -52,16 +52,16 @@ pub fn main(options: Options) -> Result<(), anyhow::Error> {
         },
         Command::ListDatabases => {
             task::block_on(async {
-                let mut conn = Connection::from_options(&options).await?;
-                let mut cli = conn.authenticate(&options).await?;
+                let mut conn = client::connect(&options).await?;
+                let mut cli = conn.authenticate().await?;
                 commands::list_databases(&mut cli, &cmdopt).await?;
                 Ok(())
             }).into()
         },
         Command::ListScalarTypes(t) => {
             task::block_on(async {
-                let mut conn = Connection::from_options(&options).await?;
-                let mut cli = conn.authenticate(&options).await?;
+                let mut conn = client::connect(&options).await?;
+                let mut cli = conn.authenticate().await?;
                 commands::list_scalar_types(&mut cli, &cmdopt,
                     &t.pattern, t.system, t.case_sensitive).await?;
                 Ok(())
@@ -69,8 +69,8 @@ pub fn main(options: Options) -> Result<(), anyhow::Error> {
         },
         Command::ListObjectTypes(t) => {
             task::block_on(async {
-                let mut conn = Connection::from_options(&options).await?;
-                let mut cli = conn.authenticate(&options).await?;
+                let mut conn = client::connect(&options).await?;
+                let mut cli = conn.authenticate().await?;
                 commands::list_object_types(&mut cli, &cmdopt,
                     &t.pattern, t.system, t.case_sensitive).await?;
                 Ok(())
@@ -78,8 +78,8 @@ pub fn main(options: Options) -> Result<(), anyhow::Error> {
         },
         Command::ListRoles(t) => {
             task::block_on(async {
-                let mut conn = Connection::from_options(&options).await?;
-                let mut cli = conn.authenticate(&options).await?;
+                let mut conn = client::connect(&options).await?;
+                let mut cli = conn.authenticate().await?;
                 commands::list_roles(&mut cli, &cmdopt,
                     &t.pattern, t.case_sensitive).await?;
                 Ok(())
@@ -87,8 +87,8 @@ pub fn main(options: Options) -> Result<(), anyhow::Error> {
         },
         Command::ListModules(t) => {
             task::block_on(async {
-                let mut conn = Connection::from_options(&options).await?;
-                let mut cli = conn.authenticate(&options).await?;
+                let mut conn = client::connect(&options).await?;
+                let mut cli = conn.authenticate().await?;
                 commands::list_modules(&mut cli, &cmdopt,
                     &t.pattern, t.case_sensitive).await?;
                 Ok(())
@@ -96,11 +96,11 @@ pub fn main(options: Options) -> Result<(), anyhow::Error> {
         },
         Command::Pgaddr => {
             task::block_on(async {
-                let mut conn = Connection::from_options(&options).await?;
-                let cli = conn.authenticate(&options).await?;
-                match cli.params.get::<PostgresAddress>() {
+                let mut conn = client::connect(&options).await?;
+                let cli = conn.authenticate().await?;
+                match PostgresAddress::from_client(&cli) {
                     Some(addr) => {
-                        println!("{}", serde_json::to_string_pretty(addr)?);
+                        println!("{}", serde_json::to_string_pretty(&addr)?);
                     }
                     None => {
                         eprintln!("pgaddr requires EdgeDB to run in DEV mode");
@@ -111,16 +111,16 @@ pub fn main(options: Options) -> Result<(), anyhow::Error> {
         },
         Command::Psql => {
             task::block_on(async {
-                let mut conn = Connection::from_options(&options).await?;
-                let mut cli = conn.authenticate(&options).await?;
+                let mut conn = client::connect(&options).await?;
+                let mut cli = conn.authenticate().await?;
                 commands::psql(&mut cli, &cmdopt).await?;
                 Ok(())
             }).into()
         },
         Command::Describe(d) => {
             task::block_on(async {
-                let mut conn = Connection::from_options(&options).await?;
-                let mut cli = conn.authenticate(&options).await?;
+                let mut conn = client::connect(&options).await?;
+                let mut cli = conn.authenticate().await?;
                 commands::describe(&mut cli, &cmdopt,
                     &d.name, d.verbose).await?;
                 Ok(())
@@ -128,16 +128,16 @@ pub fn main(options: Options) -> Result<(), anyhow::Error> {
         },
         Command::Configure(c) => {
             task::block_on(async {
-                let mut conn = Connection::from_options(&options).await?;
-                let mut cli = conn.authenticate(&options).await?;
+                let mut conn = client::connect(&options).await?;
+                let mut cli = conn.authenticate().await?;
                 commands::configure(&mut cli, &cmdopt, &c).await?;
                 Ok(())
             }).into()
         }
         Command::CreateSuperuserRole(opt) => {
             task::block_on(async {
-                let mut conn = Connection::from_options(&options).await?;
-                let mut cli = conn.authenticate(&options).await?;
+                let mut conn = client::connect(&options).await?;
+                let mut cli = conn.authenticate().await?;
                 commands::roles::create_superuser(
                     &mut cli, &cmdopt, opt).await?;
                 Ok(())
@@ -145,19 +145,57 @@ pub fn main(options: Options) -> Result<(), anyhow::Error> {
         },
         Command::AlterRole(opt) => {
             task::block_on(async {
-                let mut conn = Connection::from_options(&options).await?;
-                let mut cli = conn.authenticate(&options).await?;
+                let mut conn = client::connect(&options).await?;
+                let mut cli = conn.authenticate().await?;
                 commands::roles::alter(&mut cli, &cmdopt, opt).await?;
                 Ok(())
             }).into()
         },
         Command::DropRole(opt) => {
             task::block_on(async {
-                let mut conn = Connection::from_options(&options).await?;
-                let mut cli = conn.authenticate(&options).await?;
+                let mut conn = client::connect(&options).await?;
+                let mut cli = conn.authenticate().await?;
                 commands::roles::drop(&mut cli, &cmdopt, &opt.role).await?;
                 Ok(())
             }).into()
         },
+        Command::Generate(g) => match &g.command {
+            GenerateCommand::Rust(opt) => match &opt.from_snapshot {
+                Some(path) => {
+                    let snapshot = commands::schema_snapshot::load(path)?;
+                    commands::generate_rust::write(&snapshot, opt)
+                }
+                None => {
+                    task::block_on(async {
+                        let mut conn = client::connect(&options)
+                            .await?;
+                        let mut cli = conn.authenticate().await?;
+                        commands::generate_rust::generate_rust(
+                            &mut cli, &cmdopt, opt).await?;
+                        Ok(())
+                    }).into()
+                }
+            },
+        },
+        Command::RecordSignatures(r) => {
+            task::block_on(async {
+                let mut conn = client::connect(&options).await?;
+                let mut cli = conn.authenticate().await?;
+                commands::record_signatures(&mut cli, &cmdopt, r).await?;
+                Ok(())
+            }).into()
+        },
+        Command::SchemaSnapshot(s) => {
+            task::block_on(async {
+                let mut conn = client::connect(&options).await?;
+                let mut cli = conn.authenticate().await?;
+                commands::schema_snapshot(&mut cli, &cmdopt,
+                    &s.output).await?;
+                Ok(())
+            }).into()
+        },
+        Command::Debug(d) => match &d.command {
+            DebugCommand::DecodeCapture(c) => commands::decode_capture(c),
+        },
     }
 }
@@ -0,0 +1,34 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use anyhow::Context;
+
+use edgedb_client::capture::{self, Direction};
+
+use crate::options::DecodeCapture;
+
+
+pub fn decode_capture(options: &DecodeCapture) -> Result<(), anyhow::Error> {
+    let records = capture::read_file(&options.file)
+        .with_context(|| format!("error reading {:?}", options.file))?;
+    let start = records.first().map(|r| r.timestamp).unwrap_or(UNIX_EPOCH);
+    for record in &records {
+        let offset = record.timestamp.duration_since(start)
+            .unwrap_or(Duration::from_secs(0));
+        let (arrow, message) = match record.direction {
+            Direction::Sent => {
+                ("->", record.decode_client().map(|m| format!("{:#?}", m)))
+            }
+            Direction::Received => {
+                ("<-", record.decode_server().map(|m| format!("{:#?}", m)))
+            }
+        };
+        let message = message.unwrap_or_else(|e| {
+            format!("<undecodable {:?} message, {} bytes: {}>",
+                    record.message_type() as char, record.frame.len(), e)
+        });
+        println!("[{:>4}.{:03}] #{} {} {}",
+                 offset.as_secs(), offset.subsec_millis(),
+                 record.connection, arrow, message);
+    }
+    Ok(())
+}
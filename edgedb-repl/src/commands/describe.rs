@@ -10,7 +10,7 @@ pub async fn describe<'x>(cli: &mut Client<'x>, _options: &Options,
     name: &str, verbose: bool)
     -> Result<(), anyhow::Error>
 {
-    let mut items = cli.query::<String>(
+    let mut items = cli.query::<String, _>(
         &format!("DESCRIBE OBJECT {name} AS TEXT {flag}",
             name=quote_name(name),
             flag=if verbose { "VERBOSE" } else {""}),
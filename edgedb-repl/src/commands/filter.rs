@@ -1,16 +1,30 @@
+use edgedb_client::query_builder::{Expr, call, field, not, param};
 use edgedb_protocol::value::Value;
 
+
+/// Modules of the standard library, hidden unless system types are requested
+pub const SYSTEM_MODULES: &str =
+    "^(?:std|schema|math|sys|cfg|cal|stdgraphql)::";
+
+/// Filter expression excluding objects from the `SYSTEM_MODULES`
+pub fn not_system() -> Expr {
+    not(call("re_test", vec![param(SYSTEM_MODULES), field("name")]))
+}
+/// Regular expression for `re_test`, case insensitive unless requested
+pub fn pattern(pattern: &str, case_sensitive: bool) -> String {
+    if case_sensitive {
+        pattern.into()
+    } else {
+        String::from("(?i)") + pattern
+    }
+}
+
 pub fn pattern_to_value(pattern: &Option<String>, case_sensitive: bool)
     -> Value
 {
     match pattern {
-        Some(pattern) => {
-            let pattern = if case_sensitive {
-                pattern.clone()
-            } else {
-                String::from("(?i)") + pattern
-            };
-            Value::Tuple(vec![Value::Str(pattern)])
+        Some(p) => {
+            Value::Tuple(vec![Value::Str(self::pattern(p, case_sensitive))])
         }
         None => Value::Tuple(Vec::new()),
     }
@@ -0,0 +1,537 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+
+use crate::commands::Options;
+use crate::commands::schema_snapshot::{self, Snapshot, ObjectType, Pointer};
+use crate::commands::schema_snapshot::ScalarType;
+use crate::client::Client;
+use crate::options::{GenerateRust, Naming};
+
+
+const KEYWORDS: &[&str] = &[
+    "as", "break", "const", "continue", "crate", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod",
+    "move", "mut", "pub", "ref", "return", "self", "Self", "static",
+    "struct", "super", "trait", "true", "type", "unsafe", "use", "where",
+    "while", "async", "await", "dyn", "abstract", "become", "box", "do",
+    "final", "macro", "override", "priv", "typeof", "unsized", "virtual",
+    "yield", "try",
+];
+
+const HEADER: &str = "// Generated by `edgedb generate rust`, do not edit.\n";
+
+const ENUM_IMPORTS: &str = "\
+use std::io::Cursor;
+
+use bytes::Bytes;
+use edgedb_protocol::codec::raw::RawCodec;
+use edgedb_protocol::descriptors::{Descriptor, TypePos};
+use edgedb_protocol::errors::{self, DecodeError};
+use edgedb_protocol::queryable::{Queryable, DescriptorContext};
+use edgedb_protocol::queryable::DescriptorMismatch;
+";
+
+const ENUM_IMPL: &str = "\
+impl Queryable for $NAME {
+    fn decode_raw(buf: &mut Cursor<Bytes>) -> Result<Self, DecodeError> {
+        let value: String = RawCodec::decode_raw(buf)?;
+        match &value[..] {
+$VARIANTS            _ => errors::ExtraEnumValue.fail(),
+        }
+    }
+    fn check_descriptor(ctx: &DescriptorContext, type_pos: TypePos)
+        -> Result<(), DescriptorMismatch>
+    {
+        let desc = ctx.get(type_pos)?;
+        match desc {
+            Descriptor::Enumeration(_) => Ok(()),
+            _ => Err(ctx.wrong_type(desc, $SCHEMA_NAME)),
+        }
+    }
+}
+";
+
+struct Generator<'a> {
+    naming: Naming,
+    /// Rust names of the types being generated, by schema name
+    names: BTreeMap<&'a str, String>,
+    /// Names of `Ref` structs for the types that are targets of links
+    refs: BTreeMap<&'a str, String>,
+}
+
+fn split_name(name: &str) -> (&str, &str) {
+    match name.rfind("::") {
+        Some(idx) => (&name[..idx], &name[idx+2..]),
+        None => ("default", name),
+    }
+}
+
+/// Replaces characters that can't be used in identifiers by underscores,
+/// e.g. in enum values like `in-progress` or `2x`
+fn sanitize(name: &str) -> String {
+    let mut result = name.chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    if result.chars().next().map(|c| c.is_numeric()).unwrap_or(true) {
+        result.insert(0, 'V');
+    }
+    result
+}
+
+fn camel_case(name: &str) -> String {
+    let mut result = String::with_capacity(name.len());
+    let mut upper = true;
+    for c in name.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            result.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+fn snake_case(name: &str) -> String {
+    let mut result = String::with_capacity(name.len() + 4);
+    let mut prev_lower = false;
+    for c in name.chars() {
+        if c.is_uppercase() {
+            if prev_lower {
+                result.push('_');
+            }
+            result.extend(c.to_lowercase());
+            prev_lower = false;
+        } else {
+            result.push(c);
+            prev_lower = c.is_lowercase() || c.is_numeric();
+        }
+    }
+    result
+}
+
+/// Adds a numeric suffix if the name is already taken, as different schema
+/// names may end up the same after sanitizing, e.g. `foo-bar` and `foo_bar`
+fn unique(name: String, used: &mut BTreeSet<String>) -> String {
+    let mut result = name.clone();
+    let mut n = 1;
+    while used.contains(&result) {
+        n += 1;
+        result = format!("{}{}", name, n);
+    }
+    used.insert(result.clone());
+    result
+}
+
+fn escape_keyword(name: String) -> String {
+    if KEYWORDS.contains(&&name[..]) {
+        name + "_"
+    } else {
+        name
+    }
+}
+
+fn std_scalar(name: &str) -> Option<&'static str> {
+    let ty = match name {
+        "std::str" => "String",
+        "std::uuid" => "uuid::Uuid",
+        "std::bool" => "bool",
+        "std::int16" => "i16",
+        "std::int32" => "i32",
+        "std::int64" => "i64",
+        "std::float32" => "f32",
+        "std::float64" => "f64",
+        _ => return None,
+    };
+    Some(ty)
+}
+
+impl<'a> Generator<'a> {
+    fn new(snapshot: &'a Snapshot, options: &GenerateRust)
+        -> Generator<'a>
+    {
+        let selected = snapshot.scalar_types.iter().map(|s| &s.name[..])
+            .chain(snapshot.object_types.iter().map(|t| &t.name[..]))
+            .filter(|name| {
+                options.modules.is_empty() ||
+                options.modules.iter().any(|m| m == split_name(name).0)
+            })
+            .collect::<Vec<_>>();
+        let modules = selected.iter()
+            .map(|name| split_name(name).0)
+            .collect::<BTreeSet<_>>();
+        let link_targets = snapshot.object_types.iter()
+            .flat_map(|t| t.links.iter().map(|l| &l.target[..]))
+            .collect::<BTreeSet<_>>();
+        let mut gen = Generator {
+            naming: options.naming,
+            names: BTreeMap::new(),
+            refs: BTreeMap::new(),
+        };
+        let mut used = BTreeSet::new();
+        for name in selected {
+            let rust_name = gen.type_name(name, modules.len() > 1);
+            let rust_name = unique(rust_name, &mut used);
+            gen.names.insert(name, rust_name);
+        }
+        let refs = gen.names.iter()
+            .filter(|(name, _)| link_targets.contains(*name))
+            .map(|(name, rust_name)| {
+                (*name, unique(format!("{}Ref", rust_name), &mut used))
+            })
+            .collect();
+        gen.refs = refs;
+        gen
+    }
+
+    fn ident(&self, name: &str, camel: bool) -> String {
+        let name = sanitize(name);
+        match self.naming {
+            Naming::Rust if camel => escape_keyword(camel_case(&name)),
+            Naming::Rust => escape_keyword(snake_case(&name)),
+            Naming::Schema => escape_keyword(name),
+        }
+    }
+
+    /// Types from modules other than `default` are prefixed by the module
+    /// name when several modules are generated, to avoid name clashes
+    fn type_name(&self, name: &str, qualify: bool) -> String {
+        let (module, short) = split_name(name);
+        if qualify && module != "default" {
+            let name = format!("{}_{}", module.replace("::", "_"), short);
+            self.ident(&name, true)
+        } else {
+            self.ident(short, true)
+        }
+    }
+
+    /// Rust type of a single element of the pointer
+    fn target_type(&self, target: &str) -> Option<String> {
+        let array = target.starts_with("array<") && target.ends_with('>');
+        let elem = if array {
+            &target["array<".len()..target.len()-1]
+        } else {
+            target
+        };
+        let ty = std_scalar(elem).map(|x| x.to_string())
+            .or_else(|| self.names.get(elem).cloned())?;
+        if array {
+            Some(format!("Vec<{}>", ty))
+        } else {
+            Some(ty)
+        }
+    }
+
+    fn scalar(&self, scalar: &ScalarType) -> Option<String> {
+        let name = self.names.get(&scalar.name[..])?;
+        if let Some(values) = &scalar.enum_values {
+            return Some(self.enumeration(&scalar.name, name, values));
+        }
+        let base = scalar.ancestors.iter().filter_map(|a| std_scalar(a))
+            .next();
+        match base {
+            Some(base) => Some(format!("/// Scalar type `{}`\n\
+                                        pub type {} = {};\n",
+                                       scalar.name, name, base)),
+            None => Some(format!("// scalar type `{}` is skipped\n",
+                                 scalar.name)),
+        }
+    }
+
+    fn enumeration(&self, schema_name: &str, name: &str, values: &[String])
+        -> String
+    {
+        let mut used = BTreeSet::new();
+        let variants = values.iter()
+            .map(|v| unique(self.ident(v, true), &mut used))
+            .collect::<Vec<_>>();
+        let mut result = format!(
+            "/// Enum type `{}`\n\
+             #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]\n\
+             pub enum {} {{\n", schema_name, name);
+        for variant in &variants {
+            result += &format!("    {},\n", variant);
+        }
+        result += "}\n\n";
+        let arms = values.iter().zip(&variants)
+            .map(|(value, variant)| {
+                format!("            {:?} => Ok({}::{}),\n",
+                        value, name, variant)
+            })
+            .collect::<String>();
+        result += &ENUM_IMPL
+            .replace("$SCHEMA_NAME", &format!("{:?}", schema_name))
+            .replace("$NAME", name)
+            .replace("$VARIANTS", &arms);
+        result
+    }
+
+    fn field(&self, obj: &ObjectType, pointer: &Pointer, link: bool,
+        used: &mut BTreeSet<String>)
+        -> String
+    {
+        let ty = if link {
+            self.refs.get(&pointer.target[..]).cloned()
+        } else {
+            self.target_type(&pointer.target)
+        };
+        let ty = match ty {
+            Some(ty) => ty,
+            None => {
+                return format!(
+                    "    // {}.{} of type `{}` is skipped\n",
+                    obj.name, pointer.name, pointer.target);
+            }
+        };
+        let ty = match (&pointer.cardinality[..], pointer.required) {
+            ("MANY", _) => format!("Vec<{}>", ty),
+            (_, true) => ty,
+            (_, false) => format!("Option<{}>", ty),
+        };
+        let name = unique(self.ident(&pointer.name, false), used);
+        let mut result = String::new();
+        if name != pointer.name {
+            result += &format!("    #[edgedb(rename={:?})]\n", pointer.name);
+        }
+        result += &format!("    pub {}: {},\n", name, ty);
+        result
+    }
+
+    fn object(&self, obj: &ObjectType) -> Option<String> {
+        let name = self.names.get(&obj.name[..])?;
+        let mut result = format!(
+            "/// Object type `{}`\n\
+             #[derive(Queryable, Debug)]\n\
+             pub struct {} {{\n", obj.name, name);
+        let mut used = BTreeSet::new();
+        for prop in &obj.properties {
+            result += &self.field(obj, prop, false, &mut used);
+        }
+        for link in &obj.links {
+            result += &self.field(obj, link, true, &mut used);
+        }
+        result += "}\n";
+        // links are decoded into references rather than full objects, so
+        // cyclic schemas don't require selecting the whole graph
+        if let Some(ref_name) = self.refs.get(&obj.name[..]) {
+            result += &format!(
+                "\n/// Link to an object of type `{}`, i.e. `{{ id }}`\n\
+                 #[derive(Queryable, Debug)]\n\
+                 pub struct {} {{\n    \
+                     pub id: uuid::Uuid,\n\
+                 }}\n", obj.name, ref_name);
+        }
+        Some(result)
+    }
+}
+
+pub fn generate(snapshot: &Snapshot, options: &GenerateRust) -> String {
+    let gen = Generator::new(snapshot, options);
+    let mut items = Vec::new();
+    items.extend(snapshot.scalar_types.iter().filter_map(|s| gen.scalar(s)));
+    items.extend(snapshot.object_types.iter().filter_map(|t| gen.object(t)));
+    let mut result = String::from(HEADER);
+    match options.naming {
+        Naming::Rust => result += "#![allow(dead_code)]\n",
+        Naming::Schema => {
+            result += "#![allow(dead_code, non_camel_case_types, \
+                       non_snake_case)]\n";
+        }
+    }
+    result += "\n";
+    let has_enums = snapshot.scalar_types.iter()
+        .filter(|s| gen.names.contains_key(&s.name[..]))
+        .any(|s| s.enum_values.is_some());
+    if has_enums {
+        result += ENUM_IMPORTS;
+    }
+    if snapshot.object_types.iter()
+        .any(|t| gen.names.contains_key(&t.name[..]))
+    {
+        result += "use edgedb_derive::Queryable;\n";
+    }
+    for item in items {
+        result.push('\n');
+        result += &item;
+    }
+    result
+}
+
+pub async fn generate_rust(cli: &mut Client<'_>, _options: &Options,
+    options: &GenerateRust)
+    -> Result<(), anyhow::Error>
+{
+    let snapshot = schema_snapshot::fetch(cli).await?;
+    write(&snapshot, options)
+}
+
+pub fn write(snapshot: &Snapshot, options: &GenerateRust)
+    -> Result<(), anyhow::Error>
+{
+    let code = generate(snapshot, options);
+    match &options.output {
+        Some(path) => fs::write(path, code)?,
+        None => print!("{}", code),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::commands::schema_snapshot::Snapshot;
+    use crate::options::{GenerateRust, Naming};
+    use super::generate;
+
+    const SNAPSHOT: &str = r#"{
+        "object_types": [
+            {"name": "default::Task", "properties": [
+                {"name": "id", "target": "std::uuid",
+                 "cardinality": "ONE", "required": true},
+                {"name": "status", "target": "default::Status",
+                 "cardinality": "ONE", "required": true},
+                {"name": "type", "target": "std::str",
+                 "cardinality": "ONE", "required": false}
+            ], "links": [
+                {"name": "parent", "target": "default::Task",
+                 "cardinality": "ONE", "required": false},
+                {"name": "subTasks", "target": "default::Task",
+                 "cardinality": "MANY", "required": false}
+            ]}
+        ],
+        "scalar_types": [
+            {"name": "default::Status", "ancestors": ["std::anyenum"],
+             "enum_values": ["in-progress", "2x", "done"]}
+        ]
+    }"#;
+
+    fn options(naming: Naming) -> GenerateRust {
+        GenerateRust {
+            modules: Vec::new(),
+            naming,
+            from_snapshot: None,
+            output: None,
+        }
+    }
+
+    #[test]
+    fn rust_naming() {
+        let snapshot = serde_json::from_str(SNAPSHOT).unwrap();
+        let code = generate(&snapshot, &options(Naming::Rust));
+        assert_eq!(code, EXPECTED_RUST.trim_start());
+    }
+
+    #[test]
+    fn schema_naming_without_enums() {
+        let mut snapshot: Snapshot = serde_json::from_str(SNAPSHOT).unwrap();
+        snapshot.scalar_types.clear();
+        let code = generate(&snapshot, &options(Naming::Schema));
+        assert!(!code.contains("use edgedb_protocol::"));
+        assert!(code.contains("use edgedb_derive::Queryable;\n"));
+        assert!(code.contains("    pub subTasks: Vec<TaskRef>,\n"));
+        assert!(code.contains("    pub type_: Option<String>,\n"));
+        // enum type is not generated, so the property is skipped
+        assert!(code.contains(
+            "    // default::Task.status of type `default::Status` \
+            is skipped\n"));
+    }
+
+    #[test]
+    fn name_collisions() {
+        let snapshot = serde_json::from_str(r#"{
+            "object_types": [
+                {"name": "default::User", "properties": [
+                    {"name": "type", "target": "std::str",
+                     "cardinality": "ONE", "required": true},
+                    {"name": "type_", "target": "std::str",
+                     "cardinality": "ONE", "required": true}
+                ], "links": [
+                    {"name": "friend", "target": "default::User",
+                     "cardinality": "ONE", "required": false}
+                ]},
+                {"name": "default::UserRef", "properties": [],
+                 "links": []},
+                {"name": "default::user", "properties": [], "links": []}
+            ],
+            "scalar_types": [
+                {"name": "default::Mode", "ancestors": ["std::anyenum"],
+                 "enum_values": ["read-only", "read_only"]}
+            ]
+        }"#).unwrap();
+        let code = generate(&snapshot, &options(Naming::Rust));
+        assert!(code.contains("    ReadOnly,\n    ReadOnly2,\n"));
+        assert!(code.contains("\"read_only\" => Ok(Mode::ReadOnly2),"));
+        assert!(code.contains("pub struct User {\n"));
+        assert!(code.contains("pub struct UserRef {\n"));
+        assert!(code.contains("pub struct User2 {\n"));
+        assert!(code.contains("pub struct UserRef2 {\n"));
+        assert!(code.contains("    pub friend: Option<UserRef2>,\n"));
+        assert!(code.contains(
+            "    #[edgedb(rename=\"type\")]\n    pub type_: String,\n"));
+        assert!(code.contains(
+            "    #[edgedb(rename=\"type_\")]\n    pub type_2: String,\n"));
+    }
+
+    const EXPECTED_RUST: &str = r#"
+// Generated by `edgedb generate rust`, do not edit.
+#![allow(dead_code)]
+
+use std::io::Cursor;
+
+use bytes::Bytes;
+use edgedb_protocol::codec::raw::RawCodec;
+use edgedb_protocol::descriptors::{Descriptor, TypePos};
+use edgedb_protocol::errors::{self, DecodeError};
+use edgedb_protocol::queryable::{Queryable, DescriptorContext};
+use edgedb_protocol::queryable::DescriptorMismatch;
+use edgedb_derive::Queryable;
+
+/// Enum type `default::Status`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Status {
+    InProgress,
+    V2x,
+    Done,
+}
+
+impl Queryable for Status {
+    fn decode_raw(buf: &mut Cursor<Bytes>) -> Result<Self, DecodeError> {
+        let value: String = RawCodec::decode_raw(buf)?;
+        match &value[..] {
+            "in-progress" => Ok(Status::InProgress),
+            "2x" => Ok(Status::V2x),
+            "done" => Ok(Status::Done),
+            _ => errors::ExtraEnumValue.fail(),
+        }
+    }
+    fn check_descriptor(ctx: &DescriptorContext, type_pos: TypePos)
+        -> Result<(), DescriptorMismatch>
+    {
+        let desc = ctx.get(type_pos)?;
+        match desc {
+            Descriptor::Enumeration(_) => Ok(()),
+            _ => Err(ctx.wrong_type(desc, "default::Status")),
+        }
+    }
+}
+
+/// Object type `default::Task`
+#[derive(Queryable, Debug)]
+pub struct Task {
+    pub id: uuid::Uuid,
+    pub status: Status,
+    #[edgedb(rename="type")]
+    pub type_: Option<String>,
+    pub parent: Option<TaskRef>,
+    #[edgedb(rename="subTasks")]
+    pub sub_tasks: Vec<TaskRef>,
+}
+
+/// Link to an object of type `default::Task`, i.e. `{ id }`
+#[derive(Queryable, Debug)]
+pub struct TaskRef {
+    pub id: uuid::Uuid,
+}
+"#;
+}
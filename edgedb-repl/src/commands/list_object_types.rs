@@ -2,6 +2,8 @@ use async_std::prelude::StreamExt;
 
 use prettytable::{Table, Row, Cell};
 
+use edgedb_client::query_builder::{with, select, name, field, param, raw};
+use edgedb_client::query_builder::{call, not, Shape, Order, Select};
 use edgedb_derive::Queryable;
 use crate::commands::Options;
 use crate::commands::filter;
@@ -16,40 +18,43 @@ struct TypeRow {
     extending: String,
 }
 
+/// Object types listed by `\lt`, ordered by name and without a shape
+///
+/// Must be used in a query with `schema` as the default module.
+pub fn select_object_types(pattern: &Option<String>, system: bool,
+    case_sensitive: bool)
+    -> Select
+{
+    let mut select = select(name("ObjectType"));
+    if system || pattern.is_some() {
+        select.filter(not(field("is_from_alias")));
+    }
+    if let Some(pattern) = pattern {
+        let pattern = filter::pattern(pattern, case_sensitive);
+        select.filter(call("re_test", vec![param(pattern), field("name")]));
+    }
+    if !system {
+        select.filter(filter::not_system());
+    }
+    select.order_by(field("name"), Order::Asc);
+    select
+}
+
 pub async fn list_object_types<'x>(cli: &mut Client<'x>, options: &Options,
     pattern: &Option<String>, system: bool, case_sensitive: bool)
     -> Result<(), anyhow::Error>
 {
-    let pat = filter::pattern_to_value(pattern, case_sensitive);
-    let filter = match (pattern, system) {
-        (None, true) => "FILTER NOT .is_from_alias",
-        (None, false) => {
-            r#"FILTER NOT
-                re_test("^(?:std|schema|math|sys|cfg|cal|stdgraphql)::",
-                .name)"#
-        }
-        (Some(_), true) => {
-            "FILTER NOT .is_from_alias AND re_test(<str>$0, .name)"
-        }
-        (Some(_), false) => {
-            r#"FILTER NOT .is_from_alias
-                AND re_test(<str>$0, .name) AND
-                NOT re_test("^(?:std|schema|math|sys|cfg|cal|stdgraphql)::",
-                .name)"#
-        }
-    };
-
-    let query = &format!(r###"
-        WITH MODULE schema
-        SELECT ObjectType {{
-            name,
-            `extending` := to_str(array_agg(.ancestors.name), ', '),
-        }}
-        {filter}
-        ORDER BY .name;
-    "###, filter=filter);
+    let mut select = select_object_types(pattern, system, case_sensitive);
+    select.shape(Shape::new()
+        .field("name")
+        .computed("extending", call("to_str", vec![
+            call("array_agg", vec![raw(".ancestors.name")]),
+            param(", "),
+        ])));
+    let query = with().module("schema").body(&select).build();
 
-    let mut items = cli.query::<TypeRow>(&query, &pat).await?;
+    let mut items = cli.query::<TypeRow, _>(query.text(), query.arguments())
+        .await?;
     if !options.command_line || atty::is(atty::Stream::Stdout) {
         let mut table = Table::new();
         table.set_format(*table::FORMAT);
@@ -2,6 +2,8 @@ pub mod cli;
 pub mod backslash;
 pub mod options;
 mod configure;
+mod decode_capture;
+pub mod generate_rust;
 mod filter;
 mod helpers;
 mod list;
@@ -14,12 +16,15 @@ mod list_scalar_types;
 mod list_modules;
 mod list_roles;
 mod psql;
+mod record_signatures;
 mod roles;
+pub mod schema_snapshot;
 mod table;
 mod type_names;
 mod describe;
 
 pub use self::configure::configure;
+pub use self::decode_capture::decode_capture;
 pub use self::describe::describe;
 pub use self::list_aliases::list_aliases;
 pub use self::list_casts::list_casts;
@@ -31,3 +36,5 @@ pub use self::list_object_types::list_object_types;
 pub use self::list_scalar_types::list_scalar_types;
 pub use self::options::Options;
 pub use self::psql::psql;
+pub use self::record_signatures::record_signatures;
+pub use self::schema_snapshot::schema_snapshot;
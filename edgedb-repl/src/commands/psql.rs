@@ -9,7 +9,7 @@ use crate::server_params::PostgresAddress;
 pub async fn psql<'x>(cli: &mut Client<'x>, _options: &Options)
     -> Result<(), anyhow::Error>
 {
-    match cli.params.get::<PostgresAddress>() {
+    match PostgresAddress::from_client(cli) {
         Some(addr) => {
             let mut cmd = Command::new("psql");
             cmd.arg("-h").arg(&addr.host);
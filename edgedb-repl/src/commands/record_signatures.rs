@@ -0,0 +1,47 @@
+use std::fs;
+use std::path::Path;
+
+use bytes::BytesMut;
+
+use edgedb_protocol::signatures::{Signatures, Signature};
+use crate::commands::Options;
+use crate::client::Client;
+use crate::options::RecordSignatures;
+
+
+/// Names of the queries are file names of `*.edgeql` files without
+/// extension
+fn read_queries(dir: &Path) -> Result<Vec<(String, String)>, anyhow::Error> {
+    let mut queries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().map(|e| e != "edgeql").unwrap_or(true) {
+            continue;
+        }
+        let name = match path.file_stem().and_then(|s| s.to_str()) {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        queries.push((name, fs::read_to_string(&path)?));
+    }
+    queries.sort();
+    Ok(queries)
+}
+
+pub async fn record_signatures(cli: &mut Client<'_>, _options: &Options,
+    params: &RecordSignatures)
+    -> Result<(), anyhow::Error>
+{
+    let mut signatures = Signatures::new();
+    for (name, query) in read_queries(&params.queries)? {
+        let description = cli.describe_query(&query).await
+            .map_err(|e| anyhow::anyhow!("query {:?}: {}", name, e))?;
+        signatures.add(Signature { name, query, description });
+    }
+    let mut buf = BytesMut::new();
+    signatures.encode(&mut buf)?;
+    fs::write(&params.output, &buf[..])?;
+    eprintln!("Recorded {} queries into {}",
+        signatures.iter().count(), params.output.display());
+    Ok(())
+}
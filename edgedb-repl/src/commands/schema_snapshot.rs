@@ -0,0 +1,113 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use async_std::prelude::StreamExt;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+
+use edgedb_client::query_builder::{with, select, name, field, raw, not};
+use edgedb_client::query_builder::{Query, Shape, Order, Select};
+use crate::commands::Options;
+use crate::commands::filter;
+use crate::commands::list_object_types::select_object_types;
+use crate::client::Client;
+
+
+/// User-defined part of the schema
+///
+/// This is the format of `edgedb-schema.json` that `edgedb-derive` reads.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Snapshot {
+    pub object_types: Vec<ObjectType>,
+    pub scalar_types: Vec<ScalarType>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ObjectType {
+    pub name: String,
+    pub properties: Vec<Pointer>,
+    pub links: Vec<Pointer>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Pointer {
+    pub name: String,
+    pub target: String,
+    pub cardinality: String,
+    pub required: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ScalarType {
+    pub name: String,
+    pub ancestors: Vec<String>,
+    pub enum_values: Option<Vec<String>>,
+}
+
+async fn query_all<T>(cli: &mut Client<'_>, query: &Query)
+    -> Result<Vec<T>, anyhow::Error>
+    where T: DeserializeOwned,
+{
+    let mut items = cli.query_json(query.text(), query.arguments()).await?;
+    let mut result = Vec::new();
+    while let Some(item) = items.next().await.transpose()? {
+        result.push(serde_json::from_str(&item)?);
+    }
+    Ok(result)
+}
+
+/// Properties or links of an object type, as a subquery
+fn pointers(link: &str) -> Select {
+    let mut shape = Shape::new();
+    shape.field("name")
+        .computed("target", raw(".target.name"))
+        .computed("cardinality", raw("<str>.cardinality"))
+        .field("required");
+    let mut pointers = select(field(link));
+    pointers.shape(&shape);
+    if link == "links" {
+        pointers.filter(field("name").ne("__type__"));
+    }
+    pointers.order_by(field("name"), Order::Asc);
+    pointers
+}
+
+pub async fn fetch(cli: &mut Client<'_>) -> Result<Snapshot, anyhow::Error> {
+    let mut object_types = select_object_types(&None, false, true);
+    object_types.filter(not(field("is_from_alias")));
+    object_types.shape(Shape::new()
+        .field("name")
+        .computed("properties", pointers("properties"))
+        .computed("links", pointers("links")));
+    let object_types = query_all(cli,
+        &with().module("schema").body(&object_types).build()).await?;
+
+    let mut scalar_types = select(name("ScalarType"));
+    scalar_types.shape(Shape::new()
+        .field("name")
+        .computed("ancestors", raw(
+            "array_agg((SELECT .ancestors FILTER NOT .is_abstract).name)"))
+        .field("enum_values"));
+    scalar_types.filter(filter::not_system());
+    scalar_types.order_by(field("name"), Order::Asc);
+    let scalar_types = query_all(cli,
+        &with().module("schema").body(&scalar_types).build()).await?;
+    Ok(Snapshot { object_types, scalar_types })
+}
+
+pub fn load(path: &Path) -> Result<Snapshot, anyhow::Error> {
+    Ok(serde_json::from_slice(&fs::read(path)?)?)
+}
+
+pub async fn schema_snapshot(cli: &mut Client<'_>, _options: &Options,
+    output: &Option<PathBuf>)
+    -> Result<(), anyhow::Error>
+{
+    let snapshot = fetch(cli).await?;
+    let data = serde_json::to_string_pretty(&snapshot)?;
+    match output {
+        Some(path) => fs::write(path, data + "\n")?,
+        None => println!("{}", data),
+    }
+    Ok(())
+}
@@ -18,7 +18,7 @@ struct Row {
 pub async fn get_type_names<'x>(cli: &mut Client<'x>)
     -> Result<HashMap<Uuid, String>, anyhow::Error>
 {
-    let mut items = cli.query::<Row>(
+    let mut items = cli.query::<Row, _>(
         r###"
             WITH MODULE schema
             SELECT Type { id, name }
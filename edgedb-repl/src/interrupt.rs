@@ -0,0 +1,150 @@
+//! Ctrl+C handling while a query is running
+//!
+//! When the prompt is active the terminal is in raw mode, so Ctrl+C is
+//! handled by the line editor and no signal is sent. When neither a query
+//! nor a reconnect is in progress (e.g. on connect), Ctrl+C terminates the
+//! process as usual.
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use async_std::future::pending;
+use async_std::prelude::FutureExt;
+use async_std::task;
+
+use edgedb_client::cancel::CancelHandle;
+
+
+/// Ctrl+C is handled by the `Interrupts` rather than by the default action
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+lazy_static::lazy_static! {
+    /// Self-pipe written by the signal handler: `(read, write)`
+    static ref PIPE: std::io::Result<(
+        std::os::unix::net::UnixStream,
+        std::os::unix::net::UnixStream,
+    )> = std::os::unix::net::UnixStream::pair();
+}
+
+/// Receives Ctrl+C presses while alive
+struct Interrupts {
+    #[cfg(unix)]
+    pipe: Option<async_std::os::unix::net::UnixStream>,
+}
+
+
+/// Installs SIGINT handler, that falls back to the default action when
+/// Ctrl+C is not expected
+#[cfg(unix)]
+pub fn init() -> Result<(), anyhow::Error> {
+    let (_, write) = PIPE.as_ref()
+        .map_err(|e| anyhow::anyhow!("cannot create signal pipe: {}", e))?;
+    signal_hook::pipe::register(libc::SIGINT, write.try_clone()?)?;
+    unsafe {
+        // both `signal` and `raise` are async-signal-safe
+        signal_hook_registry::register(libc::SIGINT, || {
+            if !ACTIVE.load(Ordering::SeqCst) {
+                // signal is blocked in its handler, so the process is
+                // terminated right after the handler returns
+                libc::signal(libc::SIGINT, libc::SIG_DFL);
+                libc::raise(libc::SIGINT);
+            }
+        })?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn init() -> Result<(), anyhow::Error> {
+    Ok(())
+}
+
+impl Interrupts {
+    #[cfg(unix)]
+    fn new() -> Interrupts {
+        use std::io::Read;
+
+        let pipe = PIPE.as_ref().ok()
+            .and_then(|(read, _)| read.try_clone().ok())
+            .and_then(|mut read| {
+                // discard presses left from the previous query
+                read.set_nonblocking(true).ok()?;
+                let mut buf = [0u8; 64];
+                while let Ok(n) = read.read(&mut buf) {
+                    if n == 0 {
+                        return None;
+                    }
+                }
+                Some(read.into())
+            });
+        ACTIVE.store(true, Ordering::SeqCst);
+        Interrupts { pipe }
+    }
+    #[cfg(not(unix))]
+    fn new() -> Interrupts {
+        ACTIVE.store(true, Ordering::SeqCst);
+        Interrupts {}
+    }
+    /// Waits for Ctrl+C, never returns if signals can't be handled
+    async fn wait(&mut self) {
+        #[cfg(unix)] {
+            use async_std::io::ReadExt;
+
+            if let Some(ref pipe) = self.pipe {
+                let mut buf = [0u8; 64];
+                let mut pipe = pipe;
+                match pipe.read(&mut buf).await {
+                    Ok(n) if n > 0 => return,
+                    _ => {}
+                }
+            }
+            self.pipe = None;
+        }
+        pending().await
+    }
+}
+
+impl Drop for Interrupts {
+    fn drop(&mut self) {
+        ACTIVE.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Sleeps for the specified time, returns false if interrupted by Ctrl+C
+pub async fn sleep(duration: Duration) -> bool {
+    let mut interrupts = Interrupts::new();
+    let sleep = async {
+        task::sleep(duration).await;
+        true
+    };
+    let interrupt = async {
+        interrupts.wait().await;
+        false
+    };
+    sleep.race(interrupt).await
+}
+
+/// Runs the future cancelling the query on the server on Ctrl+C
+///
+/// The future is expected to read the query response, including the error
+/// caused by the cancellation.
+pub async fn cancellable<F, T>(handle: Option<CancelHandle>, f: F) -> T
+    where F: Future<Output=T>,
+{
+    let handle = match handle {
+        Some(handle) => handle,
+        None => return f.await,
+    };
+    let mut interrupts = Interrupts::new();
+    let watcher = async {
+        loop {
+            interrupts.wait().await;
+            eprintln!("Cancelling the query...");
+            if let Err(e) = handle.cancel().await {
+                eprintln!("Error cancelling the query: {}", e);
+            }
+        }
+    };
+    f.race(watcher).await
+}
@@ -7,10 +7,10 @@ use crate::options::Options;
 
 mod client;
 mod commands;
+mod interrupt;
 mod options;
 mod print;
 mod prompt;
-mod reader;
 mod repl;
 mod server_params;
 mod statement;
@@ -18,7 +18,7 @@ mod variables;
 
 
 fn main() -> Result<(), anyhow::Error> {
-    let opt = Options::from_args_and_env();
+    let opt = Options::from_args_and_env()?;
     if opt.subcommand.is_some() {
         commands::cli::main(opt)
     } else {
@@ -31,6 +31,7 @@ fn main() -> Result<(), anyhow::Error> {
 }
 
 fn interactive_main(options: Options) -> Result<(), anyhow::Error> {
+    interrupt::init()?;
     let (control_wr, control_rd) = channel(1);
     let (repl_wr, repl_rd) = channel(1);
     let state = repl::State {
@@ -6,10 +6,13 @@ use std::io;
 
 use async_std::stream::{Stream, StreamExt};
 use atty;
+use colorful::Colorful;
 use snafu::{Snafu, ResultExt, AsErrorSource};
 use term_size;
 use uuid::Uuid;
 
+use edgedb_protocol::server_message::{LogMessage, MessageSeverity};
+
 mod format;
 mod buffer;
 mod stream;
@@ -148,6 +151,28 @@ pub async fn print_to_stdout<S, I, E>(mut rows: S, config: &Config)
     Ok(())
 }
 
+/// Prints a warning or a notice sent by the server to stderr
+pub fn log_message(msg: &LogMessage) {
+    use MessageSeverity::*;
+
+    let line = match msg.severity {
+        Debug => format!("DEBUG: {}", msg.text),
+        Info => format!("INFO: {}", msg.text),
+        Notice => format!("NOTICE: {}", msg.text),
+        Warning => format!("WARNING: {}", msg.text),
+        Unknown(code) => format!("LOG({}): {}", code, msg.text),
+    };
+    if !atty::is(atty::Stream::Stderr) {
+        eprintln!("{}", line);
+        return;
+    }
+    match msg.severity {
+        Warning => eprintln!("{}", line.yellow().bold()),
+        Notice => eprintln!("{}", line.light_blue()),
+        _ => eprintln!("{}", line.light_gray()),
+    }
+}
+
 #[cfg(test)]
 fn test_format_rows<I: FormatExt>(prn: &mut Printer<&mut String>, items: &[I],
     reopen: bool)
@@ -2,7 +2,8 @@ use std::collections::HashMap;
 
 use serde::de::{self, Deserializer};
 use serde::{Deserialize, Serialize};
-use typemap::Key;
+
+use crate::client::Client;
 
 
 #[derive(Deserialize, Debug, Serialize)]
@@ -17,8 +18,18 @@ pub struct PostgresAddress {
 }
 
 
-impl Key for PostgresAddress {
-    type Value = PostgresAddress;
+impl PostgresAddress {
+    /// Address of the underlying postgres, only reported in DEV mode
+    pub fn from_client(cli: &Client) -> Option<PostgresAddress> {
+        let value = cli.server_param("pgaddr")?;
+        match serde_json::from_slice(&value[..]) {
+            Ok(addr) => Some(addr),
+            Err(e) => {
+                eprintln!("Can't decode param \"pgaddr\": {}", e);
+                None
+            }
+        }
+    }
 }
 
 fn str_to_u16<'de, D>(d: D) -> Result<u16, D::Error>
@@ -1,7 +1,8 @@
 use std::io;
 use std::collections::HashMap;
+use std::sync::Arc;
 
-use async_listen::{ByteStream};
+use async_listen::{ByteStream, PeerAddr};
 
 use edgedb_protocol::client_message::{ClientMessage, ClientHandshake};
 use edgedb_protocol::server_message::{ServerMessage, ServerHandshake};
@@ -9,6 +10,7 @@ use edgedb_protocol::server_message::{Authentication};
 use edgedb_protocol::server_message::{ReadyForCommand, TransactionState};
 use crate::reader::Reader;
 use crate::writer::Writer;
+use crate::tls;
 
 pub trait ConnectionErr {
     type Output;
@@ -38,13 +40,25 @@ fn is_important_error(e: &io::Error) -> bool {
     }
 }
 
-pub async fn connection_loop(stream: ByteStream) -> Result<(), ()> {
-    let log_name = stream.peer_addr()
+pub async fn connection_loop(stream: ByteStream,
+    tls: Option<Arc<tls::Settings>>)
+    -> Result<(), ()>
+{
+    let peer_addr = stream.peer_addr();
+    let log_name = peer_addr.as_ref()
         .map(|a| format!("<peer: {}>", a))
         .unwrap_or_else(|e| {
             log::debug!("Can't get peer name: {}", e);
             String::from("<unidentified-peer>")
         });
+    // Unix sockets are protected by file permissions, so are never encrypted
+    let (tls, is_loopback) = match peer_addr {
+        Ok(PeerAddr::Tcp(addr)) => (tls.as_deref(), addr.ip().is_loopback()),
+        Ok(PeerAddr::Unix(_)) => (None, true),
+        Err(_) => (tls.as_deref(), false),
+    };
+    let stream = tls::negotiate(stream, tls, is_loopback, &log_name).await?;
+    log::debug!("{}: connected, tls: {}", log_name, stream.is_tls());
     let (tx, rx) = (&stream, &stream);
     let mut rx = Reader::new(rx, &log_name);
     let mut tx = Writer::new(tx, &log_name);
@@ -11,6 +11,7 @@ use async_listen::{ListenExt, ByteStream, backpressure, error_hint};
 
 use crate::options::Options;
 use crate::connection::connection_loop;
+use crate::tls;
 
 fn spawn<F>(f: F)
     where F: Future<Output=Result<(), ()>> + Send + 'static,
@@ -25,6 +26,7 @@ pub async fn accept_loop(options: Options) -> Result<(), anyhow::Error> {
 
 #[allow(dead_code)]  // only for windows, but always compile to check validity
 pub async fn _accept_loop(options: Options) -> Result<(), anyhow::Error> {
+    let tls = tls::Settings::from_options(&options)?;
     let addr = (&options.bind_address[..], options.port);
     let (_, bp) = backpressure::new(options.max_backend_connections);
     let tcp_listener = TcpListener::bind(addr).await?;
@@ -34,7 +36,7 @@ pub async fn _accept_loop(options: Options) -> Result<(), anyhow::Error> {
         .handle_errors(Duration::from_millis(500))
         .backpressure_wrapper(bp);
     while let Some(stream) = incoming.next().await {
-        spawn(connection_loop(stream));
+        spawn(connection_loop(stream, tls.clone()));
     }
     Ok(())
 }
@@ -48,6 +50,7 @@ pub async fn accept_loop(options: Options) -> Result<(), anyhow::Error> {
     let unix_path_admin = options.runstate_dir
         .join(format!(".s.EDGEDB.admin.{}", options.port));
 
+    let tls = tls::Settings::from_options(&options)?;
     let addr = (&options.bind_address[..], options.port);
     let (ctl, throttle) = backpressure::new(options.max_backend_connections);
 
@@ -71,7 +74,7 @@ pub async fn accept_loop(options: Options) -> Result<(), anyhow::Error> {
             // admin socket. So we can connect in case in emergency
             .map(|conn| ByteStream::new_unix(ctl1.token(), conn));
         while let Some(stream) = incoming.next().await {
-            spawn(connection_loop(stream));
+            spawn(connection_loop(stream, None));
         }
     });
 
@@ -90,7 +93,7 @@ pub async fn accept_loop(options: Options) -> Result<(), anyhow::Error> {
         .apply_backpressure(throttle);
 
     while let Some(stream) = incoming.next().await {
-        spawn(connection_loop(stream));
+        spawn(connection_loop(stream, tls.clone()));
     }
     Ok(())
 }
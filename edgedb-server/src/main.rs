@@ -7,6 +7,7 @@ mod listen;
 mod options;
 mod postgres;
 mod reader;
+mod tls;
 mod writer;
 
 use options::Options;
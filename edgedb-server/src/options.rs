@@ -83,6 +83,19 @@ pub struct TmpOptions {
 
     #[structopt(long, value_name="int", default_value="100")]
     max_backend_connections: usize,
+
+    /// Certificate chain in PEM format, enables TLS for TCP connections
+    #[structopt(long, value_name="path", requires="tls-key-file")]
+    tls_cert_file: Option<PathBuf>,
+
+    /// Private key (PKCS#8 or RSA) in PEM format
+    #[structopt(long, value_name="path", requires="tls-cert-file")]
+    tls_key_file: Option<PathBuf>,
+
+    /// Accept plaintext connections from the loopback addresses when TLS
+    /// is enabled
+    #[structopt(long)]
+    allow_plaintext_on_loopback: bool,
 }
 
 #[derive(Debug)]
@@ -109,6 +122,9 @@ pub struct Options {
     pub daemon_group: Option<u32>,
     pub runstate_dir: PathBuf,
     pub max_backend_connections: usize,
+    pub tls_cert_file: Option<PathBuf>,
+    pub tls_key_file: Option<PathBuf>,
+    pub allow_plaintext_on_loopback: bool,
 }
 
 impl Options {
@@ -142,6 +158,9 @@ impl Options {
                 data_dir.unwrap_or("/run/edgedb".into())
             }),
             max_backend_connections: t.max_backend_connections,
+            tls_cert_file: t.tls_cert_file,
+            tls_key_file: t.tls_key_file,
+            allow_plaintext_on_loopback: t.allow_plaintext_on_loopback,
             mode: if let Some(dsn) = t.postgres_dsn {
                 Mode::External(dsn)
             } else {
@@ -12,6 +12,7 @@ use async_listen::ByteStream;
 use edgedb_protocol::client_message::{ClientMessage};
 
 use crate::connection::ConnectionErr;
+use crate::tls::Stream;
 
 
 const BUFFER_SIZE: usize = 8192;
@@ -20,7 +21,7 @@ const MAX_BUFFER: usize = 1_048_576;
 
 pub struct Reader<'a> {
     name: &'a str,
-    stream: &'a ByteStream,
+    stream: &'a Stream<ByteStream>,
     buf: BytesMut,
 }
 
@@ -29,7 +30,9 @@ pub struct MessageFuture<'a, 'b: 'a> {
 }
 
 impl<'a> Reader<'a> {
-    pub fn new(stream: &'a ByteStream, log_name: &'a str) -> Reader<'a> {
+    pub fn new(stream: &'a Stream<ByteStream>, log_name: &'a str)
+        -> Reader<'a>
+    {
         return Reader {
             name: log_name,
             stream,
@@ -64,8 +67,8 @@ impl<'a, 'b> Future for MessageFuture<'a, 'b> {
 
             buf.reserve(next_read);
             unsafe {
-                // this is save because the underlying ByteStream always
-                // initializes read bytes
+                // this is save because neither the ByteStream nor the TLS
+                // session read from the buffer, they only initialize bytes
                 let dest: &mut [u8] = transmute(buf.bytes_mut());
                 match Pin::new(&mut &**stream).poll_read(cx, dest) {
                     Poll::Ready(Ok(0)) => {
@@ -0,0 +1,345 @@
+//! TLS for the client connections
+//!
+//! The first byte of the connection tells whether the client starts a TLS
+//! handshake or sends a plaintext protocol message. When TLS is configured
+//! plaintext is accepted only from the loopback addresses and only if it's
+//! allowed in the options. Unix sockets are always plaintext.
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Poll, Context};
+use std::time::Duration;
+
+use anyhow::{self, Context as _};
+use async_std::io::{Read, Write};
+use async_std::io::prelude::ReadExt;
+use async_tls::TlsAcceptor;
+use async_tls::server::TlsStream;
+use rustls::{ServerConfig, NoClientAuth, PrivateKey};
+use rustls::internal::pemfile;
+
+use crate::connection::ConnectionErr;
+use crate::options::Options;
+
+
+/// Protocol name negotiated using ALPN
+pub const ALPN_PROTOCOL: &[u8] = b"edgedb-binary";
+/// Content type of the TLS handshake record
+const TLS_HANDSHAKE: u8 = 0x16;
+/// Time for the client to send the first byte and finish TLS handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct Settings {
+    acceptor: TlsAcceptor,
+    allow_plaintext_on_loopback: bool,
+    handshake_timeout: Duration,
+}
+
+/// Client connection, either plaintext or encrypted
+///
+/// Reader and writer share the stream, so it's locked for a single poll.
+pub struct Stream<S> {
+    inner: Mutex<Inner<S>>,
+}
+
+enum Inner<S> {
+    Plain(Prefixed<S>),
+    Tls(TlsStream<Prefixed<S>>),
+}
+
+/// Stream with the first byte, which is already read, put back
+struct Prefixed<S> {
+    prefix: Option<u8>,
+    stream: S,
+}
+
+
+impl Settings {
+    /// Loads certificate and key if TLS is enabled in the options
+    pub fn from_options(options: &Options)
+        -> Result<Option<Arc<Settings>>, anyhow::Error>
+    {
+        let (cert_file, key_file) = match (&options.tls_cert_file,
+                                           &options.tls_key_file)
+        {
+            (Some(cert_file), Some(key_file)) => (cert_file, key_file),
+            (None, None) => return Ok(None),
+            _ => anyhow::bail!(
+                "both --tls-cert-file and --tls-key-file are required"),
+        };
+        let config = server_config(cert_file, key_file)?;
+        Ok(Some(Arc::new(Settings {
+            acceptor: Arc::new(config).into(),
+            allow_plaintext_on_loopback: options.allow_plaintext_on_loopback,
+            handshake_timeout: HANDSHAKE_TIMEOUT,
+        })))
+    }
+}
+
+pub fn server_config(cert_file: &Path, key_file: &Path)
+    -> Result<ServerConfig, anyhow::Error>
+{
+    let data = fs::read(cert_file)
+        .with_context(|| format!("error reading {}", cert_file.display()))?;
+    let certs = pemfile::certs(&mut &data[..]).unwrap_or_default();
+    if certs.is_empty() {
+        anyhow::bail!("no certificates found in {}", cert_file.display());
+    }
+    let key = read_key(key_file)?;
+    let mut config = ServerConfig::new(NoClientAuth::new());
+    config.set_single_cert(certs, key)
+        .with_context(|| format!("invalid key {}", key_file.display()))?;
+    config.set_protocols(&[ALPN_PROTOCOL.to_vec()]);
+    Ok(config)
+}
+
+fn read_key(path: &Path) -> Result<PrivateKey, anyhow::Error> {
+    let data = fs::read(path)
+        .with_context(|| format!("error reading {}", path.display()))?;
+    let pkcs8 = pemfile::pkcs8_private_keys(&mut &data[..])
+        .unwrap_or_default();
+    let rsa = pemfile::rsa_private_keys(&mut &data[..])
+        .unwrap_or_default();
+    pkcs8.into_iter().chain(rsa).next()
+        .ok_or_else(|| anyhow::anyhow!("no private key found in {}",
+                                       path.display()))
+}
+
+/// Starts TLS or checks that plaintext connection is allowed
+pub async fn negotiate<S>(mut stream: S, settings: Option<&Settings>,
+    is_loopback: bool, log_name: &str)
+    -> Result<Stream<S>, ()>
+    where S: Read + Write + Unpin,
+{
+    let settings = match settings {
+        Some(settings) => settings,
+        None => return Ok(Stream::new(Inner::Plain(Prefixed {
+            prefix: None,
+            stream,
+        }))),
+    };
+    let handshake = async move {
+        let mut first = [0u8];
+        stream.read_exact(&mut first).await?;
+        let stream = Prefixed { prefix: Some(first[0]), stream };
+        if first[0] == TLS_HANDSHAKE {
+            Ok(Inner::Tls(settings.acceptor.accept(stream).await?))
+        } else {
+            Ok(Inner::Plain(stream))
+        }
+    };
+    let inner = async_std::io::timeout(settings.handshake_timeout, handshake)
+        .await.connection_err(log_name)?;
+    let allow_plaintext = settings.allow_plaintext_on_loopback && is_loopback;
+    match inner {
+        Inner::Plain(_) if !allow_plaintext => {
+            log::debug!("{}: plaintext connection rejected", log_name);
+            Err(())
+        }
+        inner => Ok(Stream::new(inner)),
+    }
+}
+
+impl<S> Stream<S> {
+    fn new(inner: Inner<S>) -> Stream<S> {
+        Stream { inner: Mutex::new(inner) }
+    }
+    pub fn is_tls(&self) -> bool {
+        match *self.inner.lock().expect("stream is not poisoned") {
+            Inner::Plain(_) => false,
+            Inner::Tls(_) => true,
+        }
+    }
+}
+
+macro_rules! with_stream {
+    ($stream:expr, |$s:ident| $body:expr) => {
+        match *$stream.inner.lock().expect("stream is not poisoned") {
+            Inner::Plain(ref mut stream) => {
+                let $s = Pin::new(stream);
+                $body
+            }
+            Inner::Tls(ref mut stream) => {
+                let $s = Pin::new(stream);
+                $body
+            }
+        }
+    }
+}
+
+impl<S: Read + Write + Unpin> Read for &Stream<S> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut [u8])
+        -> Poll<io::Result<usize>>
+    {
+        with_stream!(self, |s| s.poll_read(cx, buf))
+    }
+}
+
+impl<S: Read + Write + Unpin> Write for &Stream<S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8])
+        -> Poll<io::Result<usize>>
+    {
+        with_stream!(self, |s| s.poll_write(cx, buf))
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context)
+        -> Poll<io::Result<()>>
+    {
+        with_stream!(self, |s| s.poll_flush(cx))
+    }
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context)
+        -> Poll<io::Result<()>>
+    {
+        with_stream!(self, |s| s.poll_close(cx))
+    }
+}
+
+impl<S: Read + Unpin> Read for Prefixed<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context, buf: &mut [u8])
+        -> Poll<io::Result<usize>>
+    {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        if let Some(byte) = self.prefix.take() {
+            buf[0] = byte;
+            return Poll::Ready(Ok(1));
+        }
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl<S: Write + Unpin> Write for Prefixed<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context, buf: &[u8])
+        -> Poll<io::Result<usize>>
+    {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context)
+        -> Poll<io::Result<()>>
+    {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context)
+        -> Poll<io::Result<()>>
+    {
+        Pin::new(&mut self.stream).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::io::{Read as _, Write as _};
+    use std::net::TcpStream as StdTcpStream;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use async_std::io::prelude::{ReadExt, WriteExt};
+    use async_std::net::TcpListener;
+    use async_std::task;
+    use rustls::{ClientConfig, ClientSession, StreamOwned};
+    use webpki::DNSNameRef;
+
+    use super::{Settings, ALPN_PROTOCOL, server_config, negotiate};
+
+    fn settings(allow_plaintext_on_loopback: bool)
+        -> (Settings, rcgen::Certificate)
+    {
+        let cert = rcgen::generate_simple_self_signed(
+            vec!["localhost".into()]).unwrap();
+        let dir = std::env::temp_dir();
+        let prefix = format!("edgedb-server-{}-{}", std::process::id(),
+                             allow_plaintext_on_loopback);
+        let cert_file = dir.join(format!("{}.crt", prefix));
+        let key_file = dir.join(format!("{}.key", prefix));
+        fs::write(&cert_file, cert.serialize_pem().unwrap()).unwrap();
+        fs::write(&key_file, cert.serialize_private_key_pem()).unwrap();
+        let config = server_config(&cert_file, &key_file).unwrap();
+        fs::remove_file(cert_file).ok();
+        fs::remove_file(key_file).ok();
+        let settings = Settings {
+            acceptor: Arc::new(config).into(),
+            allow_plaintext_on_loopback,
+            handshake_timeout: Duration::from_millis(100),
+        };
+        (settings, cert)
+    }
+
+    /// Accepts a single connection and echoes first 5 bytes back
+    async fn echo_server(listener: TcpListener, settings: Settings)
+        -> Result<bool, ()>
+    {
+        let (sock, _) = listener.accept().await.unwrap();
+        let stream = negotiate(sock, Some(&settings), true, "test").await?;
+        let mut buf = [0u8; 5];
+        (&stream).read_exact(&mut buf).await.map_err(|_| ())?;
+        (&stream).write_all(&buf).await.map_err(|_| ())?;
+        Ok(stream.is_tls())
+    }
+
+    #[test]
+    fn tls() {
+        let (settings, cert) = settings(false);
+        let listener = task::block_on(TcpListener::bind("127.0.0.1:0"))
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = task::spawn(echo_server(listener, settings));
+
+        let client = thread::spawn(move || {
+            let mut config = ClientConfig::new();
+            config.root_store.add(&rustls::Certificate(
+                cert.serialize_der().unwrap())).unwrap();
+            config.set_protocols(&[ALPN_PROTOCOL.to_vec()]);
+            let name = DNSNameRef::try_from_ascii_str("localhost").unwrap();
+            let session = ClientSession::new(&Arc::new(config), name);
+            let sock = StdTcpStream::connect(addr).unwrap();
+            let mut stream = StreamOwned::new(session, sock);
+            stream.write_all(b"hello").unwrap();
+            let mut buf = [0u8; 5];
+            stream.read_exact(&mut buf).unwrap();
+            assert_eq!(&buf, b"hello");
+            assert_eq!(rustls::Session::get_alpn_protocol(&stream.sess),
+                       Some(ALPN_PROTOCOL));
+        });
+        client.join().unwrap();
+        assert_eq!(task::block_on(server), Ok(true));
+    }
+
+    #[test]
+    fn plaintext() {
+        for &allow in &[true, false] {
+            let (settings, _) = settings(allow);
+            let listener = task::block_on(TcpListener::bind("127.0.0.1:0"))
+                .unwrap();
+            let addr = listener.local_addr().unwrap();
+            let server = task::spawn(echo_server(listener, settings));
+            let mut sock = StdTcpStream::connect(addr).unwrap();
+            sock.write_all(b"hello").unwrap();
+            let mut buf = Vec::new();
+            sock.read_to_end(&mut buf).ok();
+            if allow {
+                assert_eq!(buf, b"hello");
+                assert_eq!(task::block_on(server), Ok(false));
+            } else {
+                assert_eq!(buf, b"");
+                assert_eq!(task::block_on(server), Err(()));
+            }
+        }
+    }
+
+    #[test]
+    fn handshake_timeout() {
+        let (settings, _) = settings(true);
+        let listener = task::block_on(TcpListener::bind("127.0.0.1:0"))
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+        let started = Instant::now();
+        let server = task::spawn(echo_server(listener, settings));
+        let _sock = StdTcpStream::connect(addr).unwrap();
+        assert_eq!(task::block_on(server), Err(()));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -5,17 +5,20 @@ use async_listen::ByteStream;
 
 use edgedb_protocol::server_message::{ServerMessage};
 use crate::connection::ConnectionErr;
+use crate::tls::Stream;
 
 const BUFFER_SIZE: usize = 8192;
 
 pub struct Writer<'a> {
     name: &'a str,
-    stream: &'a ByteStream,
+    stream: &'a Stream<ByteStream>,
     buf: BytesMut,
 }
 
 impl<'a> Writer<'a> {
-    pub fn new(stream: &'a ByteStream, log_name: &'a str) -> Writer<'a> {
+    pub fn new(stream: &'a Stream<ByteStream>, log_name: &'a str)
+        -> Writer<'a>
+    {
         return Writer {
             name: log_name,
             stream,